@@ -5,16 +5,318 @@
 #include <cassert>
 
 
+/// Most pages `list_shops` walks, in case the server keeps answering with full pages.
+static const uint32_t MAX_SHOPS_PAGES = 1024;
+
+/// Number of shops requested per page when `list_shops` walks every page.
+static const int32_t SHOPS_PAGE_SIZE = 128;
+
+/// Stable numeric code for each kind of `FFIError`, for logging and for plugins that only need to
+/// branch on the kind. Codes are never reused or renumbered.
+enum class FFIErrorCode : uint32_t {
+  Network = 1,
+  Server = 2,
+  CacheCorrupt = 3,
+  Panic = 4,
+  InvalidArgument = 5,
+  Timeout = 6,
+  ConnectionFailed = 7,
+  Unauthorized = 8,
+  NotFound = 9,
+  Conflict = 10,
+  Deserialize = 11,
+  CacheMiss = 12,
+  InvalidUrl = 13,
+  Io = 14,
+  Validation = 15,
+};
+
+/// Where a value returned by a `*_with_freshness` function came from. Ordered from most to least
+/// fresh.
+enum class FreshnessSource : uint32_t {
+  /// The server sent the value in response to this request.
+  Network = 1,
+  /// The server confirmed with a 304 that the cached value is still current.
+  NotModified = 2,
+  /// The server could not be reached or returned an error, so the cached value was returned
+  /// as is. It may be out of date.
+  OfflineFallback = 3,
+};
+
+/// Log verbosity as passed in by the plugin. Each level includes the ones before it.
+enum class LogLevel : uint32_t {
+  Off = 0,
+  Error = 1,
+  Warn = 2,
+  Info = 3,
+  Debug = 4,
+  Trace = 5,
+};
+
+enum class RawMutationKind {
+  CreateTransaction,
+  UpdateMerchandiseList,
+  UpdateInteriorRefList,
+  UpdateShop,
+};
+
+/// Long-lived handle to the API that is created once by the plugin with `client_new` and passed to
+/// every `client_*` endpoint function. It owns a pooled HTTP client so connections are reused
+/// between requests.
+///
+/// Cloning is cheap and shares the transport and request queue, which is how requests are handed
+/// off to worker threads.
+struct BazaarClient;
+
+template<typename T = void>
+struct Option;
+
 struct FFIServerError {
   uint16_t status;
   const char *title;
   const char *detail;
 };
 
+struct RawOwner {
+  int32_t id;
+  const char *name;
+  int32_t mod_version;
+};
+
+struct RawShop {
+  int32_t id;
+  const char *name;
+  const char *description;
+  int32_t gold;
+  const char *shop_type;
+  const char **vendor_keywords;
+  uintptr_t vendor_keywords_len;
+  bool vendor_keywords_exclude;
+};
+
+struct RawMerchandise {
+  const char *mod_name;
+  uint32_t local_form_id;
+  const char *name;
+  uint32_t quantity;
+  uint32_t form_type;
+  bool is_food;
+  uint32_t price;
+  const char **keywords;
+  uintptr_t keywords_len;
+};
+
+struct RawMerchandiseVec {
+  RawMerchandise *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct RawInteriorRef {
+  const char *base_mod_name;
+  uint32_t base_local_form_id;
+  const char *ref_mod_name;
+  uint32_t ref_local_form_id;
+  float position_x;
+  float position_y;
+  float position_z;
+  float angle_x;
+  float angle_y;
+  float angle_z;
+  uint16_t scale;
+};
+
+struct RawInteriorRefVec {
+  RawInteriorRef *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct RawShelf {
+  uint32_t shelf_type;
+  float position_x;
+  float position_y;
+  float position_z;
+  float angle_x;
+  float angle_y;
+  float angle_z;
+  uint16_t scale;
+  uint32_t page;
+  uint32_t filter_form_type;
+  bool filter_is_food;
+  const char *search;
+  const char *sort_on;
+  bool sort_asc;
+};
+
+struct RawShelfVec {
+  RawShelf *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct RawInteriorRefData {
+  RawInteriorRefVec interior_ref_vec;
+  RawShelfVec shelf_vec;
+};
+
+/// The server's current copy of what an update conflicted with, so the plugin can merge its change
+/// into it.
+struct RawServerCopy {
+  enum class Tag : uint8_t {
+    /// The conflict was not with an update, or the server's copy could not be fetched.
+    None,
+    Owner,
+    Shop,
+    MerchandiseList,
+    InteriorRefList,
+  };
+
+  struct Owner_Body {
+    RawOwner _0;
+  };
+
+  struct Shop_Body {
+    RawShop _0;
+  };
+
+  struct MerchandiseList_Body {
+    RawMerchandiseVec _0;
+  };
+
+  struct InteriorRefList_Body {
+    RawInteriorRefData _0;
+  };
+
+  Tag tag;
+  union {
+    Owner_Body owner;
+    Shop_Body shop;
+    MerchandiseList_Body merchandise_list;
+    InteriorRefList_Body interior_ref_list;
+  };
+
+  static RawServerCopy None() {
+    RawServerCopy result;
+    result.tag = Tag::None;
+    return result;
+  }
+
+  bool IsNone() const {
+    return tag == Tag::None;
+  }
+
+  static RawServerCopy Owner(const RawOwner &_0) {
+    RawServerCopy result;
+    ::new (&result.owner._0) (RawOwner)(_0);
+    result.tag = Tag::Owner;
+    return result;
+  }
+
+  bool IsOwner() const {
+    return tag == Tag::Owner;
+  }
+
+  const RawOwner& AsOwner() const {
+    assert(IsOwner());
+    return owner._0;
+  }
+
+  static RawServerCopy Shop(const RawShop &_0) {
+    RawServerCopy result;
+    ::new (&result.shop._0) (RawShop)(_0);
+    result.tag = Tag::Shop;
+    return result;
+  }
+
+  bool IsShop() const {
+    return tag == Tag::Shop;
+  }
+
+  const RawShop& AsShop() const {
+    assert(IsShop());
+    return shop._0;
+  }
+
+  static RawServerCopy MerchandiseList(const RawMerchandiseVec &_0) {
+    RawServerCopy result;
+    ::new (&result.merchandise_list._0) (RawMerchandiseVec)(_0);
+    result.tag = Tag::MerchandiseList;
+    return result;
+  }
+
+  bool IsMerchandiseList() const {
+    return tag == Tag::MerchandiseList;
+  }
+
+  const RawMerchandiseVec& AsMerchandiseList() const {
+    assert(IsMerchandiseList());
+    return merchandise_list._0;
+  }
+
+  static RawServerCopy InteriorRefList(const RawInteriorRefData &_0) {
+    RawServerCopy result;
+    ::new (&result.interior_ref_list._0) (RawInteriorRefData)(_0);
+    result.tag = Tag::InteriorRefList;
+    return result;
+  }
+
+  bool IsInteriorRefList() const {
+    return tag == Tag::InteriorRefList;
+  }
+
+  const RawInteriorRefData& AsInteriorRefList() const {
+    assert(IsInteriorRefList());
+    return interior_ref_list._0;
+  }
+};
+
+/// A 409 Conflict, or a 412 Precondition Failed for an update based on an out of date copy.
+struct FFIConflictError {
+  FFIServerError server_error;
+  RawServerCopy current;
+};
+
+struct RawFieldError {
+  const char *field;
+  const char *reason;
+};
+
+/// Every field of a value that failed validation.
+struct FFIValidationError {
+  RawFieldError *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+/// New variants are only ever added at the end so that the tag of each existing variant stays the
+/// same for plugins built against an older header.
 struct FFIError {
   enum class Tag : uint8_t {
+    /// The server responded with an error status that has no more specific variant.
     Server,
+    /// Any other error. Holds the error message.
     Network,
+    CacheCorrupt,
+    /// The client panicked. Holds the panic message.
+    Panic,
+    /// An argument passed to the client was null or otherwise unreadable.
+    InvalidArgument,
+    Timeout,
+    /// The server could not be reached, or the connection dropped before it responded.
+    ConnectionFailed,
+    /// The server rejected the API key (401 or 403).
+    Unauthorized,
+    NotFound,
+    Conflict,
+    /// A response or cache entry could not be decoded.
+    Deserialize,
+    /// The request failed and there was no cached copy to fall back to.
+    CacheMiss,
+    InvalidUrl,
+    Io,
+    /// An argument failed validation before anything was sent to the server.
+    Validation,
   };
 
   struct Server_Body {
@@ -25,10 +327,76 @@ struct FFIError {
     const char *_0;
   };
 
+  struct CacheCorrupt_Body {
+    const char *_0;
+  };
+
+  struct Panic_Body {
+    const char *_0;
+  };
+
+  struct InvalidArgument_Body {
+    const char *arg_name;
+    const char *reason;
+  };
+
+  struct Timeout_Body {
+    const char *_0;
+  };
+
+  struct ConnectionFailed_Body {
+    const char *_0;
+  };
+
+  struct Unauthorized_Body {
+    FFIServerError _0;
+  };
+
+  struct NotFound_Body {
+    FFIServerError _0;
+  };
+
+  struct Conflict_Body {
+    FFIConflictError _0;
+  };
+
+  struct Deserialize_Body {
+    const char *_0;
+  };
+
+  struct CacheMiss_Body {
+    const char *_0;
+  };
+
+  struct InvalidUrl_Body {
+    const char *_0;
+  };
+
+  struct Io_Body {
+    const char *_0;
+  };
+
+  struct Validation_Body {
+    FFIValidationError _0;
+  };
+
   Tag tag;
   union {
     Server_Body server;
     Network_Body network;
+    CacheCorrupt_Body cache_corrupt;
+    Panic_Body panic;
+    InvalidArgument_Body invalid_argument;
+    Timeout_Body timeout;
+    ConnectionFailed_Body connection_failed;
+    Unauthorized_Body unauthorized;
+    NotFound_Body not_found;
+    Conflict_Body conflict;
+    Deserialize_Body deserialize;
+    CacheMiss_Body cache_miss;
+    InvalidUrl_Body invalid_url;
+    Io_Body io;
+    Validation_Body validation;
   };
 
   static FFIError Server(const FFIServerError &_0) {
@@ -54,193 +422,1027 @@ struct FFIError {
     return result;
   }
 
-  bool IsNetwork() const {
-    return tag == Tag::Network;
+  bool IsNetwork() const {
+    return tag == Tag::Network;
+  }
+
+  const char*const & AsNetwork() const {
+    assert(IsNetwork());
+    return network._0;
+  }
+
+  static FFIError CacheCorrupt(const char *const &_0) {
+    FFIError result;
+    ::new (&result.cache_corrupt._0) (const char*)(_0);
+    result.tag = Tag::CacheCorrupt;
+    return result;
+  }
+
+  bool IsCacheCorrupt() const {
+    return tag == Tag::CacheCorrupt;
+  }
+
+  const char*const & AsCacheCorrupt() const {
+    assert(IsCacheCorrupt());
+    return cache_corrupt._0;
+  }
+
+  static FFIError Panic(const char *const &_0) {
+    FFIError result;
+    ::new (&result.panic._0) (const char*)(_0);
+    result.tag = Tag::Panic;
+    return result;
+  }
+
+  bool IsPanic() const {
+    return tag == Tag::Panic;
+  }
+
+  const char*const & AsPanic() const {
+    assert(IsPanic());
+    return panic._0;
+  }
+
+  static FFIError InvalidArgument(const char *const &arg_name,
+                                  const char *const &reason) {
+    FFIError result;
+    ::new (&result.invalid_argument.arg_name) (const char*)(arg_name);
+    ::new (&result.invalid_argument.reason) (const char*)(reason);
+    result.tag = Tag::InvalidArgument;
+    return result;
+  }
+
+  bool IsInvalidArgument() const {
+    return tag == Tag::InvalidArgument;
+  }
+
+  const InvalidArgument_Body& AsInvalidArgument() const {
+    assert(IsInvalidArgument());
+    return invalid_argument;
+  }
+
+  static FFIError Timeout(const char *const &_0) {
+    FFIError result;
+    ::new (&result.timeout._0) (const char*)(_0);
+    result.tag = Tag::Timeout;
+    return result;
+  }
+
+  bool IsTimeout() const {
+    return tag == Tag::Timeout;
+  }
+
+  const char*const & AsTimeout() const {
+    assert(IsTimeout());
+    return timeout._0;
+  }
+
+  static FFIError ConnectionFailed(const char *const &_0) {
+    FFIError result;
+    ::new (&result.connection_failed._0) (const char*)(_0);
+    result.tag = Tag::ConnectionFailed;
+    return result;
+  }
+
+  bool IsConnectionFailed() const {
+    return tag == Tag::ConnectionFailed;
+  }
+
+  const char*const & AsConnectionFailed() const {
+    assert(IsConnectionFailed());
+    return connection_failed._0;
+  }
+
+  static FFIError Unauthorized(const FFIServerError &_0) {
+    FFIError result;
+    ::new (&result.unauthorized._0) (FFIServerError)(_0);
+    result.tag = Tag::Unauthorized;
+    return result;
+  }
+
+  bool IsUnauthorized() const {
+    return tag == Tag::Unauthorized;
+  }
+
+  const FFIServerError& AsUnauthorized() const {
+    assert(IsUnauthorized());
+    return unauthorized._0;
+  }
+
+  static FFIError NotFound(const FFIServerError &_0) {
+    FFIError result;
+    ::new (&result.not_found._0) (FFIServerError)(_0);
+    result.tag = Tag::NotFound;
+    return result;
+  }
+
+  bool IsNotFound() const {
+    return tag == Tag::NotFound;
+  }
+
+  const FFIServerError& AsNotFound() const {
+    assert(IsNotFound());
+    return not_found._0;
+  }
+
+  static FFIError Conflict(const FFIConflictError &_0) {
+    FFIError result;
+    ::new (&result.conflict._0) (FFIConflictError)(_0);
+    result.tag = Tag::Conflict;
+    return result;
+  }
+
+  bool IsConflict() const {
+    return tag == Tag::Conflict;
+  }
+
+  const FFIConflictError& AsConflict() const {
+    assert(IsConflict());
+    return conflict._0;
+  }
+
+  static FFIError Deserialize(const char *const &_0) {
+    FFIError result;
+    ::new (&result.deserialize._0) (const char*)(_0);
+    result.tag = Tag::Deserialize;
+    return result;
+  }
+
+  bool IsDeserialize() const {
+    return tag == Tag::Deserialize;
+  }
+
+  const char*const & AsDeserialize() const {
+    assert(IsDeserialize());
+    return deserialize._0;
+  }
+
+  static FFIError CacheMiss(const char *const &_0) {
+    FFIError result;
+    ::new (&result.cache_miss._0) (const char*)(_0);
+    result.tag = Tag::CacheMiss;
+    return result;
+  }
+
+  bool IsCacheMiss() const {
+    return tag == Tag::CacheMiss;
+  }
+
+  const char*const & AsCacheMiss() const {
+    assert(IsCacheMiss());
+    return cache_miss._0;
+  }
+
+  static FFIError InvalidUrl(const char *const &_0) {
+    FFIError result;
+    ::new (&result.invalid_url._0) (const char*)(_0);
+    result.tag = Tag::InvalidUrl;
+    return result;
+  }
+
+  bool IsInvalidUrl() const {
+    return tag == Tag::InvalidUrl;
+  }
+
+  const char*const & AsInvalidUrl() const {
+    assert(IsInvalidUrl());
+    return invalid_url._0;
+  }
+
+  static FFIError Io(const char *const &_0) {
+    FFIError result;
+    ::new (&result.io._0) (const char*)(_0);
+    result.tag = Tag::Io;
+    return result;
+  }
+
+  bool IsIo() const {
+    return tag == Tag::Io;
+  }
+
+  const char*const & AsIo() const {
+    assert(IsIo());
+    return io._0;
+  }
+
+  static FFIError Validation(const FFIValidationError &_0) {
+    FFIError result;
+    ::new (&result.validation._0) (FFIValidationError)(_0);
+    result.tag = Tag::Validation;
+    return result;
+  }
+
+  bool IsValidation() const {
+    return tag == Tag::Validation;
+  }
+
+  const FFIValidationError& AsValidation() const {
+    assert(IsValidation());
+    return validation._0;
+  }
+};
+
+template<typename T>
+struct FFIResult {
+  enum class Tag : uint8_t {
+    Ok,
+    Err,
+  };
+
+  struct Ok_Body {
+    T _0;
+  };
+
+  struct Err_Body {
+    FFIError _0;
+  };
+
+  Tag tag;
+  union {
+    Ok_Body ok;
+    Err_Body err;
+  };
+
+  static FFIResult Ok(const T &_0) {
+    FFIResult result;
+    ::new (&result.ok._0) (T)(_0);
+    result.tag = Tag::Ok;
+    return result;
+  }
+
+  bool IsOk() const {
+    return tag == Tag::Ok;
+  }
+
+  const T& AsOk() const {
+    assert(IsOk());
+    return ok._0;
+  }
+
+  static FFIResult Err(const FFIError &_0) {
+    FFIResult result;
+    ::new (&result.err._0) (FFIError)(_0);
+    result.tag = Tag::Err;
+    return result;
+  }
+
+  bool IsErr() const {
+    return tag == Tag::Err;
+  }
+
+  const FFIError& AsErr() const {
+    assert(IsErr());
+    return err._0;
+  }
+};
+
+struct RawTransaction {
+  int32_t id;
+  int32_t shop_id;
+  const char *mod_name;
+  int32_t local_form_id;
+  const char *name;
+  int32_t form_type;
+  bool is_food;
+  int32_t price;
+  bool is_sell;
+  int32_t quantity;
+  int32_t amount;
+  const char **keywords;
+  uintptr_t keywords_len;
+};
+
+struct RawFreshness {
+  FreshnessSource source;
+  /// Unix timestamp in seconds of when the server sent the value, or -1 if unknown.
+  int64_t date;
+};
+
+struct RawOwnerVec {
+  RawOwner *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct RawShopVec {
+  RawShop *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+/* bad hack added by thallada. See: https://github.com/eqrion/cbindgen/issues/402 */
+struct _Helper_0 {
+    FFIResult<bool> _bool_result;
+    FFIResult<int32_t> _int_result;
+    FFIResult<RawOwner> _raw_owner_result;
+    FFIResult<RawOwnerVec> _raw_owner_vec_result;
+    FFIResult<RawShop> _raw_shop_result;
+    FFIResult<RawShopVec> _raw_shop_vec_result;
+    FFIResult<RawInteriorRefData> _raw_interior_ref_data_result;
+    FFIResult<RawMerchandiseVec> _raw_merchandise_vec_result;
+    FFIResult<RawTransaction> _raw_transaction_result;
+    FFIResult<BazaarClient*> _client_result;
+};
+
+// dummy extern C block to close curly brace (did I mention this is a bad hack?)
+extern "C" {
+};
+
+struct RawShopPage {
+  RawShopVec shops;
+  /// Offset to request the next page with, or -1 if this is the last page.
+  int32_t next_offset;
+};
+
+struct RawTransactionVec {
+  RawTransaction *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct MemoryCacheStats {
+  uint64_t hits;
+  uint64_t misses;
+  uintptr_t len;
+  uintptr_t capacity;
+};
+
+struct RawMigratedShop {
+  int32_t local_id;
+  int32_t server_id;
+};
+
+struct RawMigration {
+  int32_t owner_id;
+  RawMigratedShop *shops;
+  uintptr_t shops_len;
+  uintptr_t shops_cap;
+};
+
+/* same hack as _Helper_0, for a type that cbindgen emits after RawShopVec */
+struct _Helper_1 {
+    FFIResult<RawMigration> _raw_migration_result;
+};
+
+extern "C" {
+};
+
+struct ClientOptions {
+  /// Timeout in milliseconds for each request made by the client, or 0 to never time out.
+  uint64_t timeout_ms;
+  /// Number of background threads that run requests made with the `client_*_async` functions.
+  uint32_t worker_threads;
+  /// Total number of times a request that fails in a way that might be temporary is sent, or 1 to
+  /// never retry. Only requests that are safe to repeat are retried.
+  uint32_t retry_max_attempts;
+  /// Delay before the first retry in milliseconds, doubled on each retry after that.
+  uint64_t retry_base_delay_ms;
+  uint64_t retry_max_delay_ms;
+  /// Randomize retry delays so that many clients don't retry at the same moment.
+  bool retry_jitter;
+  /// Maximum number of API responses kept deserialized in memory in front of the file cache, or 0
+  /// to always read from the file cache.
+  uint32_t memory_cache_capacity;
+};
+
+struct RawOutboxOutcome {
+  uint64_t id;
+  RawMutationKind kind;
+  int32_t shop_id;
+  FFIResult<bool> result;
+};
+
+struct RawOutboxOutcomeVec {
+  RawOutboxOutcome *ptr;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+struct LogOptions {
+  /// Path of the log file, or null for `Documents\My Games\Skyrim Special Edition\SKSE\BazaarRealmClient.log`.
+  const char *path;
+  LogLevel level;
+  /// Size in bytes at which the log file is rotated, or 0 to let it grow forever.
+  uint64_t max_size_bytes;
+  /// Number of rotated log files kept next to it as `<path>.1`, `<path>.2` and so on.
+  uint32_t max_files;
+};
+
+struct FFIRequestResult {
+  enum class Tag : uint8_t {
+    Pending,
+    Cancelled,
+    Unknown,
+    Bool,
+    Int,
+    Owner,
+    OwnerVec,
+    Shop,
+    ShopVec,
+    ShopPage,
+    InteriorRefData,
+    MerchandiseVec,
+    Transaction,
+    TransactionVec,
+    Panicked,
+  };
+
+  struct Bool_Body {
+    FFIResult<bool> _0;
+  };
+
+  struct Int_Body {
+    FFIResult<int32_t> _0;
+  };
+
+  struct Owner_Body {
+    FFIResult<RawOwner> _0;
+  };
+
+  struct OwnerVec_Body {
+    FFIResult<RawOwnerVec> _0;
+  };
+
+  struct Shop_Body {
+    FFIResult<RawShop> _0;
+  };
+
+  struct ShopVec_Body {
+    FFIResult<RawShopVec> _0;
+  };
+
+  struct ShopPage_Body {
+    FFIResult<RawShopPage> _0;
+  };
+
+  struct InteriorRefData_Body {
+    FFIResult<RawInteriorRefData> _0;
+  };
+
+  struct MerchandiseVec_Body {
+    FFIResult<RawMerchandiseVec> _0;
+  };
+
+  struct Transaction_Body {
+    FFIResult<RawTransaction> _0;
+  };
+
+  struct TransactionVec_Body {
+    FFIResult<RawTransactionVec> _0;
+  };
+
+  struct Panicked_Body {
+    FFIError _0;
+  };
+
+  Tag tag;
+  union {
+    Bool_Body bool_;
+    Int_Body int_;
+    Owner_Body owner;
+    OwnerVec_Body owner_vec;
+    Shop_Body shop;
+    ShopVec_Body shop_vec;
+    ShopPage_Body shop_page;
+    InteriorRefData_Body interior_ref_data;
+    MerchandiseVec_Body merchandise_vec;
+    Transaction_Body transaction;
+    TransactionVec_Body transaction_vec;
+    Panicked_Body panicked;
+  };
+
+  static FFIRequestResult Pending() {
+    FFIRequestResult result;
+    result.tag = Tag::Pending;
+    return result;
+  }
+
+  bool IsPending() const {
+    return tag == Tag::Pending;
+  }
+
+  static FFIRequestResult Cancelled() {
+    FFIRequestResult result;
+    result.tag = Tag::Cancelled;
+    return result;
+  }
+
+  bool IsCancelled() const {
+    return tag == Tag::Cancelled;
+  }
+
+  static FFIRequestResult Unknown() {
+    FFIRequestResult result;
+    result.tag = Tag::Unknown;
+    return result;
+  }
+
+  bool IsUnknown() const {
+    return tag == Tag::Unknown;
+  }
+
+  static FFIRequestResult Bool(const FFIResult<bool> &_0) {
+    FFIRequestResult result;
+    ::new (&result.bool_._0) (FFIResult<bool>)(_0);
+    result.tag = Tag::Bool;
+    return result;
+  }
+
+  bool IsBool() const {
+    return tag == Tag::Bool;
+  }
+
+  const FFIResult<bool>& AsBool() const {
+    assert(IsBool());
+    return bool_._0;
+  }
+
+  static FFIRequestResult Int(const FFIResult<int32_t> &_0) {
+    FFIRequestResult result;
+    ::new (&result.int_._0) (FFIResult<int32_t>)(_0);
+    result.tag = Tag::Int;
+    return result;
+  }
+
+  bool IsInt() const {
+    return tag == Tag::Int;
+  }
+
+  const FFIResult<int32_t>& AsInt() const {
+    assert(IsInt());
+    return int_._0;
+  }
+
+  static FFIRequestResult Owner(const FFIResult<RawOwner> &_0) {
+    FFIRequestResult result;
+    ::new (&result.owner._0) (FFIResult<RawOwner>)(_0);
+    result.tag = Tag::Owner;
+    return result;
+  }
+
+  bool IsOwner() const {
+    return tag == Tag::Owner;
+  }
+
+  const FFIResult<RawOwner>& AsOwner() const {
+    assert(IsOwner());
+    return owner._0;
+  }
+
+  static FFIRequestResult OwnerVec(const FFIResult<RawOwnerVec> &_0) {
+    FFIRequestResult result;
+    ::new (&result.owner_vec._0) (FFIResult<RawOwnerVec>)(_0);
+    result.tag = Tag::OwnerVec;
+    return result;
+  }
+
+  bool IsOwnerVec() const {
+    return tag == Tag::OwnerVec;
+  }
+
+  const FFIResult<RawOwnerVec>& AsOwnerVec() const {
+    assert(IsOwnerVec());
+    return owner_vec._0;
+  }
+
+  static FFIRequestResult Shop(const FFIResult<RawShop> &_0) {
+    FFIRequestResult result;
+    ::new (&result.shop._0) (FFIResult<RawShop>)(_0);
+    result.tag = Tag::Shop;
+    return result;
+  }
+
+  bool IsShop() const {
+    return tag == Tag::Shop;
+  }
+
+  const FFIResult<RawShop>& AsShop() const {
+    assert(IsShop());
+    return shop._0;
+  }
+
+  static FFIRequestResult ShopVec(const FFIResult<RawShopVec> &_0) {
+    FFIRequestResult result;
+    ::new (&result.shop_vec._0) (FFIResult<RawShopVec>)(_0);
+    result.tag = Tag::ShopVec;
+    return result;
+  }
+
+  bool IsShopVec() const {
+    return tag == Tag::ShopVec;
+  }
+
+  const FFIResult<RawShopVec>& AsShopVec() const {
+    assert(IsShopVec());
+    return shop_vec._0;
+  }
+
+  static FFIRequestResult ShopPage(const FFIResult<RawShopPage> &_0) {
+    FFIRequestResult result;
+    ::new (&result.shop_page._0) (FFIResult<RawShopPage>)(_0);
+    result.tag = Tag::ShopPage;
+    return result;
+  }
+
+  bool IsShopPage() const {
+    return tag == Tag::ShopPage;
+  }
+
+  const FFIResult<RawShopPage>& AsShopPage() const {
+    assert(IsShopPage());
+    return shop_page._0;
+  }
+
+  static FFIRequestResult InteriorRefData(const FFIResult<RawInteriorRefData> &_0) {
+    FFIRequestResult result;
+    ::new (&result.interior_ref_data._0) (FFIResult<RawInteriorRefData>)(_0);
+    result.tag = Tag::InteriorRefData;
+    return result;
+  }
+
+  bool IsInteriorRefData() const {
+    return tag == Tag::InteriorRefData;
+  }
+
+  const FFIResult<RawInteriorRefData>& AsInteriorRefData() const {
+    assert(IsInteriorRefData());
+    return interior_ref_data._0;
   }
 
-  const char*const & AsNetwork() const {
-    assert(IsNetwork());
-    return network._0;
+  static FFIRequestResult MerchandiseVec(const FFIResult<RawMerchandiseVec> &_0) {
+    FFIRequestResult result;
+    ::new (&result.merchandise_vec._0) (FFIResult<RawMerchandiseVec>)(_0);
+    result.tag = Tag::MerchandiseVec;
+    return result;
   }
-};
 
-template<typename T>
-struct FFIResult {
-  enum class Tag : uint8_t {
-    Ok,
-    Err,
-  };
+  bool IsMerchandiseVec() const {
+    return tag == Tag::MerchandiseVec;
+  }
 
-  struct Ok_Body {
-    T _0;
-  };
+  const FFIResult<RawMerchandiseVec>& AsMerchandiseVec() const {
+    assert(IsMerchandiseVec());
+    return merchandise_vec._0;
+  }
 
-  struct Err_Body {
-    FFIError _0;
-  };
+  static FFIRequestResult Transaction(const FFIResult<RawTransaction> &_0) {
+    FFIRequestResult result;
+    ::new (&result.transaction._0) (FFIResult<RawTransaction>)(_0);
+    result.tag = Tag::Transaction;
+    return result;
+  }
 
-  Tag tag;
-  union {
-    Ok_Body ok;
-    Err_Body err;
-  };
+  bool IsTransaction() const {
+    return tag == Tag::Transaction;
+  }
 
-  static FFIResult Ok(const T &_0) {
-    FFIResult result;
-    ::new (&result.ok._0) (T)(_0);
-    result.tag = Tag::Ok;
+  const FFIResult<RawTransaction>& AsTransaction() const {
+    assert(IsTransaction());
+    return transaction._0;
+  }
+
+  static FFIRequestResult TransactionVec(const FFIResult<RawTransactionVec> &_0) {
+    FFIRequestResult result;
+    ::new (&result.transaction_vec._0) (FFIResult<RawTransactionVec>)(_0);
+    result.tag = Tag::TransactionVec;
     return result;
   }
 
-  bool IsOk() const {
-    return tag == Tag::Ok;
+  bool IsTransactionVec() const {
+    return tag == Tag::TransactionVec;
   }
 
-  const T& AsOk() const {
-    assert(IsOk());
-    return ok._0;
+  const FFIResult<RawTransactionVec>& AsTransactionVec() const {
+    assert(IsTransactionVec());
+    return transaction_vec._0;
   }
 
-  static FFIResult Err(const FFIError &_0) {
-    FFIResult result;
-    ::new (&result.err._0) (FFIError)(_0);
-    result.tag = Tag::Err;
+  static FFIRequestResult Panicked(const FFIError &_0) {
+    FFIRequestResult result;
+    ::new (&result.panicked._0) (FFIError)(_0);
+    result.tag = Tag::Panicked;
     return result;
   }
 
-  bool IsErr() const {
-    return tag == Tag::Err;
+  bool IsPanicked() const {
+    return tag == Tag::Panicked;
   }
 
-  const FFIError& AsErr() const {
-    assert(IsErr());
-    return err._0;
+  const FFIError& AsPanicked() const {
+    assert(IsPanicked());
+    return panicked._0;
   }
 };
 
-struct RawInteriorRef {
-  const char *base_mod_name;
-  uint32_t base_local_form_id;
-  const char *ref_mod_name;
-  uint32_t ref_local_form_id;
-  float position_x;
-  float position_y;
-  float position_z;
-  float angle_x;
-  float angle_y;
-  float angle_z;
-  uint16_t scale;
-};
+/// Called from a worker thread when a request finishes. The callback takes ownership of the
+/// result, and the request can no longer be polled.
+using RequestCallback = void(*)(uint64_t request_id, FFIRequestResult result);
 
-struct RawShelf {
-  uint32_t shelf_type;
-  float position_x;
-  float position_y;
-  float position_z;
-  float angle_x;
-  float angle_y;
-  float angle_z;
-  uint16_t scale;
-  uint32_t page;
-  uint32_t filter_form_type;
-  bool filter_is_food;
-  const char *search;
-  const char *sort_on;
-  bool sort_asc;
-};
 
-struct RawMerchandise {
-  const char *mod_name;
-  uint32_t local_form_id;
-  const char *name;
-  uint32_t quantity;
-  uint32_t form_type;
-  bool is_food;
-  uint32_t price;
-  const char **keywords;
-  uintptr_t keywords_len;
-};
+extern "C" {
 
-struct RawMerchandiseVec {
-  RawMerchandise *ptr;
-  uintptr_t len;
-  uintptr_t cap;
-};
+bool cancel_request(const BazaarClient *client, uint64_t request_id);
 
-struct RawOwner {
-  int32_t id;
-  const char *name;
-  int32_t mod_version;
-};
+FFIResult<int32_t> client_create_interior_ref_list(const BazaarClient *client,
+                                                   int32_t shop_id,
+                                                   const RawInteriorRef *raw_interior_ref_ptr,
+                                                   uintptr_t raw_interior_ref_len,
+                                                   const RawShelf *raw_shelf_ptr,
+                                                   uintptr_t raw_shelf_len);
 
-struct RawShop {
-  int32_t id;
-  const char *name;
-  const char *description;
-  int32_t gold;
-  const char *shop_type;
-  const char **vendor_keywords;
-  uintptr_t vendor_keywords_len;
-  bool vendor_keywords_exclude;
-};
+uint64_t client_create_interior_ref_list_async(const BazaarClient *client,
+                                               int32_t shop_id,
+                                               const RawInteriorRef *raw_interior_ref_ptr,
+                                               uintptr_t raw_interior_ref_len,
+                                               const RawShelf *raw_shelf_ptr,
+                                               uintptr_t raw_shelf_len);
 
-struct RawTransaction {
-  int32_t id;
-  int32_t shop_id;
-  const char *mod_name;
-  int32_t local_form_id;
-  const char *name;
-  int32_t form_type;
-  bool is_food;
-  int32_t price;
-  bool is_sell;
-  int32_t quantity;
-  int32_t amount;
-  const char **keywords;
-  uintptr_t keywords_len;
-};
+/// Like `client_create_interior_ref_list`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`.
+FFIResult<int32_t> client_create_interior_ref_list_with_idempotency_key(const BazaarClient *client,
+                                                                        int32_t shop_id,
+                                                                        const RawInteriorRef *raw_interior_ref_ptr,
+                                                                        uintptr_t raw_interior_ref_len,
+                                                                        const RawShelf *raw_shelf_ptr,
+                                                                        uintptr_t raw_shelf_len,
+                                                                        const char *idempotency_key);
 
-struct RawInteriorRefVec {
-  RawInteriorRef *ptr;
-  uintptr_t len;
-  uintptr_t cap;
-};
+FFIResult<RawMerchandiseVec> client_create_merchandise_list(const BazaarClient *client,
+                                                            int32_t shop_id,
+                                                            const RawMerchandise *raw_merchandise_ptr,
+                                                            uintptr_t raw_merchandise_len);
 
-struct RawShelfVec {
-  RawShelf *ptr;
-  uintptr_t len;
-  uintptr_t cap;
-};
+uint64_t client_create_merchandise_list_async(const BazaarClient *client,
+                                              int32_t shop_id,
+                                              const RawMerchandise *raw_merchandise_ptr,
+                                              uintptr_t raw_merchandise_len);
 
-struct RawInteriorRefData {
-  RawInteriorRefVec interior_ref_vec;
-  RawShelfVec shelf_vec;
-};
+/// Like `client_create_merchandise_list`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`.
+FFIResult<RawMerchandiseVec> client_create_merchandise_list_with_idempotency_key(const BazaarClient *client,
+                                                                                 int32_t shop_id,
+                                                                                 const RawMerchandise *raw_merchandise_ptr,
+                                                                                 uintptr_t raw_merchandise_len,
+                                                                                 const char *idempotency_key);
 
-struct RawShopVec {
-  RawShop *ptr;
-  uintptr_t len;
-  uintptr_t cap;
-};
+FFIResult<RawOwner> client_create_owner(const BazaarClient *client,
+                                        const char *name,
+                                        int32_t mod_version);
 
-/* bad hack added by thallada. See: https://github.com/eqrion/cbindgen/issues/402 */
-struct _Helper_0 {
-    FFIResult<bool> _bool_result;
-    FFIResult<int32_t> _int_result;
-    FFIResult<RawOwner> _raw_owner_result;
-    FFIResult<RawShop> _raw_shop_result;
-    FFIResult<RawShopVec> _raw_shop_vec_result;
-    FFIResult<RawInteriorRefData> _raw_interior_ref_data_result;
-    FFIResult<RawMerchandiseVec> _raw_merchandise_vec_result;
-    FFIResult<RawTransaction> _raw_transaction_result;
-};
+uint64_t client_create_owner_async(const BazaarClient *client,
+                                   const char *name,
+                                   int32_t mod_version);
 
-// dummy extern C block to close curly brace (did I mention this is a bad hack?)
-extern "C" {
-};
+/// Like `client_create_owner`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`, so the plugin can retry it itself without creating a second owner.
+FFIResult<RawOwner> client_create_owner_with_idempotency_key(const BazaarClient *client,
+                                                             const char *name,
+                                                             int32_t mod_version,
+                                                             const char *idempotency_key);
 
+FFIResult<RawShop> client_create_shop(const BazaarClient *client,
+                                      const char *name,
+                                      const char *description);
 
-extern "C" {
+uint64_t client_create_shop_async(const BazaarClient *client,
+                                  const char *name,
+                                  const char *description);
+
+/// Like `client_create_shop`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`, so the plugin can retry it itself without creating a second shop.
+FFIResult<RawShop> client_create_shop_with_idempotency_key(const BazaarClient *client,
+                                                           const char *name,
+                                                           const char *description,
+                                                           const char *idempotency_key);
+
+FFIResult<RawTransaction> client_create_transaction(const BazaarClient *client,
+                                                    RawTransaction raw_transaction);
+
+uint64_t client_create_transaction_async(const BazaarClient *client,
+                                         RawTransaction raw_transaction);
+
+/// Like `client_create_transaction`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`. Retrying a transaction that timed out with the same key can't make
+/// the purchase twice.
+FFIResult<RawTransaction> client_create_transaction_with_idempotency_key(const BazaarClient *client,
+                                                                         RawTransaction raw_transaction,
+                                                                         const char *idempotency_key);
+
+FFIResult<bool> client_delete_interior_ref_list(const BazaarClient *client,
+                                                int32_t interior_ref_list_id);
+
+uint64_t client_delete_interior_ref_list_async(const BazaarClient *client,
+                                               int32_t interior_ref_list_id);
+
+FFIResult<bool> client_delete_interior_ref_list_by_shop_id(const BazaarClient *client,
+                                                           int32_t shop_id);
+
+uint64_t client_delete_interior_ref_list_by_shop_id_async(const BazaarClient *client,
+                                                          int32_t shop_id);
+
+FFIResult<bool> client_delete_merchandise_list(const BazaarClient *client,
+                                               int32_t merchandise_list_id);
+
+uint64_t client_delete_merchandise_list_async(const BazaarClient *client,
+                                              int32_t merchandise_list_id);
+
+FFIResult<bool> client_delete_merchandise_list_by_shop_id(const BazaarClient *client,
+                                                          int32_t shop_id);
+
+uint64_t client_delete_merchandise_list_by_shop_id_async(const BazaarClient *client,
+                                                         int32_t shop_id);
+
+FFIResult<bool> client_delete_owner(const BazaarClient *client, int32_t id);
+
+uint64_t client_delete_owner_async(const BazaarClient *client, int32_t id);
+
+FFIResult<bool> client_delete_shop(const BazaarClient *client, int32_t shop_id);
+
+uint64_t client_delete_shop_async(const BazaarClient *client, int32_t shop_id);
+
+void client_free(BazaarClient *client);
+
+FFIResult<RawInteriorRefData> client_get_interior_ref_list(const BazaarClient *client,
+                                                           int32_t interior_ref_list_id);
+
+uint64_t client_get_interior_ref_list_async(const BazaarClient *client,
+                                            int32_t interior_ref_list_id);
+
+FFIResult<RawInteriorRefData> client_get_interior_ref_list_by_shop_id(const BazaarClient *client,
+                                                                      int32_t shop_id);
+
+uint64_t client_get_interior_ref_list_by_shop_id_async(const BazaarClient *client, int32_t shop_id);
+
+FFIResult<RawMerchandiseVec> client_get_merchandise_list(const BazaarClient *client,
+                                                         int32_t merchandise_list_id);
+
+uint64_t client_get_merchandise_list_async(const BazaarClient *client, int32_t merchandise_list_id);
+
+FFIResult<RawMerchandiseVec> client_get_merchandise_list_by_shop_id(const BazaarClient *client,
+                                                                    int32_t shop_id);
+
+uint64_t client_get_merchandise_list_by_shop_id_async(const BazaarClient *client, int32_t shop_id);
+
+/// Like `client_get_merchandise_list_by_shop_id`, but also writes whether the list came from the
+/// server or the cache to `freshness`, which may be null.
+FFIResult<RawMerchandiseVec> client_get_merchandise_list_by_shop_id_with_freshness(const BazaarClient *client,
+                                                                                   int32_t shop_id,
+                                                                                   RawFreshness *freshness);
+
+FFIResult<RawOwner> client_get_owner(const BazaarClient *client, int32_t id);
+
+uint64_t client_get_owner_async(const BazaarClient *client, int32_t id);
+
+FFIResult<RawShop> client_get_shop(const BazaarClient *client, int32_t shop_id);
+
+uint64_t client_get_shop_async(const BazaarClient *client, int32_t shop_id);
+
+/// Like `client_get_shop`, but also writes whether the shop came from the server or the cache to
+/// `freshness`, which may be null.
+FFIResult<RawShop> client_get_shop_with_freshness(const BazaarClient *client,
+                                                  int32_t shop_id,
+                                                  RawFreshness *freshness);
+
+FFIResult<RawTransaction> client_get_transaction(const BazaarClient *client,
+                                                 int32_t transaction_id);
+
+uint64_t client_get_transaction_async(const BazaarClient *client, int32_t transaction_id);
+
+FFIResult<RawOwnerVec> client_list_owners(const BazaarClient *client,
+                                          int32_t limit,
+                                          int32_t offset);
+
+uint64_t client_list_owners_async(const BazaarClient *client, int32_t limit, int32_t offset);
+
+FFIResult<RawShopVec> client_list_shops(const BazaarClient *client);
+
+uint64_t client_list_shops_async(const BazaarClient *client);
+
+FFIResult<RawShopVec> client_list_shops_by_owner(const BazaarClient *client, int32_t owner_id);
+
+uint64_t client_list_shops_by_owner_async(const BazaarClient *client, int32_t owner_id);
+
+FFIResult<RawShopPage> client_list_shops_page(const BazaarClient *client,
+                                              int32_t limit,
+                                              int32_t offset);
+
+uint64_t client_list_shops_page_async(const BazaarClient *client, int32_t limit, int32_t offset);
+
+/// Like `client_list_shops`, but also writes the freshness of the least fresh page to `freshness`,
+/// which may be null.
+FFIResult<RawShopVec> client_list_shops_with_freshness(const BazaarClient *client,
+                                                       RawFreshness *freshness);
+
+FFIResult<RawTransactionVec> client_list_transactions(const BazaarClient *client,
+                                                      int32_t limit,
+                                                      int32_t offset);
+
+uint64_t client_list_transactions_async(const BazaarClient *client, int32_t limit, int32_t offset);
+
+FFIResult<RawTransactionVec> client_list_transactions_by_shop_id(const BazaarClient *client,
+                                                                 int32_t shop_id,
+                                                                 int32_t limit,
+                                                                 int32_t offset);
+
+uint64_t client_list_transactions_by_shop_id_async(const BazaarClient *client,
+                                                   int32_t shop_id,
+                                                   int32_t limit,
+                                                   int32_t offset);
+
+MemoryCacheStats client_memory_cache_stats(const BazaarClient *client);
+
+/// Uploads the local store at `store_path` (null for the default) to the server of `client`. See
+/// `BazaarClient::migrate_local_store`.
+FFIResult<RawMigration> client_migrate_local_store(const BazaarClient *client,
+                                                   const char *store_path);
+
+FFIResult<BazaarClient*> client_new(const char *api_url,
+                                    const char *api_key,
+                                    ClientOptions options);
+
+/// Like `client_new_with_cache_root`, but the client uses the single-player backend, which keeps
+/// everything in the store at `store_path` instead of on a server. A null `store_path` uses
+/// `Data/SKSE/Plugins/BazaarRealmLocal.bin`.
+FFIResult<BazaarClient*> client_new_local(const char *store_path,
+                                          const char *cache_root,
+                                          ClientOptions options);
+
+/// Like `client_new`, but keeps this client's file cache under `cache_root`. A null `cache_root`
+/// uses the one set with `set_cache_root`.
+FFIResult<BazaarClient*> client_new_with_cache_root(const char *api_url,
+                                                    const char *api_key,
+                                                    const char *cache_root,
+                                                    ClientOptions options);
+
+/// Like `client_new_with_cache_root`, but the client talks to its own in-memory `FakeApi` instead
+/// of a server. Everything created with it is gone once the client is freed.
+FFIResult<BazaarClient*> client_new_with_fake_api(const char *api_key,
+                                                  const char *cache_root,
+                                                  ClientOptions options);
+
+/// Number of changes still waiting in the outbox to be sent, or -1 if it couldn't be read.
+int64_t client_outbox_len(const BazaarClient *client);
+
+FFIResult<bool> client_status_check(const BazaarClient *client);
+
+uint64_t client_status_check_async(const BazaarClient *client);
+
+/// Returns the outcome of every outbox item replayed since the last call and forgets them.
+FFIResult<RawOutboxOutcomeVec> client_take_outbox_outcomes(const BazaarClient *client);
+
+FFIResult<int32_t> client_update_interior_ref_list(const BazaarClient *client,
+                                                   int32_t shop_id,
+                                                   const RawInteriorRef *raw_interior_ref_ptr,
+                                                   uintptr_t raw_interior_ref_len,
+                                                   const RawShelf *raw_shelf_ptr,
+                                                   uintptr_t raw_shelf_len);
+
+uint64_t client_update_interior_ref_list_async(const BazaarClient *client,
+                                               int32_t shop_id,
+                                               const RawInteriorRef *raw_interior_ref_ptr,
+                                               uintptr_t raw_interior_ref_len,
+                                               const RawShelf *raw_shelf_ptr,
+                                               uintptr_t raw_shelf_len);
+
+FFIResult<RawMerchandiseVec> client_update_merchandise_list(const BazaarClient *client,
+                                                            int32_t shop_id,
+                                                            const RawMerchandise *raw_merchandise_ptr,
+                                                            uintptr_t raw_merchandise_len);
+
+uint64_t client_update_merchandise_list_async(const BazaarClient *client,
+                                              int32_t shop_id,
+                                              const RawMerchandise *raw_merchandise_ptr,
+                                              uintptr_t raw_merchandise_len);
+
+FFIResult<RawOwner> client_update_owner(const BazaarClient *client,
+                                        int32_t id,
+                                        const char *name,
+                                        int32_t mod_version);
+
+uint64_t client_update_owner_async(const BazaarClient *client,
+                                   int32_t id,
+                                   const char *name,
+                                   int32_t mod_version);
+
+FFIResult<RawShop> client_update_shop(const BazaarClient *client,
+                                      uint32_t id,
+                                      const char *name,
+                                      const char *description,
+                                      int32_t gold,
+                                      const char *shop_type,
+                                      const char **vendor_keywords,
+                                      uintptr_t vendor_keywords_len,
+                                      bool vendor_keywords_exclude);
+
+uint64_t client_update_shop_async(const BazaarClient *client,
+                                  uint32_t id,
+                                  const char *name,
+                                  const char *description,
+                                  int32_t gold,
+                                  const char *shop_type,
+                                  const char **vendor_keywords,
+                                  uintptr_t vendor_keywords_len,
+                                  bool vendor_keywords_exclude);
 
 FFIResult<int32_t> create_interior_ref_list(const char *api_url,
                                             const char *api_key,
@@ -270,10 +1472,62 @@ FFIResult<RawTransaction> create_transaction(const char *api_url,
                                              const char *api_key,
                                              RawTransaction raw_transaction);
 
+FFIResult<bool> delete_interior_ref_list(const char *api_url,
+                                         const char *api_key,
+                                         int32_t interior_ref_list_id);
+
+FFIResult<bool> delete_interior_ref_list_by_shop_id(const char *api_url,
+                                                    const char *api_key,
+                                                    int32_t shop_id);
+
+FFIResult<bool> delete_merchandise_list(const char *api_url,
+                                        const char *api_key,
+                                        int32_t merchandise_list_id);
+
+FFIResult<bool> delete_merchandise_list_by_shop_id(const char *api_url,
+                                                   const char *api_key,
+                                                   int32_t shop_id);
+
+FFIResult<bool> delete_owner(const char *api_url, const char *api_key, int32_t id);
+
+FFIResult<bool> delete_shop(const char *api_url, const char *api_key, int32_t shop_id);
+
+FFIErrorCode ffi_error_code(const FFIError *error);
+
+void free_ffi_error(FFIError error);
+
+void free_raw_interior_ref_data(RawInteriorRefData raw_interior_ref_data);
+
+void free_raw_merchandise_vec(RawMerchandiseVec raw_merchandise_vec);
+
+void free_raw_migration(RawMigration raw_migration);
+
+void free_raw_outbox_outcome_vec(RawOutboxOutcomeVec raw_outcome_vec);
+
+void free_raw_owner(RawOwner raw_owner);
+
+void free_raw_owner_vec(RawOwnerVec raw_owner_vec);
+
+void free_raw_shop(RawShop raw_shop);
+
+void free_raw_shop_page(RawShopPage raw_shop_page);
+
+void free_raw_shop_vec(RawShopVec raw_shop_vec);
+
+void free_raw_transaction(RawTransaction raw_transaction);
+
+void free_raw_transaction_vec(RawTransactionVec raw_transaction_vec);
+
+/// # Safety
+/// `ptr` must be a string returned by this library that hasn't been freed yet, or null.
 void free_string(char *ptr);
 
+/// Returns a new random API key. Free it with `free_string`.
 char *generate_api_key();
 
+/// Returns a new key for the `*_with_idempotency_key` functions. Free it with `free_string`.
+char *generate_idempotency_key();
+
 FFIResult<RawInteriorRefData> get_interior_ref_list(const char *api_url,
                                                     const char *api_key,
                                                     int32_t interior_ref_list_id);
@@ -290,12 +1544,55 @@ FFIResult<RawMerchandiseVec> get_merchandise_list_by_shop_id(const char *api_url
                                                              const char *api_key,
                                                              int32_t shop_id);
 
+FFIResult<RawOwner> get_owner(const char *api_url, const char *api_key, int32_t id);
+
 FFIResult<RawShop> get_shop(const char *api_url, const char *api_key, int32_t shop_id);
 
+FFIResult<RawTransaction> get_transaction(const char *api_url,
+                                          const char *api_key,
+                                          int32_t transaction_id);
+
 bool init();
 
+FFIResult<bool> init_with_options(LogOptions options);
+
+FFIResult<RawOwnerVec> list_owners(const char *api_url,
+                                   const char *api_key,
+                                   int32_t limit,
+                                   int32_t offset);
+
 FFIResult<RawShopVec> list_shops(const char *api_url, const char *api_key);
 
+FFIResult<RawShopVec> list_shops_by_owner(const char *api_url,
+                                          const char *api_key,
+                                          int32_t owner_id);
+
+FFIResult<RawShopPage> list_shops_page(const char *api_url,
+                                       const char *api_key,
+                                       int32_t limit,
+                                       int32_t offset);
+
+FFIResult<RawTransactionVec> list_transactions(const char *api_url,
+                                               const char *api_key,
+                                               int32_t limit,
+                                               int32_t offset);
+
+FFIResult<RawTransactionVec> list_transactions_by_shop_id(const char *api_url,
+                                                          const char *api_key,
+                                                          int32_t shop_id,
+                                                          int32_t limit,
+                                                          int32_t offset);
+
+/// Takes the result of a request made with a `client_*_async` function. Results are kept until
+/// they are polled, so poll every request that isn't answered through the request callback.
+FFIRequestResult poll_request(const BazaarClient *client, uint64_t request_id);
+
+/// Sets the directory that clients created after this keep their file cache in, or restores the
+/// default `Data\SKSE\Plugins\BazaarRealmCache` if `cache_root` is null.
+FFIResult<bool> set_cache_root(const char *cache_root);
+
+bool set_request_callback(const BazaarClient *client, Option<RequestCallback> callback);
+
 FFIResult<bool> status_check(const char *api_url);
 
 FFIResult<int32_t> update_interior_ref_list(const char *api_url,
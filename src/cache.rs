@@ -1,117 +1,530 @@
 use std::{
-    fs::create_dir_all, fs::File, io::BufReader, io::Write, path::Path, path::PathBuf, thread,
+    any::Any,
+    collections::HashMap,
+    convert::TryInto,
+    fs::create_dir_all,
+    fs::read,
+    fs::read_dir,
+    fs::remove_file,
+    fs::rename,
+    fs::File,
+    io::BufReader,
+    io::ErrorKind,
+    io::Write,
+    path::Path,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Arc, Mutex, RwLock},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use base64::{encode_config, URL_SAFE_NO_PAD};
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
-#[cfg(test)]
-use tempfile::tempfile;
+use tempfile::NamedTempFile;
 
 #[cfg(not(test))]
 use log::{error, info};
 #[cfg(test)]
 use std::{println as error, println as info};
 
-use super::API_VERSION;
+use crate::{
+    client::BazaarClient,
+    error::{self, CacheCorruptError, CacheMissError},
+    freshness::{Fetched, Freshness, FreshnessSource},
+    API_VERSION,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub etag: Option<String>,
     pub date: Option<DateTime<Utc>>,
 }
 
-pub fn file_cache_dir(api_url: &str) -> Result<PathBuf> {
+impl Metadata {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let etag = headers
+            .get("etag")
+            .map(|val| val.to_str().unwrap_or("").to_string());
+        // the Date header is an RFC 2822 date, not the RFC 3339 that `DateTime` parses by default
+        let date = headers.get("date").map(|val| {
+            DateTime::parse_from_rfc2822(val.to_str().unwrap_or(""))
+                .map(|date| date.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now())
+        });
+        Self { etag, date }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C)]
+pub struct MemoryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+struct MemoryCacheEntry {
+    metadata: Metadata,
+    value: Arc<dyn Any + Send + Sync>,
+    last_used: u64,
+}
+
+/// Bounded least-recently-used cache of deserialized API responses that sits in front of the file
+/// cache. Entries are keyed by the path of the body cache file they mirror.
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<HashMap<PathBuf, MemoryCacheEntry>>,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl std::fmt::Debug for MemoryCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryCache")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // Doesn't count towards the stats, so that callers count one hit or miss per lookup.
+    fn get_entry(&self, cache_path: &Path) -> Option<(Metadata, Arc<dyn Any + Send + Sync>)> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(cache_path)?;
+        entry.last_used = self.clock.fetch_add(1, Ordering::Relaxed);
+        Some((entry.metadata.clone(), entry.value.clone()))
+    }
+
+    /// Value cached at `cache_path`. An entry of another type counts as a miss.
+    pub fn get<T: Clone + 'static>(&self, cache_path: &Path) -> Option<(Metadata, T)> {
+        let found = self.get_entry(cache_path).and_then(|(metadata, value)| {
+            value
+                .downcast_ref::<T>()
+                .map(|value| (metadata, value.clone()))
+        });
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        found
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(
+        &self,
+        cache_path: &Path,
+        metadata: Metadata,
+        value: T,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(cache_path) && entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            cache_path.to_path_buf(),
+            MemoryCacheEntry {
+                metadata,
+                value: Arc::new(value),
+                last_used: self.clock.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+
+    pub fn remove(&self, cache_path: &Path) {
+        self.entries.lock().unwrap().remove(cache_path);
+    }
+
+    pub fn remove_where(&self, predicate: impl Fn(&Path) -> bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|cache_path, _| !predicate(cache_path));
+    }
+
+    pub fn stats(&self) -> MemoryCacheStats {
+        MemoryCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self.entries.lock().unwrap().len(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Metadata for the value cached at `body_cache_path`, checking memory before the metadata file.
+    /// It only decides how a request is sent, so it isn't counted as a hit or miss.
+    pub fn load_metadata(
+        &self,
+        body_cache_path: &Path,
+        metadata_cache_path: &Path,
+    ) -> Result<Metadata> {
+        match self.get_entry(body_cache_path) {
+            Some((metadata, _)) => Ok(metadata),
+            None => load_metadata_from_file_cache(metadata_cache_path),
+        }
+    }
+
+    /// Value cached at `body_cache_path`, checking memory before the body file. Values read from
+    /// disk are kept in memory for the next lookup.
+    pub fn load<T>(&self, body_cache_path: &Path, metadata_cache_path: &Path) -> error::Result<T>
+    where
+        T: for<'de> Deserialize<'de> + CacheSchema + Clone + Send + Sync + 'static,
+    {
+        self.load_fetched(
+            body_cache_path,
+            metadata_cache_path,
+            FreshnessSource::OfflineFallback,
+        )
+        .map(Fetched::into_value)
+    }
+
+    /// Like `load`, but also returns the date the cached value was sent by the server, labeled with
+    /// why the cache was read.
+    pub fn load_fetched<T>(
+        &self,
+        body_cache_path: &Path,
+        metadata_cache_path: &Path,
+        source: FreshnessSource,
+    ) -> error::Result<Fetched<T>>
+    where
+        T: for<'de> Deserialize<'de> + CacheSchema + Clone + Send + Sync + 'static,
+    {
+        if let Some((metadata, value)) = self.get::<T>(body_cache_path) {
+            info!("returning value from memory cache: {:?}", body_cache_path);
+            return Ok(Fetched {
+                value,
+                freshness: Freshness::new(source, Some(&metadata)),
+            });
+        }
+        let value: T = from_file_cache(body_cache_path).inspect_err(|err| {
+            // without its body the ETag would just get the corrupt entry validated again
+            if err.is::<CacheCorruptError>() {
+                remove_file(metadata_cache_path).ok();
+            }
+        })?;
+        let metadata = load_metadata_from_file_cache(metadata_cache_path).ok();
+        if let Some(metadata) = &metadata {
+            self.insert(body_cache_path, metadata.clone(), value.clone());
+        }
+        Ok(Fetched {
+            value,
+            freshness: Freshness::new(source, metadata.as_ref()),
+        })
+    }
+}
+
+// Cache root for clients created without one, set with `set_cache_root`.
+static DEFAULT_CACHE_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// The root set with `set_default_cache_root`, or else `Data/SKSE/Plugins/BazaarRealmCache`
+/// relative to the working directory, which is the game folder when loaded by SKSE.
+pub fn default_cache_root() -> PathBuf {
+    let default_cache_root = DEFAULT_CACHE_ROOT
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match &*default_cache_root {
+        Some(cache_root) => cache_root.clone(),
+        None => Path::new("Data")
+            .join("SKSE")
+            .join("Plugins")
+            .join("BazaarRealmCache"),
+    }
+}
+
+/// Changes the cache root of clients created after this, or restores the built-in one with `None`.
+pub fn set_default_cache_root(cache_root: Option<PathBuf>) {
+    *DEFAULT_CACHE_ROOT
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = cache_root;
+}
+
+/// Each API gets its own directory under the cache root so that switching servers never mixes up
+/// their cached objects.
+pub fn file_cache_dir(cache_root: &Path, api_url: &str) -> PathBuf {
     let encoded_url = encode_config(api_url, URL_SAFE_NO_PAD);
-    let path = Path::new("Data/SKSE/Plugins/BazaarRealmCache")
-        .join(encoded_url)
-        .join(API_VERSION);
-    #[cfg(not(test))]
-    create_dir_all(&path)?;
-    Ok(path)
+    cache_root.join(encoded_url).join(API_VERSION)
+}
+
+const CACHE_MAGIC: &[u8; 4] = b"BZRC";
+const CACHE_HEADER_LEN: usize = 20;
+
+/// Version of the serialized layout of a type stored in the file cache. Bump it whenever the
+/// fields of the type change so that entries written by an older client are discarded instead of
+/// being deserialized into garbage.
+pub trait CacheSchema {
+    const SCHEMA_VERSION: u32;
+}
+
+impl<T: CacheSchema> CacheSchema for Vec<T> {
+    const SCHEMA_VERSION: u32 = T::SCHEMA_VERSION;
 }
 
-pub fn update_file_cache(cache_path: &Path, bytes: &Bytes) -> Result<()> {
-    #[cfg(not(test))]
-    let mut file = File::create(cache_path)?;
-    #[cfg(test)]
-    let mut file = tempfile()?;
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Prefixes a bincode payload with the cache header: magic number, schema version, payload length
+/// and a CRC32 of the payload (all little-endian).
+pub fn encode_cache_entry<T: CacheSchema>(payload: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(CACHE_HEADER_LEN + payload.len());
+    contents.extend_from_slice(CACHE_MAGIC);
+    contents.extend_from_slice(&T::SCHEMA_VERSION.to_le_bytes());
+    contents.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    contents.extend_from_slice(&crc32(payload).to_le_bytes());
+    contents.extend_from_slice(payload);
+    contents
+}
 
-    file.write_all(&bytes.as_ref())?;
+/// Checks the cache header and returns the payload after it, or the reason the entry is corrupt.
+pub fn decode_cache_entry<T: CacheSchema>(contents: &[u8]) -> Result<&[u8], String> {
+    if contents.len() < CACHE_HEADER_LEN || &contents[0..4] != CACHE_MAGIC {
+        return Err("missing cache header".to_string());
+    }
+    let schema_version = u32::from_le_bytes(contents[4..8].try_into().unwrap());
+    let len = u64::from_le_bytes(contents[8..16].try_into().unwrap());
+    let checksum = u32::from_le_bytes(contents[16..20].try_into().unwrap());
+    let payload = &contents[CACHE_HEADER_LEN..];
+    if schema_version != T::SCHEMA_VERSION {
+        return Err(format!(
+            "schema version {} does not match expected version {}",
+            schema_version,
+            T::SCHEMA_VERSION
+        ));
+    }
+    if len != payload.len() as u64 {
+        return Err(format!(
+            "payload is {} bytes but header expects {}",
+            payload.len(),
+            len
+        ));
+    }
+    if checksum != crc32(payload) {
+        return Err("checksum mismatch".to_string());
+    }
+    Ok(payload)
+}
+
+/// Moves a corrupt cache file aside to `<name>.corrupt` so it is not read again but is still
+/// around to debug.
+pub fn quarantine_file_cache(cache_path: &Path) {
+    let mut quarantine_path = cache_path.as_os_str().to_owned();
+    quarantine_path.push(".corrupt");
+    match rename(cache_path, &quarantine_path) {
+        Ok(()) => info!("quarantined corrupt cache file: {:?}", quarantine_path),
+        Err(err) => error!("Failed to quarantine corrupt cache file: {}", err),
+    }
+}
+
+// Serializes cache writes so that two responses for the same object can't interleave their body
+// and metadata files.
+static FILE_CACHE_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Writes `contents` to a temp file next to `cache_path`, fsyncs it, and renames it over
+/// `cache_path` so readers only ever see the old or the new file in full.
+pub fn write_file_atomically(cache_path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = cache_path.parent().unwrap_or_else(|| Path::new("."));
+    // the cache directory is only created once there is something to put in it
+    create_dir_all(dir)?;
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.as_file().sync_all()?;
+    file.persist(cache_path)?;
     Ok(())
 }
 
+pub fn update_file_cache<T: CacheSchema>(cache_path: &Path, bytes: &Bytes) -> Result<()> {
+    let contents = encode_cache_entry::<T>(bytes.as_ref());
+    write_file_atomically(cache_path, &contents)
+}
+
 pub fn update_metadata_file_cache(cache_path: &Path, headers: &HeaderMap) -> Result<()> {
-    #[cfg(not(test))]
-    let mut file = File::create(cache_path)?;
-    #[cfg(test)]
-    let mut file = tempfile()?;
-
-    let etag = headers
-        .get("etag")
-        .map(|val| val.to_str().unwrap_or("").to_string());
-    let date = headers
-        .get("date")
-        .map(|val| val.to_str().unwrap_or("").parse().unwrap_or(Utc::now()));
-    let metadata = Metadata { etag, date };
-    serde_json::to_writer(file, &metadata)?;
+    let metadata = Metadata::from_headers(headers);
+    let contents = serde_json::to_vec(&metadata)?;
+    write_file_atomically(cache_path, &contents)
+}
+
+/// Replaces a cached body and its metadata as a pair. The old metadata is removed before the body
+/// is replaced, so a crash part way through leaves a body with no ETag (which just gets fetched
+/// again) rather than a body paired with another body's ETag.
+pub fn commit_file_caches<T: CacheSchema>(
+    body_cache_path: &Path,
+    metadata_cache_path: &Path,
+    bytes: &Bytes,
+    headers: &HeaderMap,
+) -> Result<()> {
+    let _lock = FILE_CACHE_WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match remove_file(metadata_cache_path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(err).context("Failed to remove stale metadata file cache")
+        }
+        _ => {}
+    }
+    update_file_cache::<T>(body_cache_path, bytes).context("Failed to update body file cache")?;
+    update_metadata_file_cache(metadata_cache_path, headers)
+        .context("Failed to update metadata file cache")?;
+    Ok(())
+}
+
+pub fn remove_file_caches(body_cache_path: &Path, metadata_cache_path: &Path) -> Result<()> {
+    let _lock = FILE_CACHE_WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for cache_path in &[body_cache_path, metadata_cache_path] {
+        match remove_file(cache_path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("Failed to remove {:?}", cache_path))
+            }
+            _ => {}
+        }
+    }
     Ok(())
 }
 
-pub fn update_file_caches(
+/// Writes a response to the file caches before returning, so that responses are committed in the
+/// order they were received and an older one can't overwrite a newer one. A failed write is only
+/// logged since the response itself is fine.
+pub fn update_file_caches<T: CacheSchema>(
     body_cache_path: PathBuf,
     metadata_cache_path: PathBuf,
     bytes: Bytes,
     headers: HeaderMap,
 ) {
-    thread::spawn(move || {
-        update_file_cache(&body_cache_path, &bytes)
+    commit_file_caches::<T>(&body_cache_path, &metadata_cache_path, &bytes, &headers)
+        .map_err(|err| {
+            error!("{:#}", err);
+        })
+        .ok();
+}
+
+/// Removes every cached body and metadata file in `cache_dir` whose name starts with `prefix`.
+fn remove_prefixed_file_caches(cache_dir: &Path, prefix: &str) -> Result<()> {
+    let entries = match read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {:?}", cache_dir)),
+    };
+    let _lock = FILE_CACHE_WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for entry in entries {
+        let cache_path = entry?.path();
+        let has_prefix = cache_path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(prefix));
+        if has_prefix {
+            match remove_file(&cache_path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(err).with_context(|| format!("Failed to remove {:?}", cache_path))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+impl BazaarClient {
+    /// Forgets a cached value, both in memory and on disk, so it is fetched from the server again.
+    /// This runs once the server has already deleted the value, so a failure is only logged.
+    pub(crate) fn remove_from_caches(&self, body_cache_path: &Path, metadata_cache_path: &Path) {
+        self.memory_cache.remove(body_cache_path);
+        remove_file_caches(body_cache_path, metadata_cache_path)
             .map_err(|err| {
-                error!("Failed to update body file cache: {}", err);
+                error!("{:#}", err);
             })
             .ok();
-        update_metadata_file_cache(&metadata_cache_path, &headers)
+    }
+
+    /// Removes every cached value whose name starts with `prefix`, like all the pages of a
+    /// paginated list. A failure is only logged, like in `remove_from_caches`.
+    pub(crate) fn remove_prefixed_from_caches(&self, prefix: &str) {
+        self.memory_cache.remove_where(|cache_path: &Path| {
+            cache_path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(prefix))
+        });
+        remove_prefixed_file_caches(&self.cache_dir, prefix)
             .map_err(|err| {
-                error!("Failed to update metadata file cache: {}", err);
+                error!("{:#}", err);
             })
             .ok();
-    });
+    }
 }
 
-pub fn from_file_cache<T: for<'de> Deserialize<'de>>(cache_path: &Path) -> Result<T> {
-    #[cfg(not(test))]
-    let file = File::open(cache_path).with_context(|| {
-        format!(
-            "Object not found in API or in cache: {}",
-            cache_path.file_name().unwrap_or_default().to_string_lossy()
-        )
+pub fn from_file_cache<T>(cache_path: &Path) -> Result<T>
+where
+    T: for<'de> Deserialize<'de> + CacheSchema,
+{
+    let file_name = cache_path.file_name().unwrap_or_default().to_string_lossy();
+    let contents = read(cache_path).with_context(|| CacheMissError {
+        file_name: file_name.to_string(),
     })?;
-    #[cfg(test)]
-    let file = tempfile()?; // cache always reads from an empty temp file in cfg(test)
 
-    let reader = BufReader::new(file);
+    // an empty file is what an interrupted write left behind before writes were atomic
+    if contents.is_empty() {
+        return Err(anyhow!(CacheMissError {
+            file_name: file_name.to_string(),
+        }));
+    }
+
     info!("returning value from cache: {:?}", cache_path);
-    Ok(bincode::deserialize_from(reader).with_context(|| {
-        format!(
-            "Object not found in API or in cache: {}",
-            cache_path.file_name().unwrap_or_default().to_string_lossy(),
-        )
-    })?)
+    decode_cache_entry::<T>(&contents)
+        .and_then(|payload| {
+            bincode::deserialize(payload).map_err(|err| format!("failed to deserialize: {}", err))
+        })
+        .map_err(|reason| {
+            let err = CacheCorruptError {
+                file_name: file_name.to_string(),
+                reason,
+            };
+            error!("{}", err);
+            quarantine_file_cache(cache_path);
+            anyhow!(err)
+        })
 }
 
 pub fn load_metadata_from_file_cache(cache_path: &Path) -> Result<Metadata> {
-    #[cfg(not(test))]
     let file = File::open(cache_path).with_context(|| {
         format!(
             "Object not found in API or in cache: {}",
             cache_path.file_name().unwrap_or_default().to_string_lossy()
         )
     })?;
-    #[cfg(test)]
-    let file = tempfile()?; // cache always reads from an empty temp file in cfg(test)
 
     let reader = BufReader::new(file);
     info!("returning value from cache: {:?}", cache_path);
@@ -123,3 +536,195 @@ pub fn load_metadata_from_file_cache(cache_path: &Path) -> Result<Metadata> {
     })?;
     Ok(metadata)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        let metadata = Metadata {
+            etag: Some("etag".to_string()),
+            date: None,
+        };
+        cache.insert(Path::new("a.bin"), metadata.clone(), 1);
+        cache.insert(Path::new("b.bin"), metadata.clone(), 2);
+        assert_eq!(
+            cache.get::<i32>(Path::new("a.bin")).map(|(_, v)| v),
+            Some(1)
+        );
+        cache.insert(Path::new("c.bin"), metadata, 3);
+
+        assert!(cache.get::<i32>(Path::new("b.bin")).is_none());
+        assert_eq!(
+            cache.get::<i32>(Path::new("a.bin")).map(|(_, v)| v),
+            Some(1)
+        );
+        let (metadata, value) = cache.get::<i32>(Path::new("c.bin")).unwrap();
+        assert_eq!(value, 3);
+        assert_eq!(metadata.etag, Some("etag".to_string()));
+        assert_eq!(
+            cache.stats(),
+            MemoryCacheStats {
+                hits: 3,
+                misses: 1,
+                len: 2,
+                capacity: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_memory_cache_stats_count_lookups() {
+        let cache = MemoryCache::new(2);
+        let metadata = Metadata {
+            etag: Some("etag".to_string()),
+            date: None,
+        };
+        cache.insert(Path::new("a.bin"), metadata, 1);
+        // a conditional GET answered with 304 reads the metadata and then the value
+        cache
+            .load_metadata(Path::new("a.bin"), Path::new("a_metadata.json"))
+            .unwrap();
+        assert!(cache.get::<i32>(Path::new("a.bin")).is_some());
+        assert!(cache.get::<String>(Path::new("a.bin")).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+
+    #[test]
+    fn test_metadata_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("etag", "\"abc\"".parse().unwrap());
+        headers.insert("date", "Sun, 13 Sep 2020 12:26:40 GMT".parse().unwrap());
+        let metadata = Metadata::from_headers(&headers);
+        assert_eq!(metadata.etag, Some("\"abc\"".to_string()));
+        assert_eq!(
+            metadata.date.map(|date| date.timestamp()),
+            Some(1_600_000_000)
+        );
+    }
+
+    #[test]
+    fn test_file_cache_dir() {
+        let cache_dir = file_cache_dir(Path::new("cache"), "https://example.com");
+        assert_eq!(
+            cache_dir,
+            Path::new("cache")
+                .join("aHR0cHM6Ly9leGFtcGxlLmNvbQ")
+                .join("v1")
+        );
+    }
+
+    #[test]
+    fn test_write_file_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("shop_1.bin");
+        write_file_atomically(&cache_path, b"first").unwrap();
+        write_file_atomically(&cache_path, b"second").unwrap();
+
+        assert_eq!(std::fs::read(&cache_path).unwrap(), b"second");
+        // the temp file was renamed into place rather than left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_update_file_caches_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let body_cache_path = dir.path().join("shop_1.bin");
+        let metadata_cache_path = dir.path().join("shop_1_metadata.json");
+        for (etag, body) in &[("\"old\"", "old"), ("\"new\"", "new")] {
+            let mut headers = HeaderMap::new();
+            headers.insert("etag", etag.parse().unwrap());
+            update_file_caches::<Example>(
+                body_cache_path.clone(),
+                metadata_cache_path.clone(),
+                Bytes::from_static(body.as_bytes()),
+                headers,
+            );
+        }
+
+        let contents = std::fs::read(&body_cache_path).unwrap();
+        assert_eq!(decode_cache_entry::<Example>(&contents), Ok(&b"new"[..]));
+        assert_eq!(
+            load_metadata_from_file_cache(&metadata_cache_path)
+                .unwrap()
+                .etag,
+            Some("\"new\"".to_string())
+        );
+    }
+
+    struct Example;
+
+    impl CacheSchema for Example {
+        const SCHEMA_VERSION: u32 = 2;
+    }
+
+    impl CacheSchema for () {
+        const SCHEMA_VERSION: u32 = 1;
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_decode_cache_entry() {
+        let contents = encode_cache_entry::<Example>(b"payload");
+        assert_eq!(
+            decode_cache_entry::<Example>(&contents),
+            Ok(&b"payload"[..])
+        );
+
+        let mut flipped = contents.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decode_cache_entry::<Example>(&flipped),
+            Err("checksum mismatch".to_string())
+        );
+
+        assert_eq!(
+            decode_cache_entry::<Example>(&contents[..contents.len() - 1]),
+            Err("payload is 6 bytes but header expects 7".to_string())
+        );
+
+        assert_eq!(
+            decode_cache_entry::<Vec<Example>>(b"payload"),
+            Err("missing cache header".to_string())
+        );
+
+        let old_schema = encode_cache_entry::<()>(b"payload");
+        assert_eq!(
+            decode_cache_entry::<Example>(&old_schema),
+            Err("schema version 1 does not match expected version 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quarantine_file_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("shop_1.bin");
+        std::fs::write(&cache_path, b"garbage").unwrap();
+        quarantine_file_cache(&cache_path);
+
+        assert!(!cache_path.exists());
+        assert_eq!(
+            std::fs::read(dir.path().join("shop_1.bin.corrupt")).unwrap(),
+            b"garbage"
+        );
+    }
+
+    #[test]
+    fn test_memory_cache_zero_capacity() {
+        let cache = MemoryCache::new(0);
+        let metadata = Metadata {
+            etag: None,
+            date: None,
+        };
+        cache.insert(Path::new("a.bin"), metadata, 1);
+        assert!(cache.get::<i32>(Path::new("a.bin")).is_none());
+        assert_eq!(cache.stats().misses, 1);
+    }
+}
@@ -1,8 +1,16 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char, path::Path};
+use std::{
+    ffi::CString,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    ptr::null,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::anyhow;
 use log::LevelFilter;
-use reqwest::{blocking::Response, Url};
+use reqwest::{blocking::RequestBuilder, Url};
+use serde::de::DeserializeOwned;
 use uuid::Uuid;
 
 #[cfg(not(test))]
@@ -11,72 +19,522 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    error::extract_error_from_response,
+    cache::{
+        default_cache_root, file_cache_dir, set_default_cache_root, update_file_caches,
+        CacheSchema, MemoryCache, MemoryCacheStats, Metadata,
+    },
+    error::{extract_error_from_response, Error, InvalidArgumentError, Result},
+    idempotency::IdempotencyKeys,
     log_server_error,
+    logging::{
+        api_key_fingerprint, default_log_path, init_logging, register_api_key, LogConfig, LogLevel,
+    },
+    optional_str_from_raw,
+    outbox::Outbox,
+    panic::{catch_panic, install_panic_hook},
+    request::RequestQueue,
     result::{FFIError, FFIResult},
+    retry::RetryPolicy,
+    str_from_raw,
+    transport::{ReqwestTransport, Transport},
 };
 
-#[no_mangle]
-pub extern "C" fn init() -> bool {
-    match dirs::document_dir() {
-        Some(mut log_dir) => {
-            log_dir.push(Path::new(
-                r#"My Games\Skyrim Special Edition\SKSE\BazaarRealmClient.log"#,
-            ));
-            match simple_logging::log_to_file(log_dir, LevelFilter::Info) {
-                Ok(_) => true,
-                Err(_) => false,
-            }
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ClientOptions {
+    /// Timeout in milliseconds for each request made by the client, or 0 to never time out.
+    pub timeout_ms: u64,
+    /// Number of background threads that run requests made with the `client_*_async` functions.
+    pub worker_threads: u32,
+    /// Total number of times a request that fails in a way that might be temporary is sent, or 1 to
+    /// never retry. Only requests that are safe to repeat are retried.
+    pub retry_max_attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled on each retry after that.
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    /// Randomize retry delays so that many clients don't retry at the same moment.
+    pub retry_jitter: bool,
+    /// Maximum number of API responses kept deserialized in memory in front of the file cache, or 0
+    /// to always read from the file cache.
+    pub memory_cache_capacity: u32,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 30_000,
+            worker_threads: 2,
+            retry_max_attempts: 3,
+            retry_base_delay_ms: 250,
+            retry_max_delay_ms: 5_000,
+            retry_jitter: true,
+            memory_cache_capacity: 64,
         }
-        None => false,
     }
 }
 
-#[no_mangle]
-pub extern "C" fn status_check(api_url: *const c_char) -> FFIResult<bool> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    info!("status_check api_url: {:?}", api_url);
+/// Long-lived handle to the API that is created once by the plugin with `client_new` and passed to
+/// every `client_*` endpoint function. It owns a pooled HTTP client so connections are reused
+/// between requests.
+///
+/// Cloning is cheap and shares the transport and request queue, which is how requests are handed
+/// off to worker threads.
+#[derive(Debug, Clone)]
+pub struct BazaarClient {
+    pub api_url: String,
+    pub(crate) api_key: String,
+    /// Only builds requests. They are sent with `transport`.
+    pub(crate) http: reqwest::blocking::Client,
+    pub(crate) transport: Arc<dyn Transport>,
+    /// Directory the file cache of this client's API is kept in.
+    pub cache_dir: PathBuf,
+    pub options: ClientOptions,
+    pub retry_policy: RetryPolicy,
+    pub(crate) requests: Arc<RequestQueue>,
+    pub(crate) memory_cache: Arc<MemoryCache>,
+    pub(crate) outbox: Arc<Outbox>,
+    pub(crate) idempotency_keys: Arc<IdempotencyKeys>,
+}
+
+impl BazaarClient {
+    pub fn new(api_url: &str, api_key: &str, options: ClientOptions) -> Result<Self> {
+        Self::with_cache_root(api_url, api_key, options, &default_cache_root())
+    }
 
-    fn inner(api_url: &str) -> Result<()> {
-        #[cfg(not(test))]
-        let api_url = Url::parse(api_url)?.join("v1/status")?;
-        #[cfg(test)]
-        let api_url = Url::parse(&mockito::server_url())?.join("v1/status")?;
+    /// Like `new`, but keeps the client's cache under `cache_root` instead of the default root.
+    pub fn with_cache_root(
+        api_url: &str,
+        api_key: &str,
+        options: ClientOptions,
+        cache_root: &Path,
+    ) -> Result<Self> {
+        let mut builder = reqwest::blocking::Client::builder();
+        if options.timeout_ms > 0 {
+            builder = builder.timeout(Duration::from_millis(options.timeout_ms));
+        }
+        let http = builder.build()?;
+        let transport = Arc::new(ReqwestTransport::new(http.clone()));
+        Ok(Self::from_parts(
+            api_url, api_key, options, cache_root, http, transport,
+        ))
+    }
 
-        let resp = reqwest::blocking::get(api_url)?;
+    /// Like `with_cache_root`, but sends every request with `transport` instead of over HTTP. Pass
+    /// a `FakeApi` to run the client without a server.
+    pub fn with_transport(
+        api_url: &str,
+        api_key: &str,
+        options: ClientOptions,
+        cache_root: &Path,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self> {
+        let http = reqwest::blocking::Client::builder().build()?;
+        Ok(Self::from_parts(
+            api_url, api_key, options, cache_root, http, transport,
+        ))
+    }
+
+    fn from_parts(
+        api_url: &str,
+        api_key: &str,
+        options: ClientOptions,
+        cache_root: &Path,
+        http: reqwest::blocking::Client,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        let cache_dir = file_cache_dir(cache_root, api_url);
+        register_api_key(api_key);
+        Self {
+            api_url: api_url.to_string(),
+            api_key: api_key.to_string(),
+            http,
+            transport,
+            outbox: Arc::new(Outbox::new(&cache_dir)),
+            idempotency_keys: Arc::new(IdempotencyKeys::new(&cache_dir)),
+            cache_dir,
+            options,
+            retry_policy: RetryPolicy::from(&options),
+            requests: Arc::new(RequestQueue::new(options.worker_threads as usize)),
+            memory_cache: Arc::new(MemoryCache::new(options.memory_cache_capacity as usize)),
+        }
+    }
+
+    pub(crate) fn url(&self, path: &str) -> Result<Url> {
+        Url::parse(&self.api_url)
+            .and_then(|url| url.join(path))
+            .map_err(|err| Error::InvalidUrl(err.to_string()))
+    }
+
+    /// ETag of the server copy that is cached, `None` if nothing is or it is an offline copy.
+    pub(crate) fn cached_etag(
+        &self,
+        body_cache_path: &Path,
+        metadata_cache_path: &Path,
+    ) -> Option<String> {
+        self.memory_cache
+            .load_metadata(body_cache_path, metadata_cache_path)
+            .ok()
+            .and_then(|metadata| metadata.etag)
+    }
+
+    /// Only overwrite the server's copy if it is still the one the update was based on: the copy
+    /// with `base_etag` for an update that was queued, otherwise the cached one. Without either
+    /// ETag the update is sent unconditionally.
+    pub(crate) fn if_match(
+        &self,
+        request: RequestBuilder,
+        base_etag: Option<&str>,
+        body_cache_path: &Path,
+        metadata_cache_path: &Path,
+    ) -> RequestBuilder {
+        match base_etag
+            .map(str::to_string)
+            .or_else(|| self.cached_etag(body_cache_path, metadata_cache_path))
+        {
+            Some(etag) => request.header("If-Match", etag),
+            None => request,
+        }
+    }
+
+    /// Fetches the server's copy of something an update conflicted with and caches it, so that the
+    /// update can be merged into it and sent again. `None` if it can't be fetched.
+    pub(crate) fn fetch_server_copy<T>(
+        &self,
+        path: &str,
+        body_cache_path: PathBuf,
+        metadata_cache_path: PathBuf,
+    ) -> Option<T>
+    where
+        T: DeserializeOwned + CacheSchema + Clone + Send + Sync + 'static,
+    {
+        let request = self
+            .http
+            .get(self.url(path).ok()?)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        let resp = match self.send(request) {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                log_server_error(resp);
+                return None;
+            }
+            Err(err) => {
+                error!("fetching server copy of {} failed. {}", path, err);
+                return None;
+            }
+        };
+        let headers = resp.headers().clone();
+        let bytes = resp.into_body();
+        let value: T = bincode::deserialize(&bytes).ok()?;
+        self.memory_cache.insert(
+            &body_cache_path,
+            Metadata::from_headers(&headers),
+            value.clone(),
+        );
+        update_file_caches::<T>(body_cache_path, metadata_cache_path, bytes, headers);
+        Some(value)
+    }
+
+    pub fn status_check(&self) -> Result<()> {
+        let resp = self.send(self.http.get(self.url("v1/status")?))?;
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             Ok(())
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
+}
 
-    match inner(&api_url) {
-        Ok(()) => {
-            info!("status_check ok");
-            FFIResult::Ok(true)
-        }
+pub fn client_from_ptr<'a>(client: *const BazaarClient) -> Result<&'a BazaarClient> {
+    unsafe { client.as_ref() }
+        .ok_or_else(|| Error::InvalidArgument(InvalidArgumentError::new("client", "is null")))
+}
+
+// Clients of the endpoint functions that take `api_url` and `api_key` on every call, one per pair,
+// so their memory cache, worker pool and outbox outcomes outlive a single call.
+static LEGACY_CLIENTS: Mutex<Vec<Arc<BazaarClient>>> = Mutex::new(Vec::new());
+
+/// Options of the clients shared by the endpoint functions that take `api_url` and `api_key`.
+/// Those functions block the calling thread, so a request is sent once and not given as long as
+/// a `client_new` client would give it.
+pub fn legacy_client_options() -> ClientOptions {
+    ClientOptions {
+        timeout_ms: 10_000,
+        retry_max_attempts: 1,
+        ..ClientOptions::default()
+    }
+}
+
+/// The client shared by every endpoint function called with `api_url` and `api_key`, created on
+/// first use.
+pub(crate) fn legacy_client(api_url: &str, api_key: &str) -> Result<Arc<BazaarClient>> {
+    let mut clients = LEGACY_CLIENTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(client) = clients
+        .iter()
+        .find(|client| client.api_url == api_url && client.api_key == api_key)
+    {
+        return Ok(client.clone());
+    }
+    let client = Arc::new(BazaarClient::new(
+        api_url,
+        api_key,
+        legacy_client_options(),
+    )?);
+    clients.push(client.clone());
+    Ok(client)
+}
+
+/// Checks the server at `api_url` is up, then replays the outbox with the shared client of a key
+/// that has been used with `api_url`. Changes queued in an earlier session are only replayed once
+/// a call with a key has been made in this one.
+fn legacy_status_check_and_replay_outbox(api_url: &str) -> Result<()> {
+    let keyed_client = LEGACY_CLIENTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .find(|client| client.api_url == api_url && !client.api_key.is_empty())
+        .cloned();
+    match keyed_client {
+        Some(client) => client.status_check_and_replay_outbox(),
+        None => legacy_client(api_url, "")?.status_check(),
+    }
+}
+
+/// Runs the matching `client_*` function with the shared client of `api_url` and `api_key` for the
+/// endpoint functions that take them on every call.
+pub fn with_legacy_client<T>(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    f: impl FnOnce(*const BazaarClient) -> FFIResult<T>,
+) -> FFIResult<T> {
+    let client = str_from_raw(api_url, "api_url").and_then(|api_url| {
+        let api_key = str_from_raw(api_key, "api_key")?;
+        legacy_client(&api_url, &api_key)
+    });
+    match client {
+        Ok(client) => f(&*client),
         Err(err) => {
-            error!("status_check failed. {}", err);
+            error!("failed to create client. {}", err);
             FFIResult::Err(FFIError::from(err))
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct LogOptions {
+    /// Path of the log file, or null for `Documents\My Games\Skyrim Special Edition\SKSE\BazaarRealmClient.log`.
+    pub path: *const c_char,
+    pub level: LogLevel,
+    /// Size in bytes at which the log file is rotated, or 0 to let it grow forever.
+    pub max_size_bytes: u64,
+    /// Number of rotated log files kept next to it as `<path>.1`, `<path>.2` and so on.
+    pub max_files: u32,
+}
+
+impl LogOptions {
+    fn into_config(self) -> Result<LogConfig> {
+        let path = match optional_str_from_raw(self.path) {
+            Some(path) => PathBuf::from(path),
+            None => default_log_path().ok_or_else(|| anyhow!("Could not find documents folder"))?,
+        };
+        Ok(LogConfig {
+            path,
+            level: LevelFilter::from(self.level),
+            max_size_bytes: self.max_size_bytes,
+            max_files: self.max_files,
+        })
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn init() -> bool {
+    install_panic_hook();
+    catch_panic(|| match default_log_path() {
+        Some(log_path) => init_logging(LogConfig::with_path(log_path)).is_ok(),
+        None => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn init_with_options(options: LogOptions) -> FFIResult<bool> {
+    install_panic_hook();
+    catch_panic(|| {
+        match options
+            .into_config()
+            .and_then(|config| init_logging(config).map_err(Error::from))
+        {
+            Ok(()) => {
+                info!("init_with_options options: {:?}", options);
+                FFIResult::Ok(true)
+            }
+            Err(err) => FFIResult::Err(FFIError::from(err)),
+        }
+    })
+}
+
+/// Sets the directory that clients created after this keep their file cache in, or restores the
+/// default `Data\SKSE\Plugins\BazaarRealmCache` if `cache_root` is null.
+#[no_mangle]
+pub extern "C" fn set_cache_root(cache_root: *const c_char) -> FFIResult<bool> {
+    catch_panic(|| {
+        let cache_root = optional_str_from_raw(cache_root).map(PathBuf::from);
+        info!("set_cache_root cache_root: {:?}", cache_root);
+        set_default_cache_root(cache_root);
+        FFIResult::Ok(true)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_new(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    options: ClientOptions,
+) -> FFIResult<*mut BazaarClient> {
+    client_new_with_cache_root(api_url, api_key, null(), options)
+}
+
+/// Like `client_new`, but keeps this client's file cache under `cache_root`. A null `cache_root`
+/// uses the one set with `set_cache_root`.
+#[no_mangle]
+pub extern "C" fn client_new_with_cache_root(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    cache_root: *const c_char,
+    options: ClientOptions,
+) -> FFIResult<*mut BazaarClient> {
+    install_panic_hook();
+    catch_panic(|| {
+        let api_url = optional_str_from_raw(api_url);
+        let api_key = optional_str_from_raw(api_key);
+        let cache_root = optional_str_from_raw(cache_root)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_cache_root);
+        info!(
+            "client_new api_url: {:?}, api_key: {}, cache_root: {:?}, options: {:?}",
+            api_url,
+            api_key
+                .as_deref()
+                .map_or_else(|| "null".to_string(), api_key_fingerprint),
+            cache_root,
+            options
+        );
+
+        let client = match (api_url, api_key) {
+            (None, _) => Err(Error::InvalidArgument(InvalidArgumentError::new(
+                "api_url", "is null",
+            ))),
+            (_, None) => Err(Error::InvalidArgument(InvalidArgumentError::new(
+                "api_key", "is null",
+            ))),
+            (Some(api_url), Some(api_key)) => {
+                BazaarClient::with_cache_root(&api_url, &api_key, options, &cache_root)
+            }
+        };
+        match client {
+            Ok(client) => FFIResult::Ok(Box::into_raw(Box::new(client))),
+            Err(err) => {
+                error!("client_new failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn client_free(client: *mut BazaarClient) {
+    catch_panic(|| {
+        if !client.is_null() {
+            drop(Box::from_raw(client));
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_status_check(client: *const BazaarClient) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!("client_status_check");
+        match client_from_ptr(client).and_then(|client| client.status_check_and_replay_outbox()) {
+            Ok(()) => {
+                info!("status_check ok");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("status_check failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn status_check(api_url: *const c_char) -> FFIResult<bool> {
+    catch_panic(|| {
+        let api_url = str_from_raw(api_url, "api_url");
+        info!("status_check api_url: {:?}", api_url);
+
+        match api_url.and_then(|api_url| legacy_status_check_and_replay_outbox(&api_url)) {
+            Ok(()) => {
+                info!("status_check ok");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("status_check failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_memory_cache_stats(client: *const BazaarClient) -> MemoryCacheStats {
+    catch_panic(|| match client_from_ptr(client) {
+        Ok(client) => client.memory_cache.stats(),
+        Err(err) => {
+            error!("memory_cache_stats failed. {}", err);
+            MemoryCacheStats::default()
+        }
+    })
+}
+
+/// Returns a new random API key. Free it with `free_string`.
 #[no_mangle]
 pub unsafe extern "C" fn generate_api_key() -> *mut c_char {
-    // TODO: is leaking this CString bad?
-    let uuid = CString::new(format!("{}", Uuid::new_v4()))
-        .expect("could not create CString")
-        .into_raw();
-    info!("generate_api_key successful");
-    uuid
+    catch_panic(|| {
+        let uuid = CString::new(format!("{}", Uuid::new_v4()))
+            .expect("could not create CString")
+            .into_raw();
+        info!("generate_api_key successful");
+        uuid
+    })
+}
+
+/// Client for tests that talks to the mockito server and keeps its cache in a fresh temporary
+/// directory, which is deleted when the returned `TempDir` is dropped.
+#[cfg(test)]
+pub(crate) fn test_client() -> (tempfile::TempDir, BazaarClient) {
+    test_client_with_options(&mockito::server_url(), ClientOptions::default())
+}
+
+#[cfg(test)]
+pub(crate) fn test_client_with_options(
+    api_url: &str,
+    options: ClientOptions,
+) -> (tempfile::TempDir, BazaarClient) {
+    let cache_root = tempfile::tempdir().unwrap();
+    let client =
+        BazaarClient::with_cache_root(api_url, "api-key", options, cache_root.path()).unwrap();
+    (cache_root, client)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ffi::CStr;
+
     use super::*;
     use mockito::mock;
 
@@ -84,24 +542,14 @@ mod tests {
     fn test_status_check() {
         let mock = mock("GET", "/v1/status").with_status(200).create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
+        let api_url = CString::new(mockito::server_url()).unwrap().into_raw();
         let result = status_check(api_url);
         mock.assert();
         match result {
             FFIResult::Ok(success) => {
-                assert_eq!(success, true);
+                assert!(success);
             }
-            FFIResult::Err(error) => panic!(
-                "status_check returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("status_check returned error: {}", error),
         }
     }
 
@@ -112,7 +560,7 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
+        let api_url = CString::new(mockito::server_url()).unwrap().into_raw();
         let result = status_check(api_url);
         mock.assert();
         match result {
@@ -129,4 +577,123 @@ mod tests {
             },
         }
     }
+
+    #[test]
+    fn test_client_status_check() {
+        let mock = mock("GET", "/v1/status")
+            .with_status(200)
+            .expect(2)
+            .create();
+
+        let api_url = CString::new(mockito::server_url()).unwrap().into_raw();
+        let api_key = CString::new("api-key").unwrap().into_raw();
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache_root_ptr = CString::new(cache_root.path().to_string_lossy().to_string())
+            .unwrap()
+            .into_raw();
+        let client = match client_new_with_cache_root(
+            api_url,
+            api_key,
+            cache_root_ptr,
+            ClientOptions::default(),
+        ) {
+            FFIResult::Ok(client) => client,
+            FFIResult::Err(error) => panic!("client_new returned error: {:?}", error),
+        };
+        let first_result = client_status_check(client);
+        let second_result = client_status_check(client);
+        unsafe { client_free(client) };
+        mock.assert();
+        assert_eq!(first_result, FFIResult::Ok(true));
+        assert_eq!(second_result, FFIResult::Ok(true));
+    }
+
+    #[test]
+    fn test_client_status_check_null_client() {
+        match client_status_check(std::ptr::null()) {
+            FFIResult::Ok(success) => panic!("status_check returned Ok result: {:?}", success),
+            FFIResult::Err(error) => match error {
+                FFIError::InvalidArgument { arg_name, reason } => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                        "client"
+                    );
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                        "is null"
+                    );
+                }
+                _ => panic!("status_check did not return an invalid argument error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_client_new_null_api_key() {
+        let api_url = CString::new(mockito::server_url()).unwrap().into_raw();
+        let result = client_new(api_url, std::ptr::null(), ClientOptions::default());
+        match result {
+            FFIResult::Err(FFIError::InvalidArgument { arg_name, reason }) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                    "api_key"
+                );
+                assert_eq!(
+                    unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                    "is null"
+                );
+            }
+            _ => panic!("client_new did not return an invalid argument error"),
+        }
+    }
+
+    #[test]
+    fn test_legacy_client_is_shared() {
+        let api_url = "http://legacy-client.test/";
+        let client = legacy_client(api_url, "legacy-key-1").unwrap();
+        assert!(Arc::ptr_eq(
+            &client,
+            &legacy_client(api_url, "legacy-key-1").unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &client,
+            &legacy_client(api_url, "legacy-key-2").unwrap()
+        ));
+        assert_eq!(client.options.retry_max_attempts, 1);
+    }
+
+    #[test]
+    fn test_legacy_status_check_replays_outbox() {
+        use crate::{fake_api::FakeApi, shop::Shop};
+
+        let api_url = "http://legacy-replay.test/";
+        let fake = Arc::new(FakeApi::new());
+        let cache_root = tempfile::tempdir().unwrap();
+        let client = Arc::new(
+            BazaarClient::with_transport(
+                api_url,
+                "legacy-replay-key",
+                legacy_client_options(),
+                cache_root.path(),
+                fake.clone(),
+            )
+            .unwrap(),
+        );
+        LEGACY_CLIENTS.lock().unwrap().push(client.clone());
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        fake.set_offline(true);
+        client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline", "description"))
+            .unwrap();
+        assert_eq!(client.outbox.len().unwrap(), 1);
+
+        fake.set_offline(false);
+        let api_url = CString::new(api_url).unwrap().into_raw();
+        match status_check(api_url) {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!("status_check returned error: {}", error),
+        }
+        assert!(client.outbox.is_empty());
+    }
 }
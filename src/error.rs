@@ -1,16 +1,23 @@
+use std::error::Error as StdError;
 use std::fmt;
+use std::io;
 use std::str;
+use std::str::FromStr;
 
-use anyhow::{anyhow, Error};
 use bytes::Bytes;
 use http_api_problem::HttpApiProblem;
-use reqwest::StatusCode;
+use reqwest::{StatusCode, Url};
 
 #[cfg(not(test))]
 use log::error;
 #[cfg(test)]
 use std::println as error;
 
+use crate::{
+    interior_ref_list::SavedInteriorRefList, merchandise_list::SavedMerchandiseList,
+    owner::SavedOwner, shop::SavedShop,
+};
+
 #[derive(Debug)]
 pub struct ServerError {
     pub status: StatusCode,
@@ -34,28 +41,346 @@ impl fmt::Display for ServerError {
     }
 }
 
-pub fn extract_error_from_response(status: StatusCode, bytes: &Bytes) -> Error {
-    match serde_json::from_slice::<HttpApiProblem>(bytes) {
-        Ok(api_problem) => {
-            let server_error = ServerError {
-                status,
-                title: api_problem.title,
-                detail: api_problem.detail,
-            };
-            error!("{}", server_error);
-            anyhow!(server_error)
-        }
+impl StdError for ServerError {}
+
+pub fn extract_server_error(status: StatusCode, bytes: &Bytes) -> ServerError {
+    let server_error = match serde_json::from_slice::<HttpApiProblem>(bytes) {
+        Ok(api_problem) => ServerError {
+            status,
+            title: api_problem.title,
+            detail: api_problem.detail,
+        },
         Err(_) => {
             let title = str::from_utf8(bytes)
-                .unwrap_or_else(|_| &status.canonical_reason().unwrap_or("unknown"))
+                .unwrap_or_else(|_| status.canonical_reason().unwrap_or("unknown"))
                 .to_string();
-            let server_error = ServerError {
+            ServerError {
                 status,
                 title,
                 detail: None,
-            };
-            error!("{}", server_error);
-            anyhow!(server_error)
+            }
+        }
+    };
+    error!("{}", server_error);
+    server_error
+}
+
+pub fn extract_error_from_response(status: StatusCode, bytes: &Bytes) -> Error {
+    Error::Server(extract_server_error(status, bytes))
+}
+
+/// The server's current copy of something an update conflicted with.
+#[derive(Debug, Clone)]
+pub enum ServerCopy {
+    Owner(SavedOwner),
+    Shop(SavedShop),
+    MerchandiseList(SavedMerchandiseList),
+    InteriorRefList(SavedInteriorRefList),
+}
+
+/// The server refused an update with 412 Precondition Failed because it changed since the client
+/// last fetched it.
+#[derive(Debug)]
+pub struct ConflictError {
+    pub server_error: ServerError,
+    /// What the update should be merged into, or `None` if it could not be fetched.
+    pub current: Option<ServerCopy>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Conflict with the server's copy: {}", self.server_error)
+    }
+}
+
+impl StdError for ConflictError {}
+
+/// Builds the error for a 412 response to an update, with the server copy fetched after it.
+pub fn conflict_from_response(
+    status: StatusCode,
+    bytes: &Bytes,
+    current: Option<ServerCopy>,
+) -> Error {
+    Error::Conflict(Box::new(ConflictError {
+        server_error: extract_server_error(status, bytes),
+        current,
+    }))
+}
+
+/// A file cache entry whose header, checksum or contents don't match what this client expects.
+#[derive(Debug)]
+pub struct CacheCorruptError {
+    pub file_name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for CacheCorruptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Cache entry {} is corrupt: {}",
+            self.file_name, self.reason
+        )
+    }
+}
+
+impl StdError for CacheCorruptError {}
+
+/// Neither the API nor the file cache could provide the requested object.
+#[derive(Debug)]
+pub struct CacheMissError {
+    pub file_name: String,
+}
+
+impl fmt::Display for CacheMissError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Object not found in API or in cache: {}", self.file_name)
+    }
+}
+
+impl StdError for CacheMissError {}
+
+/// An argument passed in from the plugin that can't be read, like a null pointer.
+#[derive(Debug)]
+pub struct InvalidArgumentError {
+    pub arg_name: String,
+    pub reason: String,
+}
+
+impl InvalidArgumentError {
+    pub fn new(arg_name: &str, reason: &str) -> Self {
+        Self {
+            arg_name: arg_name.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid argument {}: {}", self.arg_name, self.reason)
+    }
+}
+
+impl StdError for InvalidArgumentError {}
+
+/// Why one field of a value failed validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, reason: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.reason)
+    }
+}
+
+/// A value was rejected by the client before it was sent, with every field that is wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub field_errors: Vec<FieldError>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let field_errors: Vec<String> = self
+            .field_errors
+            .iter()
+            .map(|field_error| field_error.to_string())
+            .collect();
+        write!(f, "Validation failed: {}", field_errors.join(", "))
+    }
+}
+
+impl StdError for ValidationError {}
+
+/// Every way a `BazaarClient` call can fail. Each kind maps to one `FFIError` variant at the C
+/// boundary.
+#[derive(Debug)]
+pub enum Error {
+    /// An argument was null or otherwise unusable.
+    InvalidArgument(InvalidArgumentError),
+    /// An argument was readable but failed validation against the cache.
+    Validation(ValidationError),
+    /// The server responded with an error status.
+    Server(ServerError),
+    /// An update was based on an out of date copy.
+    Conflict(Box<ConflictError>),
+    Timeout(String),
+    /// The server could not be reached, or the connection dropped before it responded.
+    ConnectionFailed(String),
+    /// The request failed and there was no cached copy to fall back to.
+    CacheMiss(CacheMissError),
+    CacheCorrupt(CacheCorruptError),
+    /// A response or cache entry could not be decoded.
+    Deserialize(String),
+    InvalidUrl(String),
+    Io(io::Error),
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(error) => error.fmt(f),
+            Error::Validation(error) => error.fmt(f),
+            Error::Server(error) => error.fmt(f),
+            Error::Conflict(error) => error.fmt(f),
+            Error::CacheMiss(error) => error.fmt(f),
+            Error::CacheCorrupt(error) => error.fmt(f),
+            Error::Io(error) => error.fmt(f),
+            Error::Other(error) => error.fmt(f),
+            Error::Timeout(message)
+            | Error::ConnectionFailed(message)
+            | Error::Deserialize(message)
+            | Error::InvalidUrl(message) => f.write_str(message),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+type UrlParseError = <Url as FromStr>::Err;
+
+fn downcast<E>(error: anyhow::Error, kind: impl FnOnce(E) -> Error) -> Result<Error, anyhow::Error>
+where
+    E: fmt::Display + fmt::Debug + Send + Sync + 'static,
+{
+    error.downcast::<E>().map(kind)
+}
+
+fn from_reqwest_error(error: &reqwest::Error, message: String) -> Option<Error> {
+    if error.is_timeout() {
+        return Some(Error::Timeout(message));
+    }
+    if error.is_builder() {
+        return Some(Error::InvalidUrl(message));
+    }
+    // connect, DNS and reset errors all bottom out in an io::Error from the connection
+    let mut source = error.source();
+    while let Some(err) = source {
+        if err.is::<io::Error>() {
+            return Some(Error::ConnectionFailed(message));
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// Sorts an error from anywhere in the client into its kind, looking through any context added
+/// to it along the way.
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        downcast(error, |error: Error| error)
+            .or_else(|error| downcast(error, Error::InvalidArgument))
+            .or_else(|error| downcast(error, Error::Validation))
+            .or_else(|error| downcast(error, Error::Server))
+            .or_else(|error| downcast(error, Error::CacheCorrupt))
+            .or_else(|error| downcast(error, Error::CacheMiss))
+            .unwrap_or_else(|error| {
+                let message = error.to_string();
+                if let Some(reqwest_error) = error.downcast_ref::<reqwest::Error>() {
+                    if let Some(error) = from_reqwest_error(reqwest_error, message) {
+                        return error;
+                    }
+                } else if error.is::<bincode::Error>() {
+                    return Error::Deserialize(message);
+                } else if error.is::<UrlParseError>() {
+                    return Error::InvalidUrl(message);
+                }
+                downcast(error, Error::Io).unwrap_or_else(Error::Other)
+            })
+    }
+}
+
+macro_rules! impl_from_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::from(anyhow::Error::new(error))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_error!(reqwest::Error, bincode::Error, io::Error, serde_json::Error);
+
+impl From<InvalidArgumentError> for Error {
+    fn from(error: InvalidArgumentError) -> Self {
+        Error::InvalidArgument(error)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(error: ValidationError) -> Self {
+        Error::Validation(error)
+    }
+}
+
+impl From<ServerError> for Error {
+    fn from(error: ServerError) -> Self {
+        Error::Server(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn test_error_from_anyhow_error() {
+        let error = Error::from(anyhow!(CacheMissError {
+            file_name: "shop_1.bin".to_string(),
+        }));
+        match error {
+            Error::CacheMiss(error) => assert_eq!(error.file_name, "shop_1.bin"),
+            _ => panic!("did not return a cache miss error"),
+        }
+
+        let error = Error::from(
+            Err::<(), _>(io::Error::new(io::ErrorKind::NotFound, "missing"))
+                .context("Failed to read cache file")
+                .unwrap_err(),
+        );
+        match error {
+            Error::Io(error) => assert_eq!(error.kind(), io::ErrorKind::NotFound),
+            _ => panic!("did not return an io error"),
+        }
+
+        let error = Error::from(anyhow!(Error::Timeout("timed out".to_string())));
+        match error {
+            Error::Timeout(message) => assert_eq!(message, "timed out"),
+            _ => panic!("did not return a timeout error"),
+        }
+
+        match Error::from(anyhow!("something else")) {
+            Error::Other(error) => assert_eq!(error.to_string(), "something else"),
+            _ => panic!("did not return an other error"),
         }
     }
 }
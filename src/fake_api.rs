@@ -0,0 +1,902 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    os::raw::c_char,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use reqwest::{
+    blocking::Request,
+    header::{HeaderMap, HeaderValue},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+#[cfg(not(test))]
+use log::{error, info};
+#[cfg(test)]
+use std::{println as info, println as error};
+
+use crate::{
+    cache::{crc32, default_cache_root},
+    client::{BazaarClient, ClientOptions},
+    error::{Error, InvalidArgumentError, Result},
+    interior_ref_list::{InteriorRefList, SavedInteriorRefList},
+    logging::api_key_fingerprint,
+    merchandise_list::{MerchandiseList, SavedMerchandiseList},
+    optional_str_from_raw,
+    owner::{Owner, SavedOwner},
+    panic::{catch_panic, install_panic_hook},
+    result::{FFIError, FFIResult},
+    shop::{SavedShop, Shop},
+    transaction::{shop_gold_after, SavedTransaction, Transaction},
+    transport::{Response, Transport},
+};
+
+/// `api_url` of clients made with `client_new_with_fake_api`. It only namespaces their file cache.
+pub const FAKE_API_URL: &str = "http://fake-api.bazaarrealm.invalid/";
+
+/// Status and detail of an error response.
+type Problem = (StatusCode, String);
+type Reply = std::result::Result<(StatusCode, Vec<u8>), Problem>;
+
+/// In-memory stand-in for the BazaarRealmAPI server that answers every endpoint the client calls.
+/// Responses carry an `ETag` and a `Date` header and conditional GETs are answered with 304, so the
+/// client's caches behave the way they do against the real server.
+///
+/// Like the real server, creating an owner registers the request's `Api-Key`, and only that key
+/// can create or change the owner's shops and lists. A POST with an `Idempotency-Key` the same
+/// `Api-Key` already used is answered with the first response instead of being applied again.
+#[derive(Debug, Default)]
+pub struct FakeApi {
+    state: Mutex<ApiState>,
+    idempotent_replies: Mutex<HashMap<(String, String), Response>>,
+    offline: AtomicBool,
+    drop_responses: AtomicBool,
+    drop_next_response: AtomicBool,
+    time_out_responses: AtomicBool,
+}
+
+impl FakeApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// While offline every request fails as if the server could not be reached.
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::SeqCst);
+    }
+
+    /// While set, requests are applied but fail as if the connection dropped before the response
+    /// arrived.
+    pub fn set_drop_responses(&self, drop_responses: bool) {
+        self.drop_responses.store(drop_responses, Ordering::SeqCst);
+    }
+
+    /// Like `set_drop_responses`, but only for the next request.
+    pub fn drop_next_response(&self) {
+        self.drop_next_response.store(true, Ordering::SeqCst);
+    }
+
+    /// While set, requests are applied but time out before the response arrives.
+    pub fn set_time_out_responses(&self, time_out_responses: bool) {
+        self.time_out_responses
+            .store(time_out_responses, Ordering::SeqCst);
+    }
+}
+
+impl Transport for FakeApi {
+    fn execute(&self, request: Request) -> Result<Response> {
+        if self.offline.load(Ordering::SeqCst) {
+            return Err(Error::ConnectionFailed(format!(
+                "fake API is offline: {} {}",
+                request.method(),
+                request.url()
+            )));
+        }
+        let idempotency_key = match (
+            request.method().as_str(),
+            header(&request, "Idempotency-Key"),
+        ) {
+            ("POST", Some(key)) => Some((
+                header(&request, "Api-Key").unwrap_or_default().to_string(),
+                key.to_string(),
+            )),
+            _ => None,
+        };
+        let mut idempotent_replies = self
+            .idempotent_replies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let replayed = idempotency_key
+            .as_ref()
+            .and_then(|key| idempotent_replies.get(key))
+            .cloned();
+        let response = match replayed {
+            Some(response) => response,
+            None => {
+                let mut state = self
+                    .state
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let response = respond(&mut state, &request);
+                if let Some(key) = idempotency_key {
+                    idempotent_replies.insert(key, response.clone());
+                }
+                response
+            }
+        };
+        if self.time_out_responses.load(Ordering::SeqCst) {
+            return Err(Error::Timeout(format!(
+                "fake API timed out: {} {}",
+                request.method(),
+                request.url()
+            )));
+        }
+        if self.drop_responses.load(Ordering::SeqCst)
+            || self.drop_next_response.swap(false, Ordering::SeqCst)
+        {
+            return Err(Error::ConnectionFailed(format!(
+                "fake API dropped the response: {} {}",
+                request.method(),
+                request.url()
+            )));
+        }
+        Ok(response)
+    }
+}
+
+/// Answers `request` from `state` the way the server would, changing `state` if the request does.
+pub(crate) fn respond(state: &mut ApiState, request: &Request) -> Response {
+    let path: Vec<&str> = request
+        .url()
+        .path_segments()
+        .map(|segments| {
+            segments
+                .skip_while(|segment| *segment != "v1")
+                .skip(1)
+                .collect()
+        })
+        .unwrap_or_default();
+    let query: HashMap<String, String> = request.url().query_pairs().into_owned().collect();
+    let api_key = header(request, "Api-Key");
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default();
+    let reply = match precondition_failed(state, request, &path, api_key) {
+        Some(problem) => Err(problem),
+        None => state.route(request.method().as_str(), &path, &query, api_key, body),
+    };
+
+    let mut headers = HeaderMap::new();
+    let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    headers.insert("Date", HeaderValue::from_str(&date).expect("valid date"));
+    let (status, body) = match reply {
+        Ok((status, body)) => {
+            if !body.is_empty() {
+                // a hash of the body changes exactly when the resource does
+                let etag = format!("\"{:08x}\"", crc32(&body));
+                headers.insert("ETag", HeaderValue::from_str(&etag).expect("valid etag"));
+                if request.method().as_str() == "GET"
+                    && header(request, "If-None-Match") == Some(etag.as_str())
+                {
+                    return Response {
+                        status: StatusCode::NOT_MODIFIED,
+                        headers,
+                        body: Bytes::new(),
+                    };
+                }
+                headers.insert(
+                    "Content-Type",
+                    HeaderValue::from_static("application/octet-stream"),
+                );
+            }
+            (status, body)
+        }
+        Err((status, detail)) => {
+            headers.insert(
+                "Content-Type",
+                HeaderValue::from_static("application/problem+json"),
+            );
+            let problem = json!({
+                "title": status.canonical_reason().unwrap_or("Unknown Error"),
+                "status": status.as_u16(),
+                "detail": detail,
+            });
+            (status, problem.to_string().into_bytes())
+        }
+    };
+    Response {
+        status,
+        headers,
+        body: Bytes::from(body),
+    }
+}
+
+/// Like the server, an update with an `If-Match` header is only applied if it matches the `ETag` a
+/// GET of the resource would currently return.
+fn precondition_failed(
+    state: &mut ApiState,
+    request: &Request,
+    path: &[&str],
+    api_key: Option<&str>,
+) -> Option<Problem> {
+    let if_match = header(request, "If-Match")?;
+    if request.method().as_str() != "PATCH" || if_match == "*" {
+        return None;
+    }
+    let (_, current) = state
+        .route("GET", path, &HashMap::new(), api_key, &[])
+        .ok()?;
+    if if_match == format!("\"{:08x}\"", crc32(&current)) {
+        return None;
+    }
+    Some((
+        StatusCode::PRECONDITION_FAILED,
+        format!("/{} was changed since {}", path.join("/"), if_match),
+    ))
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn encode<T: Serialize>(status: StatusCode, value: &T) -> Reply {
+    bincode::serialize(value)
+        .map(|body| (status, body))
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+fn decode<T: DeserializeOwned>(body: &[u8]) -> std::result::Result<T, Problem> {
+    bincode::deserialize(body).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+fn parse_id(segment: &str) -> std::result::Result<i32, Problem> {
+    segment
+        .parse()
+        .map_err(|_| (StatusCode::NOT_FOUND, format!("Invalid id {}", segment)))
+}
+
+fn not_found(what: &str, id: i32) -> Problem {
+    (StatusCode::NOT_FOUND, format!("No {} with id {}", what, id))
+}
+
+/// Applies the `limit` and `offset` query parameters the same way the server does.
+fn page<'a, T: Clone + 'a>(
+    values: impl Iterator<Item = &'a T>,
+    query: &HashMap<String, String>,
+) -> Vec<T> {
+    let param = |name: &str| {
+        query
+            .get(name)
+            .and_then(|value| value.parse::<usize>().ok())
+    };
+    values
+        .skip(param("offset").unwrap_or(0))
+        .take(param("limit").unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
+
+/// Everything the server stores. `LocalApi` keeps it on disk, so changing its fields changes the
+/// format of local stores.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct ApiState {
+    pub(crate) last_id: i32,
+    pub(crate) owner_ids_by_api_key: HashMap<String, i32>,
+    pub(crate) owners: BTreeMap<i32, SavedOwner>,
+    pub(crate) shops: BTreeMap<i32, SavedShop>,
+    pub(crate) merchandise_lists: BTreeMap<i32, SavedMerchandiseList>,
+    pub(crate) interior_ref_lists: BTreeMap<i32, SavedInteriorRefList>,
+    pub(crate) transactions: BTreeMap<i32, SavedTransaction>,
+}
+
+impl ApiState {
+    fn route(
+        &mut self,
+        method: &str,
+        path: &[&str],
+        query: &HashMap<String, String>,
+        api_key: Option<&str>,
+        body: &[u8],
+    ) -> Reply {
+        match (method, path) {
+            ("GET", ["status"]) => Ok((StatusCode::OK, b"Ok".to_vec())),
+
+            ("POST", ["owners"]) => self.create_owner(api_key, decode(body)?),
+            ("GET", ["owners"]) => encode(StatusCode::OK, &page(self.owners.values(), query)),
+            ("GET", ["owners", id]) => encode(StatusCode::OK, self.owner(parse_id(id)?)?),
+            ("PATCH", ["owners", id]) => self.update_owner(api_key, parse_id(id)?, decode(body)?),
+            ("DELETE", ["owners", id]) => self.delete_owner(api_key, parse_id(id)?),
+            ("GET", ["owners", id, "shops"]) => {
+                let owner_id = parse_id(id)?;
+                let shops = self.shops.values().filter(|shop| shop.owner_id == owner_id);
+                encode(StatusCode::OK, &shops.cloned().collect::<Vec<_>>())
+            }
+
+            ("POST", ["shops"]) => self.create_shop(api_key, decode(body)?),
+            ("GET", ["shops"]) => encode(StatusCode::OK, &page(self.shops.values(), query)),
+            ("GET", ["shops", id]) => encode(StatusCode::OK, self.shop(parse_id(id)?)?),
+            ("PATCH", ["shops", id]) => self.update_shop(api_key, parse_id(id)?, decode(body)?),
+            ("DELETE", ["shops", id]) => self.delete_shop(api_key, parse_id(id)?),
+
+            ("POST", ["merchandise_lists"]) => self.create_merchandise_list(api_key, decode(body)?),
+            ("GET", ["merchandise_lists", id]) => {
+                let id = parse_id(id)?;
+                let list = self
+                    .merchandise_lists
+                    .get(&id)
+                    .ok_or_else(|| not_found("merchandise list", id))?;
+                encode(StatusCode::OK, list)
+            }
+            ("DELETE", ["merchandise_lists", id]) => {
+                let id = parse_id(id)?;
+                let shop_id = self
+                    .merchandise_lists
+                    .get(&id)
+                    .ok_or_else(|| not_found("merchandise list", id))?
+                    .shop_id;
+                self.delete_merchandise_list(api_key, shop_id)
+            }
+            ("GET", ["shops", id, "merchandise_list"]) => {
+                let shop_id = parse_id(id)?;
+                encode(StatusCode::OK, self.merchandise_list(shop_id)?)
+            }
+            ("PATCH", ["shops", id, "merchandise_list"]) => {
+                self.update_merchandise_list(api_key, parse_id(id)?, decode(body)?)
+            }
+            ("DELETE", ["shops", id, "merchandise_list"]) => {
+                self.delete_merchandise_list(api_key, parse_id(id)?)
+            }
+
+            ("POST", ["interior_ref_lists"]) => {
+                self.create_interior_ref_list(api_key, decode(body)?)
+            }
+            ("GET", ["interior_ref_lists", id]) => {
+                let id = parse_id(id)?;
+                let list = self
+                    .interior_ref_lists
+                    .get(&id)
+                    .ok_or_else(|| not_found("interior ref list", id))?;
+                encode(StatusCode::OK, list)
+            }
+            ("DELETE", ["interior_ref_lists", id]) => {
+                let id = parse_id(id)?;
+                let shop_id = self
+                    .interior_ref_lists
+                    .get(&id)
+                    .ok_or_else(|| not_found("interior ref list", id))?
+                    .shop_id;
+                self.delete_interior_ref_list(api_key, shop_id)
+            }
+            ("GET", ["shops", id, "interior_ref_list"]) => {
+                let shop_id = parse_id(id)?;
+                encode(StatusCode::OK, self.interior_ref_list(shop_id)?)
+            }
+            ("PATCH", ["shops", id, "interior_ref_list"]) => {
+                self.update_interior_ref_list(api_key, parse_id(id)?, decode(body)?)
+            }
+            ("DELETE", ["shops", id, "interior_ref_list"]) => {
+                self.delete_interior_ref_list(api_key, parse_id(id)?)
+            }
+
+            ("POST", ["transactions"]) => self.create_transaction(api_key, decode(body)?),
+            ("GET", ["transactions"]) => {
+                encode(StatusCode::OK, &page(self.transactions.values(), query))
+            }
+            ("GET", ["transactions", id]) => {
+                let id = parse_id(id)?;
+                let transaction = self
+                    .transactions
+                    .get(&id)
+                    .ok_or_else(|| not_found("transaction", id))?;
+                encode(StatusCode::OK, transaction)
+            }
+            ("GET", ["shops", id, "transactions"]) => {
+                let shop_id = parse_id(id)?;
+                let transactions = self
+                    .transactions
+                    .values()
+                    .filter(|transaction| transaction.shop_id == shop_id);
+                encode(StatusCode::OK, &page(transactions, query))
+            }
+
+            _ => Err((
+                StatusCode::NOT_FOUND,
+                format!("No route for {} /v1/{}", method, path.join("/")),
+            )),
+        }
+    }
+
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Owner the `Api-Key` of the request belongs to.
+    fn authenticate(&self, api_key: Option<&str>) -> std::result::Result<i32, Problem> {
+        api_key
+            .and_then(|api_key| self.owner_ids_by_api_key.get(api_key))
+            .copied()
+            .ok_or_else(|| {
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "Api-Key does not belong to an owner".to_string(),
+                )
+            })
+    }
+
+    fn authorize(&self, api_key: Option<&str>, owner_id: i32) -> std::result::Result<(), Problem> {
+        if self.authenticate(api_key)? != owner_id {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Api-Key does not belong to the owner of this resource".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn owner(&self, id: i32) -> std::result::Result<&SavedOwner, Problem> {
+        self.owners.get(&id).ok_or_else(|| not_found("owner", id))
+    }
+
+    fn shop(&self, id: i32) -> std::result::Result<&SavedShop, Problem> {
+        self.shops.get(&id).ok_or_else(|| not_found("shop", id))
+    }
+
+    fn merchandise_list(
+        &self,
+        shop_id: i32,
+    ) -> std::result::Result<&SavedMerchandiseList, Problem> {
+        self.merchandise_lists
+            .values()
+            .find(|list| list.shop_id == shop_id)
+            .ok_or_else(|| not_found("merchandise list for shop", shop_id))
+    }
+
+    fn interior_ref_list(
+        &self,
+        shop_id: i32,
+    ) -> std::result::Result<&SavedInteriorRefList, Problem> {
+        self.interior_ref_lists
+            .values()
+            .find(|list| list.shop_id == shop_id)
+            .ok_or_else(|| not_found("interior ref list for shop", shop_id))
+    }
+
+    fn create_owner(&mut self, api_key: Option<&str>, owner: Owner) -> Reply {
+        let api_key = api_key.ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                "Api-Key header is required".to_string(),
+            )
+        })?;
+        if self.owner_ids_by_api_key.contains_key(api_key) {
+            return Err((
+                StatusCode::CONFLICT,
+                "An owner already exists for this Api-Key".to_string(),
+            ));
+        }
+        let now = Utc::now().naive_utc();
+        let saved_owner = SavedOwner {
+            id: self.next_id(),
+            name: owner.name,
+            mod_version: owner.mod_version,
+            created_at: now,
+            updated_at: now,
+        };
+        self.owner_ids_by_api_key
+            .insert(api_key.to_string(), saved_owner.id);
+        self.owners.insert(saved_owner.id, saved_owner.clone());
+        encode(StatusCode::CREATED, &saved_owner)
+    }
+
+    fn update_owner(&mut self, api_key: Option<&str>, id: i32, owner: Owner) -> Reply {
+        self.owner(id)?;
+        self.authorize(api_key, id)?;
+        let saved_owner = self.owners.get_mut(&id).expect("owner exists");
+        saved_owner.name = owner.name;
+        saved_owner.mod_version = owner.mod_version;
+        saved_owner.updated_at = Utc::now().naive_utc();
+        encode(StatusCode::OK, saved_owner)
+    }
+
+    fn delete_owner(&mut self, api_key: Option<&str>, id: i32) -> Reply {
+        self.owner(id)?;
+        self.authorize(api_key, id)?;
+        self.owners.remove(&id);
+        self.owner_ids_by_api_key
+            .retain(|_, owner_id| *owner_id != id);
+        Ok((StatusCode::NO_CONTENT, Vec::new()))
+    }
+
+    fn create_shop(&mut self, api_key: Option<&str>, shop: Shop) -> Reply {
+        let owner_id = self.authenticate(api_key)?;
+        let now = Utc::now().naive_utc();
+        let mut saved_shop = SavedShop {
+            id: self.next_id(),
+            name: String::new(),
+            owner_id,
+            description: None,
+            gold: 0,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec!["VendorItemKey".to_string()],
+            vendor_keywords_exclude: false,
+            created_at: now,
+            updated_at: now,
+        };
+        saved_shop.apply_update(&shop);
+        self.shops.insert(saved_shop.id, saved_shop.clone());
+        encode(StatusCode::CREATED, &saved_shop)
+    }
+
+    fn update_shop(&mut self, api_key: Option<&str>, id: i32, shop: Shop) -> Reply {
+        self.authorize(api_key, self.shop(id)?.owner_id)?;
+        let saved_shop = self.shops.get_mut(&id).expect("shop exists");
+        saved_shop.apply_update(&shop);
+        encode(StatusCode::OK, saved_shop)
+    }
+
+    fn delete_shop(&mut self, api_key: Option<&str>, id: i32) -> Reply {
+        self.authorize(api_key, self.shop(id)?.owner_id)?;
+        self.shops.remove(&id);
+        self.merchandise_lists.retain(|_, list| list.shop_id != id);
+        self.interior_ref_lists.retain(|_, list| list.shop_id != id);
+        Ok((StatusCode::NO_CONTENT, Vec::new()))
+    }
+
+    fn create_merchandise_list(
+        &mut self,
+        api_key: Option<&str>,
+        merchandise_list: MerchandiseList,
+    ) -> Reply {
+        let shop_id = merchandise_list.shop_id;
+        let owner_id = self.shop(shop_id)?.owner_id;
+        self.authorize(api_key, owner_id)?;
+        if self.merchandise_list(shop_id).is_ok() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Shop {} already has a merchandise list", shop_id),
+            ));
+        }
+        let now = Utc::now().naive_utc();
+        let saved_merchandise_list = SavedMerchandiseList {
+            id: self.next_id(),
+            shop_id,
+            owner_id,
+            form_list: merchandise_list.form_list,
+            created_at: now,
+            updated_at: now,
+        };
+        self.merchandise_lists
+            .insert(saved_merchandise_list.id, saved_merchandise_list.clone());
+        encode(StatusCode::CREATED, &saved_merchandise_list)
+    }
+
+    fn update_merchandise_list(
+        &mut self,
+        api_key: Option<&str>,
+        shop_id: i32,
+        merchandise_list: MerchandiseList,
+    ) -> Reply {
+        let saved_merchandise_list = self.merchandise_list(shop_id)?;
+        let id = saved_merchandise_list.id;
+        self.authorize(api_key, saved_merchandise_list.owner_id)?;
+        let saved_merchandise_list = self.merchandise_lists.get_mut(&id).expect("list exists");
+        saved_merchandise_list.form_list = merchandise_list.form_list;
+        saved_merchandise_list.updated_at = Utc::now().naive_utc();
+        encode(StatusCode::OK, saved_merchandise_list)
+    }
+
+    fn delete_merchandise_list(&mut self, api_key: Option<&str>, shop_id: i32) -> Reply {
+        let saved_merchandise_list = self.merchandise_list(shop_id)?;
+        let id = saved_merchandise_list.id;
+        self.authorize(api_key, saved_merchandise_list.owner_id)?;
+        self.merchandise_lists.remove(&id);
+        Ok((StatusCode::NO_CONTENT, Vec::new()))
+    }
+
+    fn create_interior_ref_list(
+        &mut self,
+        api_key: Option<&str>,
+        interior_ref_list: InteriorRefList,
+    ) -> Reply {
+        let shop_id = interior_ref_list.shop_id;
+        let owner_id = self.shop(shop_id)?.owner_id;
+        self.authorize(api_key, owner_id)?;
+        if self.interior_ref_list(shop_id).is_ok() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Shop {} already has an interior ref list", shop_id),
+            ));
+        }
+        let now = Utc::now().naive_utc();
+        let saved_interior_ref_list = SavedInteriorRefList {
+            id: self.next_id(),
+            shop_id,
+            owner_id,
+            ref_list: interior_ref_list.ref_list,
+            shelves: interior_ref_list.shelves,
+            created_at: now,
+            updated_at: now,
+        };
+        self.interior_ref_lists
+            .insert(saved_interior_ref_list.id, saved_interior_ref_list.clone());
+        encode(StatusCode::CREATED, &saved_interior_ref_list)
+    }
+
+    fn update_interior_ref_list(
+        &mut self,
+        api_key: Option<&str>,
+        shop_id: i32,
+        interior_ref_list: InteriorRefList,
+    ) -> Reply {
+        let saved_interior_ref_list = self.interior_ref_list(shop_id)?;
+        let id = saved_interior_ref_list.id;
+        self.authorize(api_key, saved_interior_ref_list.owner_id)?;
+        let saved_interior_ref_list = self.interior_ref_lists.get_mut(&id).expect("list exists");
+        saved_interior_ref_list.ref_list = interior_ref_list.ref_list;
+        saved_interior_ref_list.shelves = interior_ref_list.shelves;
+        saved_interior_ref_list.updated_at = Utc::now().naive_utc();
+        encode(StatusCode::OK, saved_interior_ref_list)
+    }
+
+    fn delete_interior_ref_list(&mut self, api_key: Option<&str>, shop_id: i32) -> Reply {
+        let saved_interior_ref_list = self.interior_ref_list(shop_id)?;
+        let id = saved_interior_ref_list.id;
+        self.authorize(api_key, saved_interior_ref_list.owner_id)?;
+        self.interior_ref_lists.remove(&id);
+        Ok((StatusCode::NO_CONTENT, Vec::new()))
+    }
+
+    /// Records the transaction and moves gold and stock between the shop and the player, like the
+    /// server does.
+    fn create_transaction(&mut self, api_key: Option<&str>, transaction: Transaction) -> Reply {
+        let owner_id = self.authenticate(api_key)?;
+        let shop_id = transaction.shop_id;
+        let gold = shop_gold_after(self.shop(shop_id)?.gold, &transaction)
+            .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        if let Some(list) = self
+            .merchandise_lists
+            .values_mut()
+            .find(|list| list.shop_id == shop_id)
+        {
+            list.apply_transaction(&transaction)
+                .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        }
+        let shop = self.shops.get_mut(&shop_id).expect("shop exists");
+        shop.gold = gold;
+        shop.updated_at = Utc::now().naive_utc();
+        let now = Utc::now().naive_utc();
+        let saved_transaction = SavedTransaction {
+            id: self.next_id(),
+            owner_id,
+            shop_id,
+            mod_name: transaction.mod_name,
+            local_form_id: transaction.local_form_id,
+            name: transaction.name,
+            form_type: transaction.form_type,
+            is_food: transaction.is_food,
+            price: transaction.price,
+            is_sell: transaction.is_sell,
+            quantity: transaction.quantity,
+            amount: transaction.amount,
+            keywords: transaction.keywords,
+            created_at: now,
+            updated_at: now,
+        };
+        self.transactions
+            .insert(saved_transaction.id, saved_transaction.clone());
+        encode(StatusCode::CREATED, &saved_transaction)
+    }
+}
+
+/// Like `client_new_with_cache_root`, but the client talks to its own in-memory `FakeApi` instead
+/// of a server. Everything created with it is gone once the client is freed.
+#[no_mangle]
+pub extern "C" fn client_new_with_fake_api(
+    api_key: *const c_char,
+    cache_root: *const c_char,
+    options: ClientOptions,
+) -> FFIResult<*mut BazaarClient> {
+    install_panic_hook();
+    catch_panic(|| {
+        let api_key = optional_str_from_raw(api_key);
+        let cache_root = optional_str_from_raw(cache_root)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_cache_root);
+        info!(
+            "client_new_with_fake_api api_key: {}, cache_root: {:?}, options: {:?}",
+            api_key
+                .as_deref()
+                .map_or_else(|| "null".to_string(), api_key_fingerprint),
+            cache_root,
+            options
+        );
+
+        let client = match api_key {
+            None => Err(Error::InvalidArgument(InvalidArgumentError::new(
+                "api_key", "is null",
+            ))),
+            Some(api_key) => BazaarClient::with_transport(
+                FAKE_API_URL,
+                &api_key,
+                options,
+                &cache_root,
+                Arc::new(FakeApi::new()),
+            ),
+        };
+        match client {
+            Ok(client) => FFIResult::Ok(Box::into_raw(Box::new(client))),
+            Err(err) => {
+                error!("client_new_with_fake_api failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Client for tests that talks to `fake` and keeps its cache in a fresh temporary directory.
+#[cfg(test)]
+pub(crate) fn fake_client(fake: &Arc<FakeApi>, api_key: &str) -> (tempfile::TempDir, BazaarClient) {
+    let cache_root = tempfile::tempdir().unwrap();
+    let client = BazaarClient::with_transport(
+        FAKE_API_URL,
+        api_key,
+        ClientOptions {
+            retry_max_attempts: 1,
+            ..ClientOptions::default()
+        },
+        cache_root.path(),
+        fake.clone(),
+    )
+    .unwrap();
+    (cache_root, client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{freshness::FreshnessSource, merchandise_list::Merchandise};
+
+    fn example_merchandise(quantity: u32) -> Merchandise {
+        Merchandise {
+            mod_name: "Skyrim.esm".to_string(),
+            local_form_id: 1,
+            name: "Iron Sword".to_string(),
+            quantity,
+            form_type: 41,
+            is_food: false,
+            price: 25,
+            keywords: vec!["VendorItemWeapon".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_create_get_update_not_modified() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_other_cache_root, other_client) = fake_client(&fake, "owner-key");
+
+        let owner = client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        assert_eq!(shop.owner_id, owner.id);
+
+        let fetched = client.get_shop_with_freshness(shop.id).unwrap();
+        assert_eq!(fetched.freshness.source, FreshnessSource::NotModified);
+        assert_eq!(fetched.value.name, "Shop");
+
+        let update = Shop {
+            gold: Some(500),
+            ..Shop::from_game("Renamed Shop", "description")
+        };
+        let updated = client.update_shop(shop.id as u32, &update).unwrap();
+        assert_eq!(updated.name, "Renamed Shop");
+        assert_eq!(updated.gold, 500);
+        let fetched = client.get_shop_with_freshness(shop.id).unwrap();
+        assert_eq!(fetched.freshness.source, FreshnessSource::NotModified);
+        assert_eq!(fetched.value.gold, 500);
+
+        other_client
+            .update_shop(
+                shop.id as u32,
+                &Shop::from_game("Their Shop", "description"),
+            )
+            .unwrap();
+        let fetched = client.get_shop_with_freshness(shop.id).unwrap();
+        assert_eq!(fetched.freshness.source, FreshnessSource::Network);
+        assert_eq!(fetched.value.name, "Their Shop");
+    }
+
+    #[test]
+    fn test_create_transaction_moves_gold_and_stock() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_buyer_cache_root, buyer) = fake_client(&fake, "buyer-key");
+
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client
+            .create_merchandise_list(&MerchandiseList {
+                shop_id: shop.id,
+                owner_id: None,
+                form_list: vec![example_merchandise(3)],
+            })
+            .unwrap();
+        buyer.create_owner("Buyer", 1).unwrap();
+
+        let transaction = buyer
+            .create_transaction(&Transaction {
+                shop_id: shop.id,
+                owner_id: None,
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Iron Sword".to_string(),
+                form_type: 41,
+                is_food: false,
+                price: 25,
+                is_sell: false,
+                quantity: 2,
+                amount: 50,
+                keywords: vec!["VendorItemWeapon".to_string()],
+            })
+            .unwrap();
+        assert_eq!(transaction.shop_id, shop.id);
+
+        assert_eq!(client.get_shop(shop.id).unwrap().gold, 50);
+        let merchandise_list = client.get_merchandise_list_by_shop_id(shop.id).unwrap();
+        assert_eq!(merchandise_list.form_list[0].quantity, 1);
+        assert_eq!(
+            client
+                .list_transactions_by_shop_id(shop.id, 10, 0)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_only_owner_can_update() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_other_cache_root, other_client) = fake_client(&fake, "other-key");
+
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        other_client.create_owner("Other", 1).unwrap();
+
+        match other_client.update_shop(shop.id as u32, &Shop::from_game("Mine", "description")) {
+            Err(Error::Server(error)) => assert_eq!(error.status, StatusCode::FORBIDDEN),
+            result => panic!("update_shop did not return a forbidden error: {:?}", result),
+        }
+        assert_eq!(client.get_shop(shop.id).unwrap().name, "Shop");
+    }
+
+    #[test]
+    fn test_offline() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        fake.set_offline(true);
+
+        let fetched = client.get_shop_with_freshness(shop.id).unwrap();
+        assert_eq!(fetched.freshness.source, FreshnessSource::OfflineFallback);
+        assert_eq!(fetched.value.name, "Shop");
+        match client.create_shop("Another Shop", "description") {
+            Err(Error::ConnectionFailed(_)) => {}
+            result => panic!("create_shop did not fail to connect: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::mem::align_of;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    cache::Metadata,
+    error::{Error, InvalidArgumentError, Result},
+};
+
+/// Where a value returned by a `*_with_freshness` function came from. Ordered from most to least
+/// fresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum FreshnessSource {
+    /// The server sent the value in response to this request.
+    Network = 1,
+    /// The server confirmed with a 304 that the cached value is still current.
+    NotModified = 2,
+    /// The server could not be reached or returned an error, so the cached value was returned
+    /// as is. It may be out of date.
+    OfflineFallback = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Freshness {
+    pub source: FreshnessSource,
+    /// When the server sent the value, from its `Date` header.
+    pub date: Option<DateTime<Utc>>,
+}
+
+impl Freshness {
+    pub fn new(source: FreshnessSource, metadata: Option<&Metadata>) -> Self {
+        Self {
+            source,
+            date: metadata.and_then(|metadata| metadata.date),
+        }
+    }
+
+    /// Freshness of a value built from two fetched values, like the pages of a list: the least
+    /// fresh source and the oldest date.
+    pub fn combine(self, other: Freshness) -> Self {
+        Self {
+            source: self.source.max(other.source),
+            date: match (self.date, other.date) {
+                (Some(date), Some(other_date)) => Some(date.min(other_date)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fetched<T> {
+    pub value: T,
+    pub freshness: Freshness,
+}
+
+impl<T> Fetched<T> {
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct RawFreshness {
+    pub source: FreshnessSource,
+    /// Unix timestamp in seconds of when the server sent the value, or -1 if unknown.
+    pub date: i64,
+}
+
+impl From<Freshness> for RawFreshness {
+    fn from(freshness: Freshness) -> Self {
+        Self {
+            source: freshness.source,
+            date: freshness.date.map_or(-1, |date| date.timestamp()),
+        }
+    }
+}
+
+/// Writes `freshness` to the out-parameter passed in by the plugin, which may be null if it
+/// doesn't care.
+pub fn write_raw_freshness(ptr: *mut RawFreshness, freshness: Freshness) {
+    if let Some(raw_freshness) = unsafe { ptr.as_mut() } {
+        *raw_freshness = RawFreshness::from(freshness);
+    }
+}
+
+/// Out-parameters are checked for alignment before anything is fetched, so a bad pointer fails
+/// the call instead of being written to afterwards.
+pub fn check_raw_freshness(ptr: *mut RawFreshness) -> Result<()> {
+    if !(ptr as usize).is_multiple_of(align_of::<RawFreshness>()) {
+        return Err(Error::InvalidArgument(InvalidArgumentError::new(
+            "freshness",
+            "is not aligned",
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_combine() {
+        let older = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let newer = Utc.timestamp_opt(1_600_000_100, 0).unwrap();
+        let network = Freshness {
+            source: FreshnessSource::Network,
+            date: Some(newer),
+        };
+        let fallback = Freshness {
+            source: FreshnessSource::OfflineFallback,
+            date: Some(older),
+        };
+        assert_eq!(network.combine(fallback), fallback);
+        assert_eq!(fallback.combine(network), fallback);
+        assert_eq!(
+            network.combine(Freshness {
+                source: FreshnessSource::NotModified,
+                date: None,
+            }),
+            Freshness {
+                source: FreshnessSource::NotModified,
+                date: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_raw_freshness() {
+        let freshness = Freshness {
+            source: FreshnessSource::NotModified,
+            date: Some(Utc.timestamp_opt(1_600_000_000, 0).unwrap()),
+        };
+        let mut raw_freshness = RawFreshness {
+            source: FreshnessSource::Network,
+            date: -1,
+        };
+        write_raw_freshness(&mut raw_freshness, freshness);
+        assert_eq!(
+            raw_freshness,
+            RawFreshness {
+                source: FreshnessSource::NotModified,
+                date: 1_600_000_000,
+            }
+        );
+        write_raw_freshness(std::ptr::null_mut(), freshness);
+    }
+}
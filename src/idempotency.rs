@@ -0,0 +1,504 @@
+use std::{
+    ffi::CString,
+    fs::{read, remove_file},
+    io::ErrorKind,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[cfg(not(test))]
+use log::{error, info};
+#[cfg(test)]
+use std::{println as info, println as error};
+
+use crate::{
+    cache::{crc32, decode_cache_entry, encode_cache_entry, write_file_atomically, CacheSchema},
+    client::BazaarClient,
+    error::Result,
+    panic::catch_panic,
+    transport::Response,
+};
+
+/// Key of a POST that was sent but not answered, persisted so that sending the same request again
+/// after a crash reuses it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingKey {
+    pub key: String,
+    /// Hash of the request, to find the key again once the process that sent it is gone.
+    pub fingerprint: u32,
+    /// The process that sent it. Only a later process can take the key over.
+    pub session: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CacheSchema for PendingKey {
+    const SCHEMA_VERSION: u32 = 2;
+}
+
+// shared by every client for the same API, like the outbox
+static IDEMPOTENCY_KEYS_LOCK: Mutex<()> = Mutex::new(());
+static SESSION: Mutex<Option<Uuid>> = Mutex::new(None);
+
+/// Identifies this run of the game, so keys left behind by a crashed one can be told apart.
+fn current_session() -> Uuid {
+    *SESSION
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get_or_insert_with(Uuid::new_v4)
+}
+
+/// Keys of POSTs that haven't been answered, persisted to `idempotency_keys.bin` in the cache
+/// directory before they are sent. A key is forgotten once the server answers.
+///
+/// Every create gets its own key, so an identical create in the same run is applied again. Only a
+/// key left behind by an earlier run that crashed is reused, once, by the first create of the
+/// same request, since that is the create being sent again.
+#[derive(Debug)]
+pub struct IdempotencyKeys {
+    path: PathBuf,
+    session: Uuid,
+}
+
+impl IdempotencyKeys {
+    /// Keys a crashed run left behind this long ago are dropped, since its create won't be sent
+    /// again anymore.
+    const MAX_AGE_HOURS: i64 = 24;
+
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            path: cache_dir.join("idempotency_keys.bin"),
+            session: current_session(),
+        }
+    }
+
+    fn read_keys(&self) -> anyhow::Result<Vec<PendingKey>> {
+        let contents = match read(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err).context("Failed to read idempotency keys"),
+        };
+        let payload = decode_cache_entry::<Vec<PendingKey>>(&contents)
+            .map_err(|reason| anyhow!("Idempotency keys are corrupt: {}", reason))?;
+        Ok(bincode::deserialize(payload)?)
+    }
+
+    fn write_keys(&self, keys: &[PendingKey]) -> anyhow::Result<()> {
+        if keys.is_empty() {
+            return match remove_file(&self.path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    Err(err).context("Failed to remove idempotency keys")
+                }
+                _ => Ok(()),
+            };
+        }
+        let payload = bincode::serialize(keys)?;
+        write_file_atomically(&self.path, &encode_cache_entry::<Vec<PendingKey>>(&payload))
+            .context("Failed to write idempotency keys")
+    }
+
+    /// Like `new`, but as a different run of the game would see it.
+    #[cfg(test)]
+    pub fn with_session(cache_dir: &Path, session: Uuid) -> Self {
+        Self {
+            session,
+            ..Self::new(cache_dir)
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        !self.path.exists()
+    }
+
+    /// Returns the key a crashed run sent the request with `fingerprint` with, taking it over, or
+    /// a new key.
+    pub fn key_for(&self, fingerprint: u32) -> anyhow::Result<String> {
+        let _lock = IDEMPOTENCY_KEYS_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut keys = self.read_keys()?;
+        let oldest = Utc::now() - Duration::hours(Self::MAX_AGE_HOURS);
+        keys.retain(|pending| pending.session == self.session || pending.created_at > oldest);
+        let key = match keys
+            .iter_mut()
+            .find(|pending| pending.session != self.session && pending.fingerprint == fingerprint)
+        {
+            Some(pending) => {
+                info!("reusing idempotency key {} of an earlier run", pending.key);
+                pending.session = self.session;
+                pending.key.clone()
+            }
+            None => new_idempotency_key(),
+        };
+        self.write_keys(&keys)?;
+        Ok(key)
+    }
+
+    /// Persists `key` as sent for the request with `fingerprint` until it is forgotten.
+    pub fn remember(&self, fingerprint: u32, key: &str) -> anyhow::Result<()> {
+        let _lock = IDEMPOTENCY_KEYS_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut keys = self.read_keys()?;
+        keys.retain(|pending| pending.key != key);
+        keys.push(PendingKey {
+            key: key.to_string(),
+            fingerprint,
+            session: self.session,
+            created_at: Utc::now(),
+        });
+        self.write_keys(&keys)
+    }
+
+    pub fn forget(&self, key: &str) -> anyhow::Result<()> {
+        let _lock = IDEMPOTENCY_KEYS_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut keys = self.read_keys()?;
+        keys.retain(|pending| pending.key != key);
+        self.write_keys(&keys)
+    }
+}
+
+/// A new key for one create. Each call gets its own, so two identical creates are both applied,
+/// and only sending the same call again (a retry, a replayed outbox item, the plugin passing the
+/// key back in or the first create after a crash) reuses it.
+pub(crate) fn new_idempotency_key() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn request_fingerprint(api_key: &str, path: &str, body: &[u8]) -> u32 {
+    let mut bytes = Vec::with_capacity(api_key.len() + path.len() + body.len() + 2);
+    bytes.extend_from_slice(api_key.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(path.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(body);
+    crc32(&bytes)
+}
+
+impl BazaarClient {
+    /// Key for POSTing `body` to `path`: the one a crashed run left behind for it, or a new one.
+    pub(crate) fn idempotency_key_for(&self, path: &str, body: &[u8]) -> Result<String> {
+        let fingerprint = request_fingerprint(&self.api_key, path, body);
+        Ok(self.idempotency_keys.key_for(fingerprint)?)
+    }
+
+    /// POSTs `body` to `path` with an `Idempotency-Key` header, which also lets the retry policy
+    /// retry it. The server applies a key only once, so sending it again can't create it twice.
+    /// An empty key, which the plugin can pass in, sends it without one and without retries.
+    ///
+    /// The key is persisted before the request is sent and forgotten once the server accepts or
+    /// rejects it, so a create the game crashed during is sent with the same key next time.
+    pub(crate) fn send_post(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+        idempotency_key: &str,
+    ) -> Result<Response> {
+        let mut request = self
+            .http
+            .post(self.url(path)?)
+            .header("Api-Key", &self.api_key)
+            .header("Content-Type", "application/octet-stream");
+        if !idempotency_key.is_empty() {
+            let fingerprint = request_fingerprint(&self.api_key, path, &body);
+            self.idempotency_keys
+                .remember(fingerprint, idempotency_key)?;
+            request = request.header("Idempotency-Key", idempotency_key);
+        }
+        let resp = self.send(request.body(body))?;
+        if !idempotency_key.is_empty()
+            && (resp.status().is_success() || resp.status().is_client_error())
+        {
+            if let Err(err) = self.idempotency_keys.forget(idempotency_key) {
+                error!(
+                    "failed to forget idempotency key {}. {}",
+                    idempotency_key, err
+                );
+            }
+        }
+        Ok(resp)
+    }
+}
+
+/// Returns a new key for the `*_with_idempotency_key` functions. Free it with `free_string`.
+#[no_mangle]
+pub extern "C" fn generate_idempotency_key() -> *mut c_char {
+    catch_panic(|| {
+        let key = CString::new(new_idempotency_key())
+            .expect("could not create CString")
+            .into_raw();
+        info!("generate_idempotency_key successful");
+        key
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        client::ClientOptions,
+        error::Error,
+        fake_api::{fake_client, FakeApi, FAKE_API_URL},
+        merchandise_list::{Merchandise, MerchandiseList},
+        shop::SavedShop,
+        transaction::Transaction,
+    };
+
+    fn example_transaction(shop_id: i32) -> Transaction {
+        Transaction {
+            shop_id,
+            owner_id: None,
+            mod_name: "Skyrim.esm".to_string(),
+            local_form_id: 1,
+            name: "Iron Sword".to_string(),
+            form_type: 41,
+            is_food: false,
+            price: 25,
+            is_sell: false,
+            quantity: 1,
+            amount: 25,
+            keywords: vec!["VendorItemWeapon".to_string()],
+        }
+    }
+
+    fn example_shop(client: &BazaarClient) -> SavedShop {
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client
+            .create_merchandise_list(&MerchandiseList {
+                shop_id: shop.id,
+                owner_id: None,
+                form_list: vec![Merchandise {
+                    mod_name: "Skyrim.esm".to_string(),
+                    local_form_id: 1,
+                    name: "Iron Sword".to_string(),
+                    quantity: 3,
+                    form_type: 41,
+                    is_food: false,
+                    price: 25,
+                    keywords: vec!["VendorItemWeapon".to_string()],
+                }],
+            })
+            .unwrap();
+        shop
+    }
+
+    #[test]
+    fn test_replayed_transaction_is_applied_once() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_buyer_cache_root, buyer) = fake_client(&fake, "buyer-key");
+        let shop = example_shop(&client);
+        buyer.create_owner("Buyer", 1).unwrap();
+
+        // the server commits the transaction but the buyer never hears back, so it gets queued
+        fake.set_drop_responses(true);
+        buyer
+            .create_transaction(&example_transaction(shop.id))
+            .unwrap();
+        // its key is kept in the outbox item only
+        assert!(buyer.idempotency_keys.is_empty());
+        fake.set_drop_responses(false);
+        buyer.replay_outbox().unwrap();
+        assert!(buyer.outbox.is_empty());
+
+        assert_eq!(client.get_shop(shop.id).unwrap().gold, 25);
+        assert_eq!(
+            client
+                .list_transactions_by_shop_id(shop.id, 10, 0)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_identical_transactions_are_both_applied() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_buyer_cache_root, buyer) = fake_client(&fake, "buyer-key");
+        let shop = example_shop(&client);
+        buyer.create_owner("Buyer", 1).unwrap();
+
+        // the same purchase twice while the first is still waiting in the outbox
+        fake.set_drop_responses(true);
+        buyer
+            .create_transaction(&example_transaction(shop.id))
+            .unwrap();
+        fake.set_drop_responses(false);
+        buyer
+            .create_transaction(&example_transaction(shop.id))
+            .unwrap();
+        buyer.replay_outbox().unwrap();
+
+        assert_eq!(client.get_shop(shop.id).unwrap().gold, 50);
+        assert_eq!(
+            client
+                .list_transactions_by_shop_id(shop.id, 10, 0)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_timed_out_transaction() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_buyer_cache_root, buyer) = fake_client(&fake, "buyer-key");
+        let shop = example_shop(&client);
+        buyer.create_owner("Buyer", 1).unwrap();
+
+        // without a key the server may have applied it, so it is not queued to be sent again
+        fake.set_time_out_responses(true);
+        match buyer.create_transaction_with_idempotency_key(&example_transaction(shop.id), "") {
+            Err(Error::Timeout(_)) => {}
+            result => panic!("create_transaction did not time out: {:?}", result),
+        }
+        assert!(buyer.outbox.is_empty());
+        // with one, the server ignores it if the first attempt was applied
+        buyer
+            .create_transaction(&example_transaction(shop.id))
+            .unwrap();
+        assert!(!buyer.outbox.is_empty());
+        fake.set_time_out_responses(false);
+        buyer.replay_outbox().unwrap();
+
+        assert_eq!(client.get_shop(shop.id).unwrap().gold, 50);
+        assert_eq!(
+            client
+                .list_transactions_by_shop_id(shop.id, 10, 0)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_key_for() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let keys = IdempotencyKeys::new(cache_dir.path());
+        keys.remember(1, "sent").unwrap();
+        // the same run gets a new key for the same request
+        assert_ne!(keys.key_for(1).unwrap(), "sent");
+
+        // a later run takes it over, but only once
+        let restarted = IdempotencyKeys::with_session(cache_dir.path(), Uuid::new_v4());
+        assert_eq!(restarted.key_for(1).unwrap(), "sent");
+        assert_ne!(restarted.key_for(1).unwrap(), "sent");
+        assert_ne!(restarted.key_for(2).unwrap(), "sent");
+
+        restarted.forget("sent").unwrap();
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn test_create_after_crash_reuses_key() {
+        let fake = Arc::new(FakeApi::new());
+        let cache_root = tempfile::tempdir().unwrap();
+        let new_client = || {
+            BazaarClient::with_transport(
+                FAKE_API_URL,
+                "owner-key",
+                ClientOptions {
+                    retry_max_attempts: 1,
+                    ..ClientOptions::default()
+                },
+                cache_root.path(),
+                fake.clone(),
+            )
+            .unwrap()
+        };
+
+        let client = new_client();
+        client.create_owner("Owner", 1).unwrap();
+        // the server creates the shop but the game crashes before the response arrives
+        fake.set_drop_responses(true);
+        assert!(client.create_shop("Shop", "description").is_err());
+        assert!(!client.idempotency_keys.is_empty());
+        drop(client);
+        fake.set_drop_responses(false);
+
+        let mut client = new_client();
+        client.idempotency_keys = Arc::new(IdempotencyKeys::with_session(
+            &client.cache_dir,
+            Uuid::new_v4(),
+        ));
+        let shop = client.create_shop("Shop", "description").unwrap();
+        assert!(client.idempotency_keys.is_empty());
+        assert_eq!(client.list_shops().unwrap().len(), 1);
+        assert_eq!(client.list_shops().unwrap()[0].id, shop.id);
+        // a second create in the same run is a new shop
+        client.create_shop("Shop", "description").unwrap();
+        assert_eq!(client.list_shops().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_create_after_crash_with_supplied_key() {
+        let fake = Arc::new(FakeApi::new());
+        let cache_root = tempfile::tempdir().unwrap();
+        let new_client = || {
+            BazaarClient::with_transport(
+                FAKE_API_URL,
+                "owner-key",
+                ClientOptions {
+                    retry_max_attempts: 1,
+                    ..ClientOptions::default()
+                },
+                cache_root.path(),
+                fake.clone(),
+            )
+            .unwrap()
+        };
+        let key = new_idempotency_key();
+
+        let client = new_client();
+        client.create_owner("Owner", 1).unwrap();
+        fake.set_drop_responses(true);
+        match client.create_shop_with_idempotency_key("Shop", "description", &key) {
+            Err(Error::ConnectionFailed(_)) => {}
+            result => panic!("create_shop did not fail to connect: {:?}", result),
+        }
+        drop(client);
+        fake.set_drop_responses(false);
+
+        let client = new_client();
+        let shop = client
+            .create_shop_with_idempotency_key("Shop", "description", &key)
+            .unwrap();
+        assert_eq!(client.list_shops().unwrap().len(), 1);
+        assert_eq!(client.list_shops().unwrap()[0].id, shop.id);
+        // without the key the same create is a second shop
+        client.create_shop("Shop", "description").unwrap();
+        assert_eq!(client.list_shops().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_supplied_key() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_other_cache_root, other_client) = fake_client(&fake, "owner-key");
+
+        let key = unsafe { CString::from_raw(generate_idempotency_key()) }
+            .into_string()
+            .unwrap();
+        let owner = client
+            .create_owner_with_idempotency_key("Owner", 1, &key)
+            .unwrap();
+        // a second create with the same key gets the first one's response instead of a new owner
+        let other_owner = other_client
+            .create_owner_with_idempotency_key("Owner", 1, &key)
+            .unwrap();
+        assert_eq!(other_owner.id, owner.id);
+    }
+}
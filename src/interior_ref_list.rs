@@ -1,8 +1,7 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char, slice};
+use std::{ffi::CString, os::raw::c_char};
 
-use anyhow::Result;
-use chrono::NaiveDateTime;
-use reqwest::{StatusCode, Url};
+use chrono::{NaiveDateTime, Utc};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(test))]
@@ -11,16 +10,18 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    cache::file_cache_dir,
-    cache::from_file_cache,
-    cache::load_metadata_from_file_cache,
-    cache::update_file_caches,
-    error::extract_error_from_response,
-    log_server_error,
+    cache::{update_file_caches, CacheSchema, Metadata},
+    client::{client_from_ptr, with_legacy_client, BazaarClient},
+    error::{conflict_from_response, extract_error_from_response, Result, ServerCopy},
+    free_raw_string, log_server_error, optional_str_from_raw,
+    outbox::Mutation,
+    panic::catch_panic,
+    request::{submit_request, RequestOutcome},
     result::{FFIError, FFIResult},
+    slice_from_raw, str_from_raw,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InteriorRefList {
     pub shop_id: i32,
     pub owner_id: Option<i32>,
@@ -28,7 +29,7 @@ pub struct InteriorRefList {
     pub shelves: Vec<Shelf>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct InteriorRef {
     pub base_mod_name: String,
     pub base_local_form_id: u32,
@@ -43,7 +44,7 @@ pub struct InteriorRef {
     pub scale: u16,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Shelf {
     pub shelf_type: u32,
     pub position_x: f32,
@@ -66,35 +67,32 @@ impl InteriorRefList {
         shop_id: i32,
         raw_interior_ref_slice: &[RawInteriorRef],
         raw_shelves_slice: &[RawShelf],
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        Ok(Self {
             shop_id,
             owner_id: None,
             ref_list: raw_interior_ref_slice
                 .iter()
-                .map(|rec| InteriorRef {
-                    base_mod_name: unsafe { CStr::from_ptr(rec.base_mod_name) }
-                        .to_string_lossy()
-                        .to_string(),
-                    base_local_form_id: rec.base_local_form_id,
-                    ref_mod_name: match rec.ref_mod_name.is_null() {
-                        true => None,
-                        false => Some(
-                            unsafe { CStr::from_ptr(rec.ref_mod_name) }
-                                .to_string_lossy()
-                                .to_string(),
-                        ),
-                    },
-                    ref_local_form_id: rec.ref_local_form_id,
-                    position_x: rec.position_x,
-                    position_y: rec.position_y,
-                    position_z: rec.position_z,
-                    angle_x: rec.angle_x,
-                    angle_y: rec.angle_y,
-                    angle_z: rec.angle_z,
-                    scale: rec.scale,
+                .enumerate()
+                .map(|(i, rec)| {
+                    Ok(InteriorRef {
+                        base_mod_name: str_from_raw(
+                            rec.base_mod_name,
+                            &format!("raw_interior_ref_ptr[{}].base_mod_name", i),
+                        )?,
+                        base_local_form_id: rec.base_local_form_id,
+                        ref_mod_name: optional_str_from_raw(rec.ref_mod_name),
+                        ref_local_form_id: rec.ref_local_form_id,
+                        position_x: rec.position_x,
+                        position_y: rec.position_y,
+                        position_z: rec.position_z,
+                        angle_x: rec.angle_x,
+                        angle_y: rec.angle_y,
+                        angle_z: rec.angle_z,
+                        scale: rec.scale,
+                    })
                 })
-                .collect(),
+                .collect::<Result<Vec<InteriorRef>>>()?,
             shelves: raw_shelves_slice
                 .iter()
                 .map(|rec| Shelf {
@@ -112,30 +110,25 @@ impl InteriorRefList {
                         _ => Some(rec.filter_form_type),
                     },
                     filter_is_food: rec.filter_is_food,
-                    search: match rec.search.is_null() {
-                        true => None,
-                        false => Some(
-                            unsafe { CStr::from_ptr(rec.search) }
-                                .to_string_lossy()
-                                .to_string(),
-                        ),
-                    },
-                    sort_on: match rec.sort_on.is_null() {
-                        true => None,
-                        false => Some(
-                            unsafe { CStr::from_ptr(rec.sort_on) }
-                                .to_string_lossy()
-                                .to_string(),
-                        ),
-                    },
+                    search: optional_str_from_raw(rec.search),
+                    sort_on: optional_str_from_raw(rec.sort_on),
                     sort_asc: rec.sort_asc,
                 })
                 .collect(),
-        }
+        })
+    }
+
+    /// Whether `interior_ref_list` already has everything this update sets.
+    pub fn is_applied_to(&self, interior_ref_list: &SavedInteriorRefList) -> bool {
+        self.owner_id
+            .iter()
+            .all(|&owner_id| owner_id == interior_ref_list.owner_id)
+            && self.ref_list == interior_ref_list.ref_list
+            && self.shelves == interior_ref_list.shelves
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SavedInteriorRefList {
     pub id: i32,
     pub shop_id: i32,
@@ -146,6 +139,10 @@ pub struct SavedInteriorRefList {
     pub updated_at: NaiveDateTime,
 }
 
+impl CacheSchema for SavedInteriorRefList {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct RawInteriorRef {
@@ -216,10 +213,7 @@ impl From<Shelf> for RawShelf {
             angle_z: shelf.angle_z,
             scale: shelf.scale,
             page: shelf.page,
-            filter_form_type: match shelf.filter_form_type {
-                None => 0,
-                Some(form_type) => form_type,
-            },
+            filter_form_type: shelf.filter_form_type.unwrap_or_default(),
             filter_is_food: shelf.filter_is_food,
             search: match shelf.search {
                 None => std::ptr::null(),
@@ -234,7 +228,7 @@ impl From<Shelf> for RawShelf {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[repr(C)]
 pub struct RawInteriorRefVec {
     pub ptr: *mut RawInteriorRef,
@@ -242,7 +236,7 @@ pub struct RawInteriorRefVec {
     pub cap: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[repr(C)]
 pub struct RawShelfVec {
     pub ptr: *mut RawShelf,
@@ -250,338 +244,373 @@ pub struct RawShelfVec {
     pub cap: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[repr(C)]
 pub struct RawInteriorRefData {
     pub interior_ref_vec: RawInteriorRefVec,
     pub shelf_vec: RawShelfVec,
 }
 
-// TODO: delete me if unused
+impl From<SavedInteriorRefList> for RawInteriorRefData {
+    fn from(interior_ref_list: SavedInteriorRefList) -> Self {
+        let (interior_ref_ptr, interior_ref_len, interior_ref_cap) = interior_ref_list
+            .ref_list
+            .into_iter()
+            .map(RawInteriorRef::from)
+            .collect::<Vec<RawInteriorRef>>()
+            .into_raw_parts();
+        let (shelf_ptr, shelf_len, shelf_cap) = interior_ref_list
+            .shelves
+            .into_iter()
+            .map(RawShelf::from)
+            .collect::<Vec<RawShelf>>()
+            .into_raw_parts();
+        Self {
+            interior_ref_vec: RawInteriorRefVec {
+                ptr: interior_ref_ptr,
+                len: interior_ref_len,
+                cap: interior_ref_cap,
+            },
+            shelf_vec: RawShelfVec {
+                ptr: shelf_ptr,
+                len: shelf_len,
+                cap: shelf_cap,
+            },
+        }
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn create_interior_ref_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-    raw_interior_ref_ptr: *const RawInteriorRef,
-    raw_interior_ref_len: usize,
-    raw_shelf_ptr: *const RawShelf,
-    raw_shelf_len: usize,
-) -> FFIResult<i32> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!("create_interior_ref_list api_url: {:?}, api_key: {:?}, shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}", api_url, api_key, shop_id, raw_interior_ref_len, raw_shelf_len);
-    let raw_interior_ref_slice = match raw_interior_ref_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_interior_ref_ptr, raw_interior_ref_len) },
-    };
-    let raw_shelf_slice = match raw_shelf_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_shelf_ptr, raw_shelf_len) },
-    };
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
-        shop_id: i32,
-        raw_interior_ref_slice: &[RawInteriorRef],
-        raw_shelf_slice: &[RawShelf],
+pub extern "C" fn free_raw_interior_ref_data(raw_interior_ref_data: RawInteriorRefData) {
+    catch_panic(|| {
+        let RawInteriorRefData {
+            interior_ref_vec,
+            shelf_vec,
+        } = raw_interior_ref_data;
+        if !interior_ref_vec.ptr.is_null() {
+            let interior_refs = unsafe {
+                Vec::from_raw_parts(
+                    interior_ref_vec.ptr,
+                    interior_ref_vec.len,
+                    interior_ref_vec.cap,
+                )
+            };
+            for interior_ref in interior_refs {
+                unsafe {
+                    free_raw_string(interior_ref.base_mod_name);
+                    free_raw_string(interior_ref.ref_mod_name);
+                }
+            }
+        }
+        if !shelf_vec.ptr.is_null() {
+            let shelves =
+                unsafe { Vec::from_raw_parts(shelf_vec.ptr, shelf_vec.len, shelf_vec.cap) };
+            for shelf in shelves {
+                unsafe {
+                    free_raw_string(shelf.search);
+                    free_raw_string(shelf.sort_on);
+                }
+            }
+        }
+    })
+}
+
+impl BazaarClient {
+    pub fn create_interior_ref_list(
+        &self,
+        interior_ref_list: &InteriorRefList,
     ) -> Result<SavedInteriorRefList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/interior_ref_lists")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/interior_ref_lists")?;
+        let key = self.idempotency_key_for(
+            "v1/interior_ref_lists",
+            &bincode::serialize(interior_ref_list)?,
+        )?;
+        self.create_interior_ref_list_with_idempotency_key(interior_ref_list, &key)
+    }
 
-        let interior_ref_list =
-            InteriorRefList::from_game(shop_id, raw_interior_ref_slice, raw_shelf_slice);
-        info!(
-            "created interior_ref_list from game: shop_id: {}",
-            &interior_ref_list.shop_id
-        );
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post(url)
-            .header("Api-Key", api_key)
-            .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&interior_ref_list)?)
-            .send()?;
-        info!("create interior_ref_list response from api: {:?}", &resp);
+    /// Like `create_interior_ref_list`, but sent with `idempotency_key` instead of a new key, so
+    /// that sending it again with the same key can't create a list twice.
+    pub fn create_interior_ref_list_with_idempotency_key(
+        &self,
+        interior_ref_list: &InteriorRefList,
+        idempotency_key: &str,
+    ) -> Result<SavedInteriorRefList> {
+        let resp = self.send_post(
+            "v1/interior_ref_lists",
+            bincode::serialize(interior_ref_list)?,
+            idempotency_key,
+        )?;
+        info!("create interior_ref_list response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_interior_ref_list: SavedInteriorRefList = bincode::deserialize(&bytes)?;
-            let body_cache_path = cache_dir.join(format!(
+            let body_cache_path = self.cache_dir.join(format!(
                 "interior_ref_list_{}.bin",
                 saved_interior_ref_list.id
             ));
-            let metadata_cache_path = cache_dir.join(format!(
+            let metadata_cache_path = self.cache_dir.join(format!(
                 "interior_ref_list_{}_metadata.json",
                 saved_interior_ref_list.id
             ));
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_interior_ref_list.clone(),
+            );
+            update_file_caches::<SavedInteriorRefList>(
+                body_cache_path,
+                metadata_cache_path,
+                bytes,
+                headers,
+            );
             Ok(saved_interior_ref_list)
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(
-        &api_url,
-        &api_key,
-        shop_id,
-        raw_interior_ref_slice,
-        raw_shelf_slice,
-    ) {
-        Ok(interior_ref_list) => FFIResult::Ok(interior_ref_list.id),
-        Err(err) => {
-            error!("create_interior_ref_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
+    pub fn update_interior_ref_list(
+        &self,
+        shop_id: i32,
+        interior_ref_list: &InteriorRefList,
+    ) -> Result<SavedInteriorRefList> {
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+        self.send_or_queue(
+            || Mutation::UpdateInteriorRefList {
+                shop_id,
+                interior_ref_list: interior_ref_list.clone(),
+                base_etag: self.cached_etag(&body_cache_path, &metadata_cache_path),
+            },
+            || self.send_update_interior_ref_list(shop_id, interior_ref_list, None),
+            || self.update_interior_ref_list_offline(shop_id, interior_ref_list),
+        )
     }
-}
 
-#[no_mangle]
-pub extern "C" fn update_interior_ref_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-    raw_interior_ref_ptr: *const RawInteriorRef,
-    raw_interior_ref_len: usize,
-    raw_shelf_ptr: *const RawShelf,
-    raw_shelf_len: usize,
-) -> FFIResult<i32> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!("update_interior_ref_list api_url: {:?}, api_key: {:?}, shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}", api_url, api_key, shop_id, raw_interior_ref_len, raw_shelf_len);
-    let raw_interior_ref_slice = match raw_interior_ref_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_interior_ref_ptr, raw_interior_ref_len) },
-    };
-    let raw_shelf_slice = match raw_shelf_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_shelf_ptr, raw_shelf_len) },
-    };
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
+    fn update_interior_ref_list_offline(
+        &self,
         shop_id: i32,
-        raw_interior_ref_slice: &[RawInteriorRef],
-        raw_shelf_slice: &[RawShelf],
+        interior_ref_list: &InteriorRefList,
     ) -> Result<SavedInteriorRefList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
-
-        let interior_ref_list =
-            InteriorRefList::from_game(shop_id, raw_interior_ref_slice, raw_shelf_slice);
-        info!(
-            "created interior_ref_list from game: shop_id: {}",
-            &interior_ref_list.shop_id
-        );
-        let client = reqwest::blocking::Client::new();
-        let resp = client
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+        let now = Utc::now().naive_utc();
+        let saved_interior_ref_list = match self
+            .memory_cache
+            .load::<SavedInteriorRefList>(&body_cache_path, &metadata_cache_path)
+        {
+            Ok(cached) => SavedInteriorRefList {
+                ref_list: interior_ref_list.ref_list.clone(),
+                shelves: interior_ref_list.shelves.clone(),
+                updated_at: now,
+                ..cached
+            },
+            Err(_) => SavedInteriorRefList {
+                id: 0,
+                shop_id,
+                owner_id: interior_ref_list.owner_id.unwrap_or(0),
+                ref_list: interior_ref_list.ref_list.clone(),
+                shelves: interior_ref_list.shelves.clone(),
+                created_at: now,
+                updated_at: now,
+            },
+        };
+        self.cache_offline_copy(
+            body_cache_path,
+            metadata_cache_path,
+            &saved_interior_ref_list,
+        )?;
+        Ok(saved_interior_ref_list)
+    }
+
+    pub(crate) fn send_update_interior_ref_list(
+        &self,
+        shop_id: i32,
+        interior_ref_list: &InteriorRefList,
+        base_etag: Option<&str>,
+    ) -> Result<SavedInteriorRefList> {
+        let url = self.url(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+        let request = self
+            .http
             .patch(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&interior_ref_list)?)
-            .send()?;
-        info!("update interior_ref_list response from api: {:?}", &resp);
-
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}_interior_ref_list.bin", shop_id));
-        let metadata_cache_path =
-            cache_dir.join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+            .body(bincode::serialize(interior_ref_list)?);
+        let request = self.if_match(request, base_etag, &body_cache_path, &metadata_cache_path);
+        let (resp, attempts) = self.send_counting_attempts(request)?;
+        info!("update interior_ref_list response from api: {:?}", resp);
+
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_interior_ref_list: SavedInteriorRefList = bincode::deserialize(&bytes)?;
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_interior_ref_list.clone(),
+            );
+            update_file_caches::<SavedInteriorRefList>(
+                body_cache_path,
+                metadata_cache_path,
+                bytes,
+                headers,
+            );
             Ok(saved_interior_ref_list)
+        } else if status == StatusCode::PRECONDITION_FAILED {
+            let current: Option<SavedInteriorRefList> = self.fetch_server_copy(
+                &format!("v1/shops/{}/interior_ref_list", shop_id),
+                body_cache_path,
+                metadata_cache_path,
+            );
+            // a retry fails its precondition when the first attempt was applied
+            match current {
+                Some(current) if attempts > 1 && interior_ref_list.is_applied_to(&current) => {
+                    Ok(current)
+                }
+                current => Err(conflict_from_response(
+                    status,
+                    &bytes,
+                    current.map(ServerCopy::InteriorRefList),
+                )),
+            }
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(
-        &api_url,
-        &api_key,
-        shop_id,
-        raw_interior_ref_slice,
-        raw_shelf_slice,
-    ) {
-        Ok(interior_ref_list) => FFIResult::Ok(interior_ref_list.id),
-        Err(err) => {
-            error!("update_interior_ref_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
-    }
-}
-
-// TODO: delete me if unused
-#[no_mangle]
-pub extern "C" fn get_interior_ref_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    interior_ref_list_id: i32,
-) -> FFIResult<RawInteriorRefData> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!(
-        "get_interior_ref_list api_url: {:?}, api_key: {:?}, interior_ref_list_id: {:?}",
-        api_url, api_key, interior_ref_list_id
-    );
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
-        interior_ref_list_id: i32,
-    ) -> Result<SavedInteriorRefList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?
-            .join(&format!("v1/interior_ref_lists/{}", interior_ref_list_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/interior_ref_lists/{}", interior_ref_list_id))?;
+    pub fn get_interior_ref_list(&self, interior_ref_list_id: i32) -> Result<SavedInteriorRefList> {
+        let url = self.url(&format!("v1/interior_ref_lists/{}", interior_ref_list_id))?;
         info!("api_url: {:?}", url);
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path =
-            cache_dir.join(format!("interior_ref_list_{}.bin", interior_ref_list_id));
-        let metadata_cache_path = cache_dir.join(format!(
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("interior_ref_list_{}.bin", interior_ref_list_id));
+        let metadata_cache_path = self.cache_dir.join(format!(
             "interior_ref_list_{}_metadata.json",
             interior_ref_list_id
         ));
-        let mut request = client
+        let mut request = self
+            .http
             .get(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
             if let Some(etag) = metadata.etag {
                 request = request.header("If-None-Match", etag);
             }
         }
 
-        match request.send() {
+        match self.send(request) {
             Ok(resp) => {
-                info!("get_interior_ref_list response from api: {:?}", &resp);
+                info!("get_interior_ref_list response from api: {:?}", resp);
                 if resp.status().is_success() {
                     let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
-                    let saved_interior_ref_list = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+                    let bytes = resp.into_body();
+                    let saved_interior_ref_list: SavedInteriorRefList =
+                        bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_interior_ref_list.clone(),
+                    );
+                    update_file_caches::<SavedInteriorRefList>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
                     Ok(saved_interior_ref_list)
                 } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 } else {
                     log_server_error(resp);
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 }
             }
             Err(err) => {
                 error!("get_interior_ref_list api request error: {}", err);
-                from_file_cache(&body_cache_path)
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
             }
         }
     }
 
-    match inner(&api_url, &api_key, interior_ref_list_id) {
-        Ok(interior_ref_list) => {
-            let (interior_ref_ptr, interior_ref_len, interior_ref_cap) = interior_ref_list
-                .ref_list
-                .into_iter()
-                .map(RawInteriorRef::from)
-                .collect::<Vec<RawInteriorRef>>()
-                .into_raw_parts();
-            let (shelf_ptr, shelf_len, shelf_cap) = interior_ref_list
-                .shelves
-                .into_iter()
-                .map(RawShelf::from)
-                .collect::<Vec<RawShelf>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawInteriorRefData {
-                interior_ref_vec: RawInteriorRefVec {
-                    ptr: interior_ref_ptr,
-                    len: interior_ref_len,
-                    cap: interior_ref_cap,
-                },
-                shelf_vec: RawShelfVec {
-                    ptr: shelf_ptr,
-                    len: shelf_len,
-                    cap: shelf_cap,
-                },
-            })
-        }
-        Err(err) => {
-            error!("get_interior_ref_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn get_interior_ref_list_by_shop_id(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-) -> FFIResult<RawInteriorRefData> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!(
-        "get_interior_ref_list_by_shop_id api_url: {:?}, api_key: {:?}, shop_id: {:?}",
-        api_url, api_key, shop_id
-    );
-
-    fn inner(api_url: &str, api_key: &str, shop_id: i32) -> Result<SavedInteriorRefList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
+    pub fn get_interior_ref_list_by_shop_id(&self, shop_id: i32) -> Result<SavedInteriorRefList> {
+        let url = self.url(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
         info!("api_url: {:?}", url);
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}_interior_ref_list.bin", shop_id));
-        let metadata_cache_path =
-            cache_dir.join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
-        let mut request = client
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+        let mut request = self
+            .http
             .get(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
             if let Some(etag) = metadata.etag {
                 request = request.header("If-None-Match", etag);
             }
         }
 
-        match request.send() {
+        match self.send(request) {
             Ok(resp) => {
                 info!(
                     "get_interior_ref_list_by_shop_id response from api: {:?}",
-                    &resp
+                    resp
                 );
                 if resp.status().is_success() {
                     let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
-                    let saved_interior_ref_list = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+                    let bytes = resp.into_body();
+                    let saved_interior_ref_list: SavedInteriorRefList =
+                        bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_interior_ref_list.clone(),
+                    );
+                    update_file_caches::<SavedInteriorRefList>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
                     Ok(saved_interior_ref_list)
                 } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 } else {
                     log_server_error(resp);
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 }
             }
             Err(err) => {
@@ -589,51 +618,546 @@ pub extern "C" fn get_interior_ref_list_by_shop_id(
                     "get_interior_ref_list_by_shop_id api request error: {}",
                     err
                 );
-                from_file_cache(&body_cache_path)
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
             }
         }
     }
 
-    match inner(&api_url, &api_key, shop_id) {
-        Ok(interior_ref_list) => {
-            let (interior_ref_ptr, interior_ref_len, interior_ref_cap) = interior_ref_list
-                .ref_list
-                .into_iter()
-                .map(RawInteriorRef::from)
-                .collect::<Vec<RawInteriorRef>>()
-                .into_raw_parts();
-            let (shelf_ptr, shelf_len, shelf_cap) = interior_ref_list
-                .shelves
-                .into_iter()
-                .map(RawShelf::from)
-                .collect::<Vec<RawShelf>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawInteriorRefData {
-                interior_ref_vec: RawInteriorRefVec {
-                    ptr: interior_ref_ptr,
-                    len: interior_ref_len,
-                    cap: interior_ref_cap,
-                },
-                shelf_vec: RawShelfVec {
-                    ptr: shelf_ptr,
-                    len: shelf_len,
-                    cap: shelf_cap,
-                },
-            })
+    pub fn delete_interior_ref_list(&self, interior_ref_list_id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/interior_ref_lists/{}", interior_ref_list_id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!("delete_interior_ref_list response from api: {:?}", resp);
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            let body_cache_path = self
+                .cache_dir
+                .join(format!("interior_ref_list_{}.bin", interior_ref_list_id));
+            let metadata_cache_path = self.cache_dir.join(format!(
+                "interior_ref_list_{}_metadata.json",
+                interior_ref_list_id
+            ));
+            // the same list may also be cached under its shop
+            if let Ok(cached) = self
+                .memory_cache
+                .load::<SavedInteriorRefList>(&body_cache_path, &metadata_cache_path)
+            {
+                self.remove_from_caches(
+                    &self
+                        .cache_dir
+                        .join(format!("shop_{}_interior_ref_list.bin", cached.shop_id)),
+                    &self.cache_dir.join(format!(
+                        "shop_{}_interior_ref_list_metadata.json",
+                        cached.shop_id
+                    )),
+                );
+            }
+            self.remove_from_caches(&body_cache_path, &metadata_cache_path);
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
         }
-        Err(err) => {
-            error!("get_interior_ref_list_by_shop_id failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+    }
+
+    pub fn delete_interior_ref_list_by_shop_id(&self, shop_id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/shops/{}/interior_ref_list", shop_id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!(
+            "delete_interior_ref_list_by_shop_id response from api: {:?}",
+            resp
+        );
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            let body_cache_path = self
+                .cache_dir
+                .join(format!("shop_{}_interior_ref_list.bin", shop_id));
+            let metadata_cache_path = self
+                .cache_dir
+                .join(format!("shop_{}_interior_ref_list_metadata.json", shop_id));
+            self.remove_from_caches(&body_cache_path, &metadata_cache_path);
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
         }
     }
 }
 
+fn interior_ref_list_from_raw(
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> Result<InteriorRefList> {
+    let raw_interior_ref_slice = slice_from_raw(
+        raw_interior_ref_ptr,
+        raw_interior_ref_len,
+        "raw_interior_ref_ptr",
+    )?;
+    let raw_shelf_slice = slice_from_raw(raw_shelf_ptr, raw_shelf_len, "raw_shelf_ptr")?;
+    InteriorRefList::from_game(shop_id, raw_interior_ref_slice, raw_shelf_slice)
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_create_interior_ref_list(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> FFIResult<i32> {
+    catch_panic(|| {
+        info!(
+            "create_interior_ref_list shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}",
+            shop_id, raw_interior_ref_len, raw_shelf_len
+        );
+        let interior_ref_list = interior_ref_list_from_raw(
+            shop_id,
+            raw_interior_ref_ptr,
+            raw_interior_ref_len,
+            raw_shelf_ptr,
+            raw_shelf_len,
+        );
+        info!("created interior_ref_list from game: shop_id: {}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.create_interior_ref_list(&interior_ref_list?))
+        {
+            Ok(interior_ref_list) => FFIResult::Ok(interior_ref_list.id),
+            Err(err) => {
+                error!("create_interior_ref_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_create_interior_ref_list`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`.
+#[no_mangle]
+pub extern "C" fn client_create_interior_ref_list_with_idempotency_key(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+    idempotency_key: *const c_char,
+) -> FFIResult<i32> {
+    catch_panic(|| {
+        let idempotency_key = str_from_raw(idempotency_key, "idempotency_key");
+        info!(
+            "create_interior_ref_list_with_idempotency_key shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}, idempotency_key: {:?}",
+            shop_id, raw_interior_ref_len, raw_shelf_len, idempotency_key
+        );
+        let interior_ref_list = interior_ref_list_from_raw(
+            shop_id,
+            raw_interior_ref_ptr,
+            raw_interior_ref_len,
+            raw_shelf_ptr,
+            raw_shelf_len,
+        );
+
+        match client_from_ptr(client).and_then(|client| {
+            client.create_interior_ref_list_with_idempotency_key(
+                &interior_ref_list?,
+                &idempotency_key?,
+            )
+        }) {
+            Ok(interior_ref_list) => FFIResult::Ok(interior_ref_list.id),
+            Err(err) => {
+                error!(
+                    "create_interior_ref_list_with_idempotency_key failed. {}",
+                    err
+                );
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn create_interior_ref_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> FFIResult<i32> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_create_interior_ref_list(
+                client,
+                shop_id,
+                raw_interior_ref_ptr,
+                raw_interior_ref_len,
+                raw_shelf_ptr,
+                raw_shelf_len,
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_interior_ref_list(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> FFIResult<i32> {
+    catch_panic(|| {
+        info!(
+            "update_interior_ref_list shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}",
+            shop_id, raw_interior_ref_len, raw_shelf_len
+        );
+        let interior_ref_list = interior_ref_list_from_raw(
+            shop_id,
+            raw_interior_ref_ptr,
+            raw_interior_ref_len,
+            raw_shelf_ptr,
+            raw_shelf_len,
+        );
+        info!("created interior_ref_list from game: shop_id: {}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.update_interior_ref_list(shop_id, &interior_ref_list?))
+        {
+            Ok(interior_ref_list) => FFIResult::Ok(interior_ref_list.id),
+            Err(err) => {
+                error!("update_interior_ref_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn update_interior_ref_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> FFIResult<i32> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_update_interior_ref_list(
+                client,
+                shop_id,
+                raw_interior_ref_ptr,
+                raw_interior_ref_len,
+                raw_shelf_ptr,
+                raw_shelf_len,
+            )
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_get_interior_ref_list(
+    client: *const BazaarClient,
+    interior_ref_list_id: i32,
+) -> FFIResult<RawInteriorRefData> {
+    catch_panic(|| {
+        info!(
+            "get_interior_ref_list interior_ref_list_id: {:?}",
+            interior_ref_list_id
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.get_interior_ref_list(interior_ref_list_id))
+        {
+            Ok(interior_ref_list) => FFIResult::Ok(RawInteriorRefData::from(interior_ref_list)),
+            Err(err) => {
+                error!("get_interior_ref_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn get_interior_ref_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    interior_ref_list_id: i32,
+) -> FFIResult<RawInteriorRefData> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_get_interior_ref_list(client, interior_ref_list_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_interior_ref_list_by_shop_id(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> FFIResult<RawInteriorRefData> {
+    catch_panic(|| {
+        info!("get_interior_ref_list_by_shop_id shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.get_interior_ref_list_by_shop_id(shop_id))
+        {
+            Ok(interior_ref_list) => FFIResult::Ok(RawInteriorRefData::from(interior_ref_list)),
+            Err(err) => {
+                error!("get_interior_ref_list_by_shop_id failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_interior_ref_list_by_shop_id(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<RawInteriorRefData> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_get_interior_ref_list_by_shop_id(client, shop_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_interior_ref_list(
+    client: *const BazaarClient,
+    interior_ref_list_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!(
+            "delete_interior_ref_list interior_ref_list_id: {:?}",
+            interior_ref_list_id
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.delete_interior_ref_list(interior_ref_list_id))
+        {
+            Ok(()) => {
+                info!("delete_interior_ref_list successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_interior_ref_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_interior_ref_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    interior_ref_list_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_delete_interior_ref_list(client, interior_ref_list_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_interior_ref_list_by_shop_id(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!("delete_interior_ref_list_by_shop_id shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.delete_interior_ref_list_by_shop_id(shop_id))
+        {
+            Ok(()) => {
+                info!("delete_interior_ref_list_by_shop_id successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_interior_ref_list_by_shop_id failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_interior_ref_list_by_shop_id(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_delete_interior_ref_list_by_shop_id(client, shop_id)
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_create_interior_ref_list_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "create_interior_ref_list_async shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}",
+            shop_id, raw_interior_ref_len, raw_shelf_len
+        );
+        let interior_ref_list = interior_ref_list_from_raw(
+            shop_id,
+            raw_interior_ref_ptr,
+            raw_interior_ref_len,
+            raw_shelf_ptr,
+            raw_shelf_len,
+        );
+
+        submit_request(client, "create_interior_ref_list", move |client| {
+            RequestOutcome::InteriorRefListId(
+                interior_ref_list
+                    .and_then(|interior_ref_list| {
+                        client.create_interior_ref_list(&interior_ref_list)
+                    })
+                    .map(|interior_ref_list| interior_ref_list.id),
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_interior_ref_list_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_interior_ref_ptr: *const RawInteriorRef,
+    raw_interior_ref_len: usize,
+    raw_shelf_ptr: *const RawShelf,
+    raw_shelf_len: usize,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "update_interior_ref_list_async shop_id: {:?}, raw_interior_ref_len: {:?}, raw_shelf_len: {:?}",
+            shop_id, raw_interior_ref_len, raw_shelf_len
+        );
+        let interior_ref_list = interior_ref_list_from_raw(
+            shop_id,
+            raw_interior_ref_ptr,
+            raw_interior_ref_len,
+            raw_shelf_ptr,
+            raw_shelf_len,
+        );
+
+        submit_request(client, "update_interior_ref_list", move |client| {
+            RequestOutcome::InteriorRefListId(
+                interior_ref_list
+                    .and_then(|interior_ref_list| {
+                        client.update_interior_ref_list(shop_id, &interior_ref_list)
+                    })
+                    .map(|interior_ref_list| interior_ref_list.id),
+            )
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_get_interior_ref_list_async(
+    client: *const BazaarClient,
+    interior_ref_list_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "get_interior_ref_list_async interior_ref_list_id: {:?}",
+            interior_ref_list_id
+        );
+        submit_request(client, "get_interior_ref_list", move |client| {
+            RequestOutcome::InteriorRefList(client.get_interior_ref_list(interior_ref_list_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_interior_ref_list_by_shop_id_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "get_interior_ref_list_by_shop_id_async shop_id: {:?}",
+            shop_id
+        );
+        submit_request(client, "get_interior_ref_list_by_shop_id", move |client| {
+            RequestOutcome::InteriorRefList(client.get_interior_ref_list_by_shop_id(shop_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_interior_ref_list_async(
+    client: *const BazaarClient,
+    interior_ref_list_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "delete_interior_ref_list_async interior_ref_list_id: {:?}",
+            interior_ref_list_id
+        );
+        submit_request(client, "delete_interior_ref_list", move |client| {
+            RequestOutcome::Deleted(client.delete_interior_ref_list(interior_ref_list_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_interior_ref_list_by_shop_id_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "delete_interior_ref_list_by_shop_id_async shop_id: {:?}",
+            shop_id
+        );
+        submit_request(
+            client,
+            "delete_interior_ref_list_by_shop_id",
+            move |client| {
+                RequestOutcome::Deleted(client.delete_interior_ref_list_by_shop_id(shop_id))
+            },
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::CString;
+    use std::{ffi::CStr, ffi::CString, slice};
 
     use super::*;
+    use crate::client::test_client;
     use chrono::Utc;
     use mockito::mock;
 
@@ -681,8 +1205,7 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (interior_ref_ptr, interior_ref_len, _cap) = vec![RawInteriorRef {
             base_mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             base_local_form_id: 1,
@@ -714,9 +1237,8 @@ mod tests {
             sort_asc: true,
         }]
         .into_raw_parts();
-        let result = create_interior_ref_list(
-            api_url,
-            api_key,
+        let result = client_create_interior_ref_list(
+            &client,
             1,
             interior_ref_ptr,
             interior_ref_len,
@@ -728,17 +1250,7 @@ mod tests {
             FFIResult::Ok(interior_ref_list_id) => {
                 assert_eq!(interior_ref_list_id, 1);
             }
-            FFIResult::Err(error) => panic!(
-                "create_interior_ref_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("create_interior_ref_list returned error: {}", error),
         }
     }
 
@@ -749,8 +1261,7 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (interior_ref_ptr, interior_ref_len, _cap) = vec![RawInteriorRef {
             base_mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             base_local_form_id: 1,
@@ -782,9 +1293,8 @@ mod tests {
             sort_asc: true,
         }]
         .into_raw_parts();
-        let result = create_interior_ref_list(
-            api_url,
-            api_key,
+        let result = client_create_interior_ref_list(
+            &client,
             1,
             interior_ref_ptr,
             interior_ref_len,
@@ -854,8 +1364,7 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (interior_ref_ptr, interior_ref_len, _cap) = vec![RawInteriorRef {
             base_mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             base_local_form_id: 1,
@@ -887,9 +1396,8 @@ mod tests {
             sort_asc: true,
         }]
         .into_raw_parts();
-        let result = update_interior_ref_list(
-            api_url,
-            api_key,
+        let result = client_update_interior_ref_list(
+            &client,
             1,
             interior_ref_ptr,
             interior_ref_len,
@@ -901,17 +1409,7 @@ mod tests {
             FFIResult::Ok(interior_ref_list_id) => {
                 assert_eq!(interior_ref_list_id, 1);
             }
-            FFIResult::Err(error) => panic!(
-                "update_interior_ref_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("update_interior_ref_list returned error: {}", error),
         }
     }
 
@@ -922,8 +1420,7 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (interior_ref_ptr, interior_ref_len, _cap) = vec![RawInteriorRef {
             base_mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             base_local_form_id: 1,
@@ -955,9 +1452,8 @@ mod tests {
             sort_asc: true,
         }]
         .into_raw_parts();
-        let result = update_interior_ref_list(
-            api_url,
-            api_key,
+        let result = client_update_interior_ref_list(
+            &client,
             1,
             interior_ref_ptr,
             interior_ref_len,
@@ -1027,9 +1523,8 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_interior_ref_list(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_interior_ref_list(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_interior_ref_data) => {
@@ -1081,22 +1576,12 @@ mod tests {
                 assert_eq!(raw_shelf.angle_z, 0.);
                 assert_eq!(raw_shelf.scale, 1);
                 assert_eq!(raw_shelf.filter_form_type, 0);
-                assert_eq!(raw_shelf.filter_is_food, false);
+                assert!(!raw_shelf.filter_is_food);
                 assert_eq!(raw_shelf.search, std::ptr::null());
                 assert_eq!(raw_shelf.sort_on, std::ptr::null());
-                assert_eq!(raw_shelf.sort_asc, true);
+                assert!(raw_shelf.sort_asc);
             }
-            FFIResult::Err(error) => panic!(
-                "get_interior_ref_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("get_interior_ref_list returned error: {}", error),
         }
     }
 
@@ -1107,9 +1592,8 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_interior_ref_list(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_interior_ref_list(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_interior_ref_vec) => panic!(
@@ -1117,13 +1601,13 @@ mod tests {
                 raw_interior_ref_vec
             ),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
                         "Object not found in API or in cache: interior_ref_list_1.bin",
                     );
                 }
-                _ => panic!("get_interior_ref_list did not return a network error"),
+                _ => panic!("get_interior_ref_list did not return a cache miss error"),
             },
         }
     }
@@ -1172,9 +1656,8 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_interior_ref_list_by_shop_id(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_interior_ref_list_by_shop_id(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_interior_ref_data) => {
@@ -1226,22 +1709,14 @@ mod tests {
                 assert_eq!(raw_shelf.angle_z, 0.);
                 assert_eq!(raw_shelf.scale, 1);
                 assert_eq!(raw_shelf.filter_form_type, 0);
-                assert_eq!(raw_shelf.filter_is_food, false);
+                assert!(!raw_shelf.filter_is_food);
                 assert_eq!(raw_shelf.search, std::ptr::null());
                 assert_eq!(raw_shelf.sort_on, std::ptr::null());
-                assert_eq!(raw_shelf.sort_asc, true);
+                assert!(raw_shelf.sort_asc);
+            }
+            FFIResult::Err(error) => {
+                panic!("get_interior_ref_list_by_shop_id returned error: {}", error)
             }
-            FFIResult::Err(error) => panic!(
-                "get_interior_ref_list_by_shop_id returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
         }
     }
 
@@ -1252,9 +1727,8 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_interior_ref_list_by_shop_id(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_interior_ref_list_by_shop_id(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_interior_ref_vec) => panic!(
@@ -1262,13 +1736,145 @@ mod tests {
                 raw_interior_ref_vec
             ),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
                         "Object not found in API or in cache: shop_1_interior_ref_list.bin",
                     );
                 }
-                _ => panic!("get_interior_ref_list_by_shop_id did not return a network error"),
+                _ => panic!("get_interior_ref_list_by_shop_id did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_free_raw_interior_ref_data() {
+        let example = SavedInteriorRefList {
+            id: 1,
+            owner_id: 1,
+            shop_id: 1,
+            ref_list: vec![InteriorRef {
+                base_mod_name: "Skyrim.esm".to_string(),
+                base_local_form_id: 1,
+                ref_mod_name: None,
+                ref_local_form_id: 1,
+                position_x: 100.,
+                position_y: 0.,
+                position_z: 100.,
+                angle_x: 0.,
+                angle_y: 0.,
+                angle_z: 0.,
+                scale: 1,
+            }],
+            shelves: vec![Shelf {
+                shelf_type: 1,
+                position_x: 100.,
+                position_y: 0.,
+                position_z: 100.,
+                angle_x: 0.,
+                angle_y: 0.,
+                angle_z: 0.,
+                scale: 1,
+                page: 1,
+                filter_form_type: None,
+                filter_is_food: false,
+                search: Some("search".to_string()),
+                sort_on: None,
+                sort_asc: true,
+            }],
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let raw_interior_ref_data = RawInteriorRefData::from(example);
+        assert_eq!(raw_interior_ref_data.interior_ref_vec.len, 1);
+        assert_eq!(raw_interior_ref_data.shelf_vec.len, 1);
+        free_raw_interior_ref_data(raw_interior_ref_data);
+    }
+
+    #[test]
+    fn test_delete_interior_ref_list() {
+        let mock = mock("DELETE", "/v1/interior_ref_lists/1")
+            .with_status(204)
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_interior_ref_list(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!("delete_interior_ref_list returned error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_delete_interior_ref_list_server_error() {
+        let mock = mock("DELETE", "/v1/interior_ref_lists/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_interior_ref_list(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => {
+                panic!("delete_interior_ref_list returned Ok result: {:?}", success)
+            }
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
+                    );
+                }
+                _ => panic!("delete_interior_ref_list did not return a server error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_delete_interior_ref_list_by_shop_id() {
+        let mock = mock("DELETE", "/v1/shops/1/interior_ref_list")
+            .with_status(204)
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_interior_ref_list_by_shop_id(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!(
+                "delete_interior_ref_list_by_shop_id returned error: {:?}",
+                error
+            ),
+        }
+    }
+
+    #[test]
+    fn test_delete_interior_ref_list_by_shop_id_server_error() {
+        let mock = mock("DELETE", "/v1/shops/1/interior_ref_list")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_interior_ref_list_by_shop_id(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => panic!(
+                "delete_interior_ref_list_by_shop_id returned Ok result: {:?}",
+                success
+            ),
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
+                    );
+                }
+                _ => panic!("delete_interior_ref_list_by_shop_id did not return a server error"),
             },
         }
     }
@@ -1,10 +1,47 @@
+//! Client for the Bazaar Realm API. Built as a `cdylib` for the SKSE plugin, which calls the
+//! `extern "C"` functions declared in `bindings.h`, and as an `rlib` for Rust tools, which can use
+//! `BazaarClient` directly:
+//!
+//! ```no_run
+//! use BazaarRealmClient::{BazaarClient, ClientOptions};
+//!
+//! let client = BazaarClient::new("https://api.bazaarrealm.com", "api-key", ClientOptions::default())?;
+//! let shop = client.get_shop(1)?;
+//! println!("shop {} is called {}", shop.id, shop.name);
+//! # Ok::<(), BazaarRealmClient::Error>(())
+//! ```
 #![allow(non_snake_case)]
+// stable on newer toolchains, still needed on the nightly the plugin is built with
+#![allow(stable_features)]
 #![feature(vec_into_raw_parts)]
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::mem::{align_of, size_of};
 use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
 
-use reqwest::blocking::Response;
+use crate::panic::catch_panic;
+
+pub use crate::{
+    cache::{default_cache_root, set_default_cache_root, MemoryCacheStats},
+    client::{BazaarClient, ClientOptions},
+    error::{
+        CacheCorruptError, CacheMissError, ConflictError, Error, FieldError, InvalidArgumentError,
+        Result, ServerCopy, ServerError, ValidationError,
+    },
+    fake_api::{FakeApi, FAKE_API_URL},
+    freshness::{Fetched, Freshness, FreshnessSource},
+    interior_ref_list::{InteriorRef, InteriorRefList, SavedInteriorRefList, Shelf},
+    local_api::{default_local_store_path, LocalApi, Migration, LOCAL_API_KEY, LOCAL_API_URL},
+    logging::{init_logging, LogConfig},
+    merchandise_list::{Merchandise, MerchandiseList, SavedMerchandiseList},
+    owner::{Owner, SavedOwner},
+    retry::RetryPolicy,
+    shop::{SavedShop, Shop, ShopPage},
+    transaction::{SavedTransaction, Transaction},
+    transport::{ReqwestTransport, Response, Transport},
+};
 
 #[cfg(not(test))]
 use log::error;
@@ -15,24 +52,117 @@ use std::println as error;
 mod cache;
 mod client;
 mod error;
+mod fake_api;
+mod freshness;
+mod idempotency;
 mod interior_ref_list;
+mod local_api;
+mod logging;
 mod merchandise_list;
+mod outbox;
 mod owner;
+mod panic;
+mod request;
 mod result;
+mod retry;
 mod shop;
 mod transaction;
+mod transport;
 
-pub const API_VERSION: &'static str = "v1";
+pub const API_VERSION: &str = "v1";
 
 pub fn log_server_error(resp: Response) {
-    let status = resp.status();
-    if let Ok(text) = resp.text() {
-        error!("Server error: {} {}", status, text);
+    error!("Server error: {} {}", resp.status(), resp.text());
+}
+
+/// Converts a list of keywords into a C array of C strings. The array is allocated as a boxed
+/// slice so that its capacity always equals its length and it can be rebuilt from `(ptr, len)`
+/// alone by `free_raw_keywords`.
+pub(crate) fn keywords_into_raw(keywords: Vec<String>) -> (*mut *const c_char, usize) {
+    let keywords: Box<[*const c_char]> = keywords
+        .into_iter()
+        .map(|keyword| CString::new(keyword).unwrap_or_default().into_raw() as *const c_char)
+        .collect();
+    let len = keywords.len();
+    (Box::into_raw(keywords) as *mut *const c_char, len)
+}
+
+/// Reads a C string passed in from the plugin. Fails instead of dereferencing a null pointer.
+pub(crate) fn str_from_raw(ptr: *const c_char, arg_name: &str) -> Result<String> {
+    if ptr.is_null() {
+        return Err(Error::InvalidArgument(InvalidArgumentError::new(
+            arg_name, "is null",
+        )));
+    }
+    Ok(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string())
+}
+
+/// Reads a C string that the plugin passes as null to mean "none".
+pub(crate) fn optional_str_from_raw(ptr: *const c_char) -> Option<String> {
+    match ptr.is_null() {
+        true => None,
+        false => Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string()),
+    }
+}
+
+/// Reads a C array passed in from the plugin as a pointer and length. A null pointer is only
+/// accepted for an empty array.
+pub(crate) fn slice_from_raw<'a, T>(ptr: *const T, len: usize, arg_name: &str) -> Result<&'a [T]> {
+    if ptr.is_null() {
+        return match len {
+            0 => Ok(&[]),
+            _ => Err(Error::InvalidArgument(InvalidArgumentError::new(
+                arg_name,
+                &format!("is null but its length is {}", len),
+            ))),
+        };
+    }
+    if !(ptr as usize).is_multiple_of(align_of::<T>()) {
+        return Err(Error::InvalidArgument(InvalidArgumentError::new(
+            arg_name,
+            "is not aligned",
+        )));
+    }
+    if len.saturating_mul(size_of::<T>()) > isize::MAX as usize {
+        return Err(Error::InvalidArgument(InvalidArgumentError::new(
+            arg_name,
+            &format!("length {} is too large", len),
+        )));
+    }
+    Ok(unsafe { slice::from_raw_parts(ptr, len) })
+}
+
+pub(crate) fn keywords_from_raw(
+    ptr: *const *const c_char,
+    len: usize,
+    arg_name: &str,
+) -> Result<Vec<String>> {
+    slice_from_raw(ptr, len, arg_name)?
+        .iter()
+        .enumerate()
+        .map(|(i, &keyword)| str_from_raw(keyword, &format!("{}[{}]", arg_name, i)))
+        .collect()
+}
+
+pub(crate) unsafe fn free_raw_string(ptr: *const c_char) {
+    if !ptr.is_null() {
+        drop(CString::from_raw(ptr as *mut c_char));
+    }
+}
+
+pub(crate) unsafe fn free_raw_keywords(ptr: *mut *const c_char, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    let keywords = Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len));
+    for &keyword in keywords.iter() {
+        free_raw_string(keyword);
     }
-    error!("Server error: {}", status);
 }
 
+/// # Safety
+/// `ptr` must be a string returned by this library that hasn't been freed yet, or null.
 #[no_mangle]
-pub extern "C" fn free_string(ptr: *mut c_char) {
-    unsafe { drop(CString::from_raw(ptr)) }
+pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
+    catch_panic(|| free_raw_string(ptr))
 }
@@ -0,0 +1,495 @@
+use std::{
+    collections::BTreeMap,
+    fs::read,
+    io::ErrorKind,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{blocking::Request, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use log::{error, info};
+#[cfg(test)]
+use std::{println as info, println as error};
+
+use crate::{
+    cache::{
+        decode_cache_entry, default_cache_root, encode_cache_entry, write_file_atomically,
+        CacheSchema,
+    },
+    client::{client_from_ptr, BazaarClient, ClientOptions},
+    error::{CacheCorruptError, Error, InvalidArgumentError, Result, ServerError},
+    fake_api::{respond, ApiState},
+    interior_ref_list::InteriorRefList,
+    merchandise_list::MerchandiseList,
+    optional_str_from_raw,
+    panic::{catch_panic, install_panic_hook},
+    result::{FFIError, FFIResult},
+    shop::Shop,
+    transport::{Response, Transport},
+};
+
+/// `api_url` of clients made with `BazaarClient::local`. It only namespaces their file cache.
+pub const LOCAL_API_URL: &str = "http://local.bazaarrealm.invalid/";
+/// There is only one player in a local store, so every local client uses the same key.
+pub const LOCAL_API_KEY: &str = "local";
+
+/// `Data/SKSE/Plugins/BazaarRealmLocal.bin` relative to the working directory, which is the game
+/// folder when loaded by SKSE. It is kept out of the cache root since it is not a cache.
+pub fn default_local_store_path() -> PathBuf {
+    Path::new("Data")
+        .join("SKSE")
+        .join("Plugins")
+        .join("BazaarRealmLocal.bin")
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct LocalStore {
+    api: ApiState,
+    /// Server IDs of what `migrate_local_store` already uploaded, so running it again after it
+    /// failed part way doesn't create everything twice.
+    migrated_owner_id: Option<i32>,
+    migrated_shop_ids: BTreeMap<i32, i32>,
+}
+
+impl CacheSchema for LocalStore {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/// A missing store is an empty one. A corrupt store is an error rather than a fresh start, since
+/// it holds the only copy of the player's shops.
+fn read_store(path: &Path) -> Result<LocalStore> {
+    let contents = match read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(LocalStore::default()),
+        Err(err) => return Err(Error::Io(err)),
+    };
+    decode_cache_entry::<LocalStore>(&contents)
+        .and_then(|payload| {
+            bincode::deserialize(payload).map_err(|err| format!("failed to deserialize: {}", err))
+        })
+        .map_err(|reason| {
+            Error::CacheCorrupt(CacheCorruptError {
+                file_name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                reason,
+            })
+        })
+}
+
+fn write_store(path: &Path, store: &LocalStore) -> Result<()> {
+    let contents = encode_cache_entry::<LocalStore>(&bincode::serialize(store)?);
+    write_file_atomically(path, &contents).map_err(Error::from)
+}
+
+/// Single-player backend. It answers every endpoint the way the server does, from a store file
+/// that is rewritten after every change, so the client works the same with no server at all.
+///
+/// Only one `LocalApi` should have a given store open at a time.
+#[derive(Debug)]
+pub struct LocalApi {
+    path: PathBuf,
+    store: Mutex<LocalStore>,
+}
+
+impl LocalApi {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            store: Mutex::new(read_store(path)?),
+        })
+    }
+}
+
+impl Transport for LocalApi {
+    fn execute(&self, request: Request) -> Result<Response> {
+        let mut store = self
+            .store
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if request.method() == Method::GET {
+            return Ok(respond(&mut store.api, &request));
+        }
+        let previous = store.api.clone();
+        let resp = respond(&mut store.api, &request);
+        if resp.status().is_success() {
+            // a change that didn't make it to disk didn't happen
+            if let Err(err) = write_store(&self.path, &store) {
+                store.api = previous;
+                return Err(err);
+            }
+        }
+        Ok(resp)
+    }
+}
+
+/// Where `migrate_local_store` put the local owner and shops on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub owner_id: i32,
+    /// Server ID of every migrated shop, by its local ID.
+    pub shop_ids: BTreeMap<i32, i32>,
+}
+
+impl BazaarClient {
+    /// Client for the single-player backend, which keeps everything in the store at `store_path`.
+    pub fn local(store_path: &Path, options: ClientOptions, cache_root: &Path) -> Result<Self> {
+        Self::with_transport(
+            LOCAL_API_URL,
+            LOCAL_API_KEY,
+            options,
+            cache_root,
+            Arc::new(LocalApi::open(store_path)?),
+        )
+    }
+
+    /// Uploads the owner, shops and lists in the local store at `store_path` to this client's
+    /// server. Transactions are not uploaded: the gold and stock they moved are already part of the
+    /// shops and lists. Progress is saved to the store, so running this again after a failure only
+    /// uploads what is left.
+    ///
+    /// The store must not be open in a `LocalApi` while it is migrated.
+    pub fn migrate_local_store(&self, store_path: &Path) -> Result<Migration> {
+        let mut store = read_store(store_path)?;
+        let owner = match store.api.owners.values().next() {
+            Some(owner) => owner.clone(),
+            None => {
+                return Err(Error::InvalidArgument(InvalidArgumentError::new(
+                    "store_path",
+                    "has no owner to migrate",
+                )))
+            }
+        };
+
+        let owner_id = match store.migrated_owner_id {
+            Some(owner_id) => owner_id,
+            None => {
+                let owner_id = self.create_owner(&owner.name, owner.mod_version)?.id;
+                store.migrated_owner_id = Some(owner_id);
+                write_store(store_path, &store)?;
+                owner_id
+            }
+        };
+        info!("migrating local owner {} to owner {}", owner.id, owner_id);
+
+        let shops: Vec<_> = store
+            .api
+            .shops
+            .values()
+            .filter(|shop| shop.owner_id == owner.id)
+            .cloned()
+            .collect();
+        for shop in shops {
+            let shop_id = match store.migrated_shop_ids.get(&shop.id) {
+                Some(shop_id) => *shop_id,
+                None => {
+                    let description = shop.description.as_deref().unwrap_or_default();
+                    let shop_id = self.create_shop(&shop.name, description)?.id;
+                    store.migrated_shop_ids.insert(shop.id, shop_id);
+                    write_store(store_path, &store)?;
+                    shop_id
+                }
+            };
+            info!("migrating local shop {} to shop {}", shop.id, shop_id);
+
+            // everything after creating the shop is safe to send again if the last run stopped
+            // part way through
+            self.send_update_shop(
+                shop_id as u32,
+                &Shop {
+                    name: shop.name.clone(),
+                    owner_id: None,
+                    description: shop.description.clone(),
+                    gold: Some(shop.gold),
+                    shop_type: Some(shop.shop_type.clone()),
+                    vendor_keywords: Some(shop.vendor_keywords.clone()),
+                    vendor_keywords_exclude: Some(shop.vendor_keywords_exclude),
+                },
+                None,
+            )?;
+            if let Some(list) = store
+                .api
+                .merchandise_lists
+                .values()
+                .find(|list| list.shop_id == shop.id)
+            {
+                let merchandise_list = MerchandiseList {
+                    shop_id,
+                    owner_id: Some(owner_id),
+                    form_list: list.form_list.clone(),
+                };
+                match self.create_merchandise_list(&merchandise_list) {
+                    Err(err) if is_conflict(&err) => self
+                        .send_update_merchandise_list(shop_id, &merchandise_list, None)
+                        .map(|_| ()),
+                    result => result.map(|_| ()),
+                }?;
+            }
+            if let Some(list) = store
+                .api
+                .interior_ref_lists
+                .values()
+                .find(|list| list.shop_id == shop.id)
+            {
+                let interior_ref_list = InteriorRefList {
+                    shop_id,
+                    owner_id: Some(owner_id),
+                    ref_list: list.ref_list.clone(),
+                    shelves: list.shelves.clone(),
+                };
+                match self.create_interior_ref_list(&interior_ref_list) {
+                    Err(err) if is_conflict(&err) => self
+                        .send_update_interior_ref_list(shop_id, &interior_ref_list, None)
+                        .map(|_| ()),
+                    result => result.map(|_| ()),
+                }?;
+            }
+        }
+
+        Ok(Migration {
+            owner_id,
+            shop_ids: store.migrated_shop_ids,
+        })
+    }
+}
+
+/// The list was already created by an earlier run of the migration.
+fn is_conflict(err: &Error) -> bool {
+    matches!(err, Error::Server(ServerError { status, .. }) if *status == StatusCode::CONFLICT)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct RawMigratedShop {
+    pub local_id: i32,
+    pub server_id: i32,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct RawMigration {
+    pub owner_id: i32,
+    pub shops: *mut RawMigratedShop,
+    pub shops_len: usize,
+    pub shops_cap: usize,
+}
+
+impl From<Migration> for RawMigration {
+    fn from(migration: Migration) -> Self {
+        let (shops, shops_len, shops_cap) = migration
+            .shop_ids
+            .into_iter()
+            .map(|(local_id, server_id)| RawMigratedShop {
+                local_id,
+                server_id,
+            })
+            .collect::<Vec<RawMigratedShop>>()
+            .into_raw_parts();
+        Self {
+            owner_id: migration.owner_id,
+            shops,
+            shops_len,
+            shops_cap,
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_raw_migration(raw_migration: RawMigration) {
+    catch_panic(|| {
+        if raw_migration.shops.is_null() {
+            return;
+        }
+        unsafe {
+            Vec::from_raw_parts(
+                raw_migration.shops,
+                raw_migration.shops_len,
+                raw_migration.shops_cap,
+            )
+        };
+    })
+}
+
+/// Like `client_new_with_cache_root`, but the client uses the single-player backend, which keeps
+/// everything in the store at `store_path` instead of on a server. A null `store_path` uses
+/// `Data/SKSE/Plugins/BazaarRealmLocal.bin`.
+#[no_mangle]
+pub extern "C" fn client_new_local(
+    store_path: *const c_char,
+    cache_root: *const c_char,
+    options: ClientOptions,
+) -> FFIResult<*mut BazaarClient> {
+    install_panic_hook();
+    catch_panic(|| {
+        let store_path = optional_str_from_raw(store_path)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_local_store_path);
+        let cache_root = optional_str_from_raw(cache_root)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_cache_root);
+        info!(
+            "client_new_local store_path: {:?}, cache_root: {:?}, options: {:?}",
+            store_path, cache_root, options
+        );
+
+        match BazaarClient::local(&store_path, options, &cache_root) {
+            Ok(client) => FFIResult::Ok(Box::into_raw(Box::new(client))),
+            Err(err) => {
+                error!("client_new_local failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Uploads the local store at `store_path` (null for the default) to the server of `client`. See
+/// `BazaarClient::migrate_local_store`.
+#[no_mangle]
+pub extern "C" fn client_migrate_local_store(
+    client: *const BazaarClient,
+    store_path: *const c_char,
+) -> FFIResult<RawMigration> {
+    catch_panic(|| {
+        let store_path = optional_str_from_raw(store_path)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_local_store_path);
+        info!("migrate_local_store store_path: {:?}", store_path);
+
+        match client_from_ptr(client).and_then(|client| client.migrate_local_store(&store_path)) {
+            Ok(migration) => FFIResult::Ok(RawMigration::from(migration)),
+            Err(err) => {
+                error!("migrate_local_store failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use super::*;
+    use crate::{
+        fake_api::{fake_client, FakeApi},
+        merchandise_list::Merchandise,
+        transaction::Transaction,
+    };
+
+    fn local_client(store_path: &Path) -> (tempfile::TempDir, BazaarClient) {
+        let cache_root = tempfile::tempdir().unwrap();
+        let client =
+            BazaarClient::local(store_path, ClientOptions::default(), cache_root.path()).unwrap();
+        (cache_root, client)
+    }
+
+    fn example_merchandise_list(shop_id: i32) -> MerchandiseList {
+        MerchandiseList {
+            shop_id,
+            owner_id: None,
+            form_list: vec![Merchandise {
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Iron Sword".to_string(),
+                quantity: 3,
+                form_type: 41,
+                is_food: false,
+                price: 25,
+                keywords: vec!["VendorItemWeapon".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_local_store_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = dir.path().join("store.bin");
+        let (_cache_root, client) = local_client(&store_path);
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client
+            .create_merchandise_list(&example_merchandise_list(shop.id))
+            .unwrap();
+        client
+            .create_transaction(&Transaction {
+                shop_id: shop.id,
+                owner_id: None,
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Iron Sword".to_string(),
+                form_type: 41,
+                is_food: false,
+                price: 25,
+                is_sell: false,
+                quantity: 1,
+                amount: 25,
+                keywords: vec![],
+            })
+            .unwrap();
+        drop(client);
+
+        let (_cache_root, client) = local_client(&store_path);
+        assert_eq!(client.get_shop(shop.id).unwrap().gold, 25);
+        let merchandise_list = client.get_merchandise_list_by_shop_id(shop.id).unwrap();
+        assert_eq!(merchandise_list.form_list[0].quantity, 2);
+        let another_shop = client.create_shop("Another Shop", "description").unwrap();
+        assert!(another_shop.id > merchandise_list.id);
+    }
+
+    #[test]
+    fn test_corrupt_local_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = dir.path().join("store.bin");
+        write(&store_path, b"not a store").unwrap();
+        match LocalApi::open(&store_path) {
+            Err(Error::CacheCorrupt(error)) => assert_eq!(error.reason, "missing cache header"),
+            result => panic!("open did not return a corrupt error: {:?}", result),
+        }
+        // the store is left alone so the player's shops can still be recovered
+        assert_eq!(std::fs::read(&store_path).unwrap(), b"not a store");
+    }
+
+    #[test]
+    fn test_migrate_local_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = dir.path().join("store.bin");
+        let (_cache_root, local) = local_client(&store_path);
+        local.create_owner("Owner", 1).unwrap();
+        let shop = local.create_shop("Shop", "description").unwrap();
+        local
+            .update_shop(
+                shop.id as u32,
+                &Shop {
+                    gold: Some(1000),
+                    ..Shop::from_game("Shop", "description")
+                },
+            )
+            .unwrap();
+        local
+            .create_merchandise_list(&example_merchandise_list(shop.id))
+            .unwrap();
+        drop(local);
+
+        let fake = Arc::new(FakeApi::new());
+        let (_server_cache_root, client) = fake_client(&fake, "server-key");
+        let migration = client.migrate_local_store(&store_path).unwrap();
+        let server_shop_id = migration.shop_ids[&shop.id];
+        let server_shop = client.get_shop(server_shop_id).unwrap();
+        assert_eq!(server_shop.name, "Shop");
+        assert_eq!(server_shop.gold, 1000);
+        assert_eq!(server_shop.owner_id, migration.owner_id);
+        let merchandise_list = client
+            .get_merchandise_list_by_shop_id(server_shop_id)
+            .unwrap();
+        assert_eq!(merchandise_list.form_list[0].quantity, 3);
+
+        assert_eq!(client.migrate_local_store(&store_path).unwrap(), migration);
+        assert_eq!(client.list_shops().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    fs::{rename, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+    thread,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::cache::crc32;
+
+/// Log verbosity as passed in by the plugin. Each level includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub path: PathBuf,
+    pub level: LevelFilter,
+    /// Size in bytes at which the log file is rotated, or 0 to never rotate it.
+    pub max_size_bytes: u64,
+    /// Number of rotated files kept as `<path>.1` (newest) to `<path>.<max_files>` (oldest). With 0
+    /// the log file is just truncated when it fills up.
+    pub max_files: u32,
+}
+
+impl LogConfig {
+    pub fn with_path(path: PathBuf) -> Self {
+        Self {
+            path,
+            level: LevelFilter::Info,
+            max_size_bytes: 10 * 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+pub fn default_log_path() -> Option<PathBuf> {
+    dirs::document_dir().map(|document_dir| {
+        document_dir
+            .join("My Games")
+            .join("Skyrim Special Edition")
+            .join("SKSE")
+            .join("BazaarRealmClient.log")
+    })
+}
+
+/// Short, stable stand-in for an API key that is safe to print. Two log lines with the same
+/// fingerprint came from the same key.
+pub fn api_key_fingerprint(api_key: &str) -> String {
+    if api_key.is_empty() {
+        return "<none>".to_string();
+    }
+    format!("<key {:08x}>", crc32(api_key.as_bytes()))
+}
+
+// Every API key a client has been created with. Any of them showing up in a log message is
+// replaced with its fingerprint, so a key can't leak into a log no matter how it was formatted.
+static API_KEYS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn register_api_key(api_key: &str) {
+    if api_key.is_empty() {
+        return;
+    }
+    let mut api_keys = API_KEYS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !api_keys.iter().any(|known| known == api_key) {
+        api_keys.push(api_key.to_string());
+        // longest first, so a key that contains another key is redacted as a whole
+        api_keys.sort_by_key(|known| std::cmp::Reverse(known.len()));
+    }
+}
+
+pub fn redact_api_keys(message: &str) -> String {
+    let api_keys = API_KEYS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    api_keys
+        .iter()
+        .fold(message.to_string(), |message, api_key| {
+            message.replace(api_key.as_str(), &api_key_fingerprint(api_key))
+        })
+}
+
+struct LogFile {
+    config: LogConfig,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(config: LogConfig) -> Result<Self> {
+        if let Some(dir) = config.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create log directory {:?}", dir))?;
+        }
+        let file = open_append(&config.path)?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(Self { config, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.config.max_size_bytes > 0
+            && self.size > 0
+            && self.size + len > self.config.max_size_bytes
+        {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.config.path;
+        if self.config.max_files > 0 {
+            for index in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, index);
+                if from.exists() {
+                    rename(&from, rotated_path(path, index + 1))?;
+                }
+            }
+            rename(path, rotated_path(path, 1))?;
+            self.file = open_append(path)?;
+        } else {
+            self.file = File::create(path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+pub fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated_path = path.as_os_str().to_owned();
+    rotated_path.push(format!(".{}", index));
+    PathBuf::from(rotated_path)
+}
+
+struct FileLogger {
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "[{}] ({:?}) {:5} {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            thread::current().id(),
+            record.level(),
+            redact_api_keys(&record.args().to_string())
+        );
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(file) = file.as_mut() {
+            // there is nowhere left to report a failed log write
+            file.write_line(&line).ok();
+        }
+    }
+
+    fn flush(&self) {
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(file) = file.as_mut() {
+            file.file.flush().ok();
+        }
+    }
+}
+
+static LOGGER: FileLogger = FileLogger {
+    file: Mutex::new(None),
+};
+static SET_LOGGER: Once = Once::new();
+
+/// Sends all log output to the file in `config`. Can be called again to switch to another file or
+/// level.
+pub fn init_logging(config: LogConfig) -> Result<()> {
+    let level = config.level;
+    let log_file = LogFile::open(config)?;
+    let mut set_logger_result = Ok(());
+    SET_LOGGER.call_once(|| {
+        set_logger_result = log::set_logger(&LOGGER).map_err(|err| anyhow!("{}", err));
+    });
+    set_logger_result?;
+    *LOGGER
+        .file
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(log_file);
+    log::set_max_level(level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_fingerprint() {
+        let fingerprint = api_key_fingerprint("f2b8a1f4-6f3c-4d8b-9a43-3d3b8c3f0e21");
+        assert_eq!(
+            fingerprint,
+            api_key_fingerprint("f2b8a1f4-6f3c-4d8b-9a43-3d3b8c3f0e21")
+        );
+        assert!(!fingerprint.contains("f2b8a1f4"));
+        assert_eq!(api_key_fingerprint(""), "<none>");
+    }
+
+    #[test]
+    fn test_redact_api_keys() {
+        register_api_key("secret-api-key");
+        let message = redact_api_keys("client_new api_key: Some(\"secret-api-key\")");
+        assert_eq!(
+            message,
+            format!(
+                "client_new api_key: Some(\"{}\")",
+                api_key_fingerprint("secret-api-key")
+            )
+        );
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("client.log");
+        let mut log_file = LogFile::open(LogConfig {
+            max_size_bytes: 10,
+            max_files: 2,
+            ..LogConfig::with_path(path.clone())
+        })
+        .unwrap();
+        for line in &["first\n", "second\n", "third\n", "fourth\n"] {
+            log_file.write_line(line).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "second\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+    }
+}
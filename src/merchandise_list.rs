@@ -1,8 +1,7 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char, slice};
+use std::{convert::TryFrom, ffi::CString, os::raw::c_char};
 
-use anyhow::Result;
-use chrono::NaiveDateTime;
-use reqwest::{StatusCode, Url};
+use chrono::{NaiveDateTime, Utc};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(test))]
@@ -11,9 +10,23 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    cache::file_cache_dir, cache::from_file_cache, cache::load_metadata_from_file_cache,
-    cache::update_file_caches, error::extract_error_from_response, log_server_error,
+    cache::{update_file_caches, CacheSchema, Metadata},
+    client::{client_from_ptr, with_legacy_client, BazaarClient},
+    error::{
+        conflict_from_response, extract_error_from_response, InvalidArgumentError, Result,
+        ServerCopy,
+    },
+    free_raw_keywords, free_raw_string,
+    freshness::{
+        check_raw_freshness, write_raw_freshness, Fetched, Freshness, FreshnessSource, RawFreshness,
+    },
+    keywords_from_raw, keywords_into_raw, log_server_error,
+    outbox::Mutation,
+    panic::catch_panic,
+    request::{submit_request, RequestOutcome},
     result::{FFIError, FFIResult},
+    slice_from_raw, str_from_raw,
+    transaction::Transaction,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,7 +36,7 @@ pub struct MerchandiseList {
     pub form_list: Vec<Merchandise>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Merchandise {
     pub mod_name: String,
     pub local_form_id: u32,
@@ -36,42 +49,44 @@ pub struct Merchandise {
 }
 
 impl MerchandiseList {
-    pub fn from_game(shop_id: i32, merch_records: &[RawMerchandise]) -> Self {
+    pub fn from_game(shop_id: i32, merch_records: &[RawMerchandise]) -> Result<Self> {
         info!("MerchandiseList::from_game shop_id: {:?}", shop_id);
-        Self {
+        Ok(Self {
             shop_id,
             owner_id: None,
             form_list: merch_records
                 .iter()
-                .map(|rec| {
+                .enumerate()
+                .map(|(i, rec)| {
                     info!("MerchandiseList::from_game local_form_id: {:?} keywords_len: {:?} keywords.is_null(): {:?}", rec.local_form_id, rec.keywords_len, rec.keywords.is_null());
-                    Merchandise {
-                        mod_name: unsafe { CStr::from_ptr(rec.mod_name) }
-                            .to_string_lossy()
-                            .to_string(),
+                    Ok(Merchandise {
+                        mod_name: str_from_raw(
+                            rec.mod_name,
+                            &format!("raw_merchandise_ptr[{}].mod_name", i),
+                        )?,
                         local_form_id: rec.local_form_id,
-                        name: unsafe { CStr::from_ptr(rec.name) }
-                            .to_string_lossy()
-                            .to_string(),
+                        name: str_from_raw(rec.name, &format!("raw_merchandise_ptr[{}].name", i))?,
                         quantity: rec.quantity,
                         form_type: rec.form_type,
                         is_food: rec.is_food,
                         price: rec.price,
-                        keywords: match rec.keywords.is_null() {
-                            true => vec![],
-                            false => unsafe { slice::from_raw_parts(rec.keywords, rec.keywords_len) }
-                                .iter()
-                                .map(|&keyword| {
-                                    unsafe { CStr::from_ptr(keyword) }
-                                        .to_string_lossy()
-                                        .to_string()
-                                })
-                                .collect(),
-                        }
-                    }
+                        keywords: keywords_from_raw(
+                            rec.keywords,
+                            rec.keywords_len,
+                            &format!("raw_merchandise_ptr[{}].keywords", i),
+                        )?,
+                    })
                 })
-                .collect(),
-        }
+                .collect::<Result<Vec<Merchandise>>>()?,
+        })
+    }
+
+    /// Whether `merchandise_list` already has everything this update sets.
+    pub fn is_applied_to(&self, merchandise_list: &SavedMerchandiseList) -> bool {
+        self.owner_id
+            .iter()
+            .all(|&owner_id| owner_id == merchandise_list.owner_id)
+            && self.form_list == merchandise_list.form_list
     }
 }
 
@@ -85,6 +100,54 @@ pub struct SavedMerchandiseList {
     pub updated_at: NaiveDateTime,
 }
 
+impl CacheSchema for SavedMerchandiseList {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+impl SavedMerchandiseList {
+    /// Adjusts the stock for a transaction the same way the server does: selling to the shop adds
+    /// the item (or more of it), buying from the shop takes it away. Nothing is changed if a field
+    /// of the transaction is negative.
+    pub fn apply_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        let non_negative = |value: i32, arg_name: &str| {
+            u32::try_from(value)
+                .map_err(|_| InvalidArgumentError::new(arg_name, "must not be negative"))
+        };
+        let local_form_id = non_negative(transaction.local_form_id, "transaction.local_form_id")?;
+        let quantity = non_negative(transaction.quantity, "transaction.quantity")?;
+        let form_type = non_negative(transaction.form_type, "transaction.form_type")?;
+        let price = non_negative(transaction.price, "transaction.price")?;
+
+        let position = self.form_list.iter().position(|merchandise| {
+            merchandise.mod_name == transaction.mod_name
+                && merchandise.local_form_id == local_form_id
+        });
+        match (position, transaction.is_sell) {
+            (Some(position), true) => self.form_list[position].quantity += quantity,
+            (Some(position), false) => {
+                let merchandise = &mut self.form_list[position];
+                merchandise.quantity = merchandise.quantity.saturating_sub(quantity);
+                if merchandise.quantity == 0 {
+                    self.form_list.remove(position);
+                }
+            }
+            (None, true) => self.form_list.push(Merchandise {
+                mod_name: transaction.mod_name.clone(),
+                local_form_id,
+                name: transaction.name.clone(),
+                quantity,
+                form_type,
+                is_food: transaction.is_food,
+                price,
+                keywords: transaction.keywords.clone(),
+            }),
+            (None, false) => {}
+        }
+        self.updated_at = Utc::now().naive_utc();
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct RawMerchandise {
@@ -99,7 +162,28 @@ pub struct RawMerchandise {
     pub keywords_len: usize,
 }
 
-#[derive(Debug)]
+impl From<Merchandise> for RawMerchandise {
+    fn from(merchandise: Merchandise) -> Self {
+        let (keywords_ptr, keywords_len) = keywords_into_raw(merchandise.keywords);
+        Self {
+            mod_name: CString::new(merchandise.mod_name)
+                .unwrap_or_default()
+                .into_raw(),
+            local_form_id: merchandise.local_form_id,
+            name: CString::new(merchandise.name)
+                .unwrap_or_default()
+                .into_raw(),
+            quantity: merchandise.quantity,
+            form_type: merchandise.form_type,
+            is_food: merchandise.is_food,
+            price: merchandise.price,
+            keywords: keywords_ptr,
+            keywords_len,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 #[repr(C)]
 pub struct RawMerchandiseVec {
     pub ptr: *mut RawMerchandise,
@@ -107,433 +191,896 @@ pub struct RawMerchandiseVec {
     pub cap: usize,
 }
 
-// TODO: delete me if unused
+impl From<SavedMerchandiseList> for RawMerchandiseVec {
+    fn from(merchandise_list: SavedMerchandiseList) -> Self {
+        let (ptr, len, cap) = merchandise_list
+            .form_list
+            .into_iter()
+            .map(RawMerchandise::from)
+            .collect::<Vec<RawMerchandise>>()
+            .into_raw_parts();
+        Self { ptr, len, cap }
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn create_merchandise_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-    raw_merchandise_ptr: *const RawMerchandise,
-    raw_merchandise_len: usize,
-) -> FFIResult<RawMerchandiseVec> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!("create_merchandise_list api_url: {:?}, api_key: {:?}, shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}", api_url, api_key, shop_id, raw_merchandise_len, raw_merchandise_ptr);
-    let raw_merchandise_slice = match raw_merchandise_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_merchandise_ptr, raw_merchandise_len) },
-    };
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
-        shop_id: i32,
-        raw_merchandise_slice: &[RawMerchandise],
+pub extern "C" fn free_raw_merchandise_vec(raw_merchandise_vec: RawMerchandiseVec) {
+    catch_panic(|| {
+        if raw_merchandise_vec.ptr.is_null() {
+            return;
+        }
+        let raw_merchandise = unsafe {
+            Vec::from_raw_parts(
+                raw_merchandise_vec.ptr,
+                raw_merchandise_vec.len,
+                raw_merchandise_vec.cap,
+            )
+        };
+        for merchandise in raw_merchandise {
+            unsafe {
+                free_raw_string(merchandise.mod_name);
+                free_raw_string(merchandise.name);
+                free_raw_keywords(merchandise.keywords, merchandise.keywords_len);
+            }
+        }
+    })
+}
+
+impl BazaarClient {
+    pub fn create_merchandise_list(
+        &self,
+        merchandise_list: &MerchandiseList,
     ) -> Result<SavedMerchandiseList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/merchandise_lists")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/merchandise_lists")?;
+        let key = self.idempotency_key_for(
+            "v1/merchandise_lists",
+            &bincode::serialize(merchandise_list)?,
+        )?;
+        self.create_merchandise_list_with_idempotency_key(merchandise_list, &key)
+    }
 
-        let merchandise_list = MerchandiseList::from_game(shop_id, raw_merchandise_slice);
-        info!(
-            "created merchandise_list from game: shop_id: {}",
-            &merchandise_list.shop_id
-        );
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post(url)
-            .header("Api-Key", api_key)
-            .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&merchandise_list)?)
-            .send()?;
-        info!("create merchandise_list response from api: {:?}", &resp);
+    /// Like `create_merchandise_list`, but sent with `idempotency_key` instead of a new key, so
+    /// that sending it again with the same key can't create a list twice.
+    pub fn create_merchandise_list_with_idempotency_key(
+        &self,
+        merchandise_list: &MerchandiseList,
+        idempotency_key: &str,
+    ) -> Result<SavedMerchandiseList> {
+        let resp = self.send_post(
+            "v1/merchandise_lists",
+            bincode::serialize(merchandise_list)?,
+            idempotency_key,
+        )?;
+        info!("create merchandise_list response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_merchandise_list: SavedMerchandiseList = bincode::deserialize(&bytes)?;
-            let body_cache_path = cache_dir.join(format!(
+            let body_cache_path = self.cache_dir.join(format!(
                 "merchandise_list_{}.bin",
                 saved_merchandise_list.id
             ));
-            let metadata_cache_path = cache_dir.join(format!(
+            let metadata_cache_path = self.cache_dir.join(format!(
                 "merchandise_list_{}_metadata.json",
                 saved_merchandise_list.id
             ));
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_merchandise_list.clone(),
+            );
+            update_file_caches::<SavedMerchandiseList>(
+                body_cache_path,
+                metadata_cache_path,
+                bytes,
+                headers,
+            );
             Ok(saved_merchandise_list)
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, shop_id, raw_merchandise_slice) {
-        Ok(merchandise_list) => {
-            let (ptr, len, cap) = merchandise_list
-                .form_list
-                .into_iter()
-                .map(|merchandise| {
-                    let (keywords_ptr, keywords_len, _) = merchandise
-                        .keywords
-                        .into_iter()
-                        .map(|keyword| {
-                            CString::new(keyword).unwrap_or_default().into_raw() as *const c_char
-                        })
-                        .collect::<Vec<*const c_char>>()
-                        .into_raw_parts();
-                    RawMerchandise {
-                        mod_name: CString::new(merchandise.mod_name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        local_form_id: merchandise.local_form_id,
-                        name: CString::new(merchandise.name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        quantity: merchandise.quantity,
-                        form_type: merchandise.form_type,
-                        is_food: merchandise.is_food,
-                        price: merchandise.price,
-                        keywords: keywords_ptr,
-                        keywords_len,
-                    }
-                })
-                .collect::<Vec<RawMerchandise>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawMerchandiseVec { ptr, len, cap })
-        }
-        Err(err) => {
-            error!("create_merchandise_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
+    pub fn update_merchandise_list(
+        &self,
+        shop_id: i32,
+        merchandise_list: &MerchandiseList,
+    ) -> Result<SavedMerchandiseList> {
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+        self.send_or_queue(
+            || Mutation::UpdateMerchandiseList {
+                shop_id,
+                merchandise_list: merchandise_list.clone(),
+                base_etag: self.cached_etag(&body_cache_path, &metadata_cache_path),
+            },
+            || self.send_update_merchandise_list(shop_id, merchandise_list, None),
+            || self.update_merchandise_list_offline(shop_id, merchandise_list),
+        )
     }
-}
 
-#[no_mangle]
-pub extern "C" fn update_merchandise_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-    raw_merchandise_ptr: *const RawMerchandise,
-    raw_merchandise_len: usize,
-) -> FFIResult<RawMerchandiseVec> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!("update_merchandise_list api_url: {:?}, api_key: {:?}, shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}", api_url, api_key, shop_id, raw_merchandise_len, raw_merchandise_ptr);
-    let raw_merchandise_slice = match raw_merchandise_ptr.is_null() {
-        true => &[],
-        false => unsafe { slice::from_raw_parts(raw_merchandise_ptr, raw_merchandise_len) },
-    };
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
+    fn update_merchandise_list_offline(
+        &self,
         shop_id: i32,
-        raw_merchandise_slice: &[RawMerchandise],
+        merchandise_list: &MerchandiseList,
     ) -> Result<SavedMerchandiseList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}/merchandise_list", shop_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/shops/{}/merchandise_list", shop_id))?;
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+        let now = Utc::now().naive_utc();
+        let saved_merchandise_list = match self
+            .memory_cache
+            .load::<SavedMerchandiseList>(&body_cache_path, &metadata_cache_path)
+        {
+            Ok(cached) => SavedMerchandiseList {
+                form_list: merchandise_list.form_list.clone(),
+                updated_at: now,
+                ..cached
+            },
+            Err(_) => SavedMerchandiseList {
+                id: 0,
+                shop_id,
+                owner_id: merchandise_list.owner_id.unwrap_or(0),
+                form_list: merchandise_list.form_list.clone(),
+                created_at: now,
+                updated_at: now,
+            },
+        };
+        self.cache_offline_copy(
+            body_cache_path,
+            metadata_cache_path,
+            &saved_merchandise_list,
+        )?;
+        Ok(saved_merchandise_list)
+    }
 
-        let merchandise_list = MerchandiseList::from_game(shop_id, raw_merchandise_slice);
-        info!(
-            "created merchandise_list from game: shop_id: {}",
-            &merchandise_list.shop_id
-        );
-        let client = reqwest::blocking::Client::new();
-        let resp = client
+    pub(crate) fn send_update_merchandise_list(
+        &self,
+        shop_id: i32,
+        merchandise_list: &MerchandiseList,
+        base_etag: Option<&str>,
+    ) -> Result<SavedMerchandiseList> {
+        let url = self.url(&format!("v1/shops/{}/merchandise_list", shop_id))?;
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+        let request = self
+            .http
             .patch(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&merchandise_list)?)
-            .send()?;
-        info!("update merchandise_list response from api: {:?}", &resp);
-
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}_merchandise_list.bin", shop_id));
-        let metadata_cache_path =
-            cache_dir.join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+            .body(bincode::serialize(merchandise_list)?);
+        let request = self.if_match(request, base_etag, &body_cache_path, &metadata_cache_path);
+        let (resp, attempts) = self.send_counting_attempts(request)?;
+        info!("update merchandise_list response from api: {:?}", resp);
+
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
-            let saved_merchandise_list = bincode::deserialize(&bytes)?;
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            let saved_merchandise_list: SavedMerchandiseList = bincode::deserialize(&bytes)?;
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_merchandise_list.clone(),
+            );
+            update_file_caches::<SavedMerchandiseList>(
+                body_cache_path,
+                metadata_cache_path,
+                bytes,
+                headers,
+            );
             Ok(saved_merchandise_list)
+        } else if status == StatusCode::PRECONDITION_FAILED {
+            let current: Option<SavedMerchandiseList> = self.fetch_server_copy(
+                &format!("v1/shops/{}/merchandise_list", shop_id),
+                body_cache_path,
+                metadata_cache_path,
+            );
+            // a retry fails its precondition when the first attempt was applied
+            match current {
+                Some(current) if attempts > 1 && merchandise_list.is_applied_to(&current) => {
+                    Ok(current)
+                }
+                current => Err(conflict_from_response(
+                    status,
+                    &bytes,
+                    current.map(ServerCopy::MerchandiseList),
+                )),
+            }
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, shop_id, raw_merchandise_slice) {
-        Ok(merchandise_list) => {
-            let (ptr, len, cap) = merchandise_list
-                .form_list
-                .into_iter()
-                .map(|merchandise| {
-                    let (keywords_ptr, keywords_len, _) = merchandise
-                        .keywords
-                        .into_iter()
-                        .map(|keyword| {
-                            CString::new(keyword).unwrap_or_default().into_raw() as *const c_char
-                        })
-                        .collect::<Vec<*const c_char>>()
-                        .into_raw_parts();
-                    RawMerchandise {
-                        mod_name: CString::new(merchandise.mod_name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        local_form_id: merchandise.local_form_id,
-                        name: CString::new(merchandise.name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        quantity: merchandise.quantity,
-                        form_type: merchandise.form_type,
-                        is_food: merchandise.is_food,
-                        price: merchandise.price,
-                        keywords: keywords_ptr,
-                        keywords_len,
-                    }
-                })
-                .collect::<Vec<RawMerchandise>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawMerchandiseVec { ptr, len, cap })
-        }
-        Err(err) => {
-            error!("update_merchandise_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
-    }
-}
-
-// TODO: delete me if unused
-#[no_mangle]
-pub extern "C" fn get_merchandise_list(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    merchandise_list_id: i32,
-) -> FFIResult<RawMerchandiseVec> {
-    info!("get_merchandise_list begin");
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!(
-        "get_merchandise_list api_url: {:?}, api_key: {:?}, merchandise_list_id: {:?}",
-        api_url, api_key, merchandise_list_id
-    );
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
-        merchandise_list_id: i32,
-    ) -> Result<SavedMerchandiseList> {
-        #[cfg(not(test))]
-        let url =
-            Url::parse(api_url)?.join(&format!("v1/merchandise_lists/{}", merchandise_list_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/merchandise_lists/{}", merchandise_list_id))?;
+    pub fn get_merchandise_list(&self, merchandise_list_id: i32) -> Result<SavedMerchandiseList> {
+        let url = self.url(&format!("v1/merchandise_lists/{}", merchandise_list_id))?;
         info!("api_url: {:?}", url);
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path =
-            cache_dir.join(format!("merchandise_list_{}.bin", merchandise_list_id));
-        let metadata_cache_path = cache_dir.join(format!(
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("merchandise_list_{}.bin", merchandise_list_id));
+        let metadata_cache_path = self.cache_dir.join(format!(
             "merchandise_list_{}_metadata.json",
             merchandise_list_id
         ));
-        let mut request = client
+        let mut request = self
+            .http
             .get(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
             if let Some(etag) = metadata.etag {
                 request = request.header("If-None-Match", etag);
             }
         }
 
-        match request.send() {
+        match self.send(request) {
             Ok(resp) => {
-                info!("get_merchandise_list response from api: {:?}", &resp);
+                info!("get_merchandise_list response from api: {:?}", resp);
                 if resp.status().is_success() {
                     let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
-                    let saved_merchandise_list = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+                    let bytes = resp.into_body();
+                    let saved_merchandise_list: SavedMerchandiseList =
+                        bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_merchandise_list.clone(),
+                    );
+                    update_file_caches::<SavedMerchandiseList>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
                     Ok(saved_merchandise_list)
                 } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 } else {
                     log_server_error(resp);
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
                 }
             }
             Err(err) => {
                 error!("get_merchandise_list api request error: {}", err);
-                from_file_cache(&body_cache_path)
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
             }
         }
     }
 
-    match inner(&api_url, &api_key, merchandise_list_id) {
-        Ok(merchandise_list) => {
-            let (ptr, len, cap) = merchandise_list
-                .form_list
-                .into_iter()
-                .map(|merchandise| {
-                    let (keywords_ptr, keywords_len, _) = merchandise
-                        .keywords
-                        .into_iter()
-                        .map(|keyword| {
-                            CString::new(keyword).unwrap_or_default().into_raw() as *const c_char
-                        })
-                        .collect::<Vec<*const c_char>>()
-                        .into_raw_parts();
-                    RawMerchandise {
-                        mod_name: CString::new(merchandise.mod_name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        local_form_id: merchandise.local_form_id,
-                        name: CString::new(merchandise.name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        quantity: merchandise.quantity,
-                        form_type: merchandise.form_type,
-                        is_food: merchandise.is_food,
-                        price: merchandise.price,
-                        keywords: keywords_ptr,
-                        keywords_len,
-                    }
-                })
-                .collect::<Vec<RawMerchandise>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawMerchandiseVec { ptr, len, cap })
-        }
-        Err(err) => {
-            error!("get_merchandise_list failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
+    pub fn get_merchandise_list_by_shop_id(&self, shop_id: i32) -> Result<SavedMerchandiseList> {
+        self.get_merchandise_list_by_shop_id_with_freshness(shop_id)
+            .map(Fetched::into_value)
     }
-}
 
-#[no_mangle]
-pub extern "C" fn get_merchandise_list_by_shop_id(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-) -> FFIResult<RawMerchandiseVec> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!(
-        "get_merchandise_list_by_shop_id api_url: {:?}, api_key: {:?}, shop_id: {:?}",
-        api_url, api_key, shop_id
-    );
-
-    fn inner(api_url: &str, api_key: &str, shop_id: i32) -> Result<SavedMerchandiseList> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}/merchandise_list", shop_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?
-            .join(&format!("v1/shops/{}/merchandise_list", shop_id))?;
+    pub fn get_merchandise_list_by_shop_id_with_freshness(
+        &self,
+        shop_id: i32,
+    ) -> Result<Fetched<SavedMerchandiseList>> {
+        let url = self.url(&format!("v1/shops/{}/merchandise_list", shop_id))?;
         info!("api_url: {:?}", url);
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}_merchandise_list.bin", shop_id));
-        let metadata_cache_path =
-            cache_dir.join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
-        let mut request = client
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+        let mut request = self
+            .http
             .get(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
             if let Some(etag) = metadata.etag {
                 request = request.header("If-None-Match", etag);
             }
         }
 
-        match request.send() {
+        match self.send(request) {
             Ok(resp) => {
                 info!(
                     "get_merchandise_list_by_shop_id response from api: {:?}",
-                    &resp
+                    resp
                 );
                 if resp.status().is_success() {
                     let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
-                    let saved_merchandise_list = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
-                    Ok(saved_merchandise_list)
+                    let bytes = resp.into_body();
+                    let saved_merchandise_list: SavedMerchandiseList =
+                        bincode::deserialize(&bytes)?;
+                    let metadata = Metadata::from_headers(&headers);
+                    let freshness = Freshness::new(FreshnessSource::Network, Some(&metadata));
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        metadata,
+                        saved_merchandise_list.clone(),
+                    );
+                    update_file_caches::<SavedMerchandiseList>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(Fetched {
+                        value: saved_merchandise_list,
+                        freshness,
+                    })
                 } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::NotModified,
+                    )
                 } else {
                     log_server_error(resp);
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::OfflineFallback,
+                    )
                 }
             }
             Err(err) => {
                 error!("get_merchandise_list_by_shop_id api request error: {}", err);
-                from_file_cache(&body_cache_path)
+                self.memory_cache.load_fetched(
+                    &body_cache_path,
+                    &metadata_cache_path,
+                    FreshnessSource::OfflineFallback,
+                )
             }
         }
     }
 
-    match inner(&api_url, &api_key, shop_id) {
-        Ok(merchandise_list) => {
-            let (ptr, len, cap) = merchandise_list
-                .form_list
-                .into_iter()
-                .map(|merchandise| {
-                    let (keywords_ptr, keywords_len, _) = merchandise
-                        .keywords
-                        .into_iter()
-                        .map(|keyword| {
-                            CString::new(keyword).unwrap_or_default().into_raw() as *const c_char
-                        })
-                        .collect::<Vec<*const c_char>>()
-                        .into_raw_parts();
-                    RawMerchandise {
-                        mod_name: CString::new(merchandise.mod_name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        local_form_id: merchandise.local_form_id,
-                        name: CString::new(merchandise.name)
-                            .unwrap_or_default()
-                            .into_raw(),
-                        quantity: merchandise.quantity,
-                        form_type: merchandise.form_type,
-                        is_food: merchandise.is_food,
-                        price: merchandise.price,
-                        keywords: keywords_ptr,
-                        keywords_len,
-                    }
-                })
-                .collect::<Vec<RawMerchandise>>()
-                .into_raw_parts();
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawMerchandiseVec { ptr, len, cap })
+    pub fn delete_merchandise_list(&self, merchandise_list_id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/merchandise_lists/{}", merchandise_list_id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!("delete_merchandise_list response from api: {:?}", resp);
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            let body_cache_path = self
+                .cache_dir
+                .join(format!("merchandise_list_{}.bin", merchandise_list_id));
+            let metadata_cache_path = self.cache_dir.join(format!(
+                "merchandise_list_{}_metadata.json",
+                merchandise_list_id
+            ));
+            // the same list may also be cached under its shop
+            if let Ok(cached) = self
+                .memory_cache
+                .load::<SavedMerchandiseList>(&body_cache_path, &metadata_cache_path)
+            {
+                self.remove_from_caches(
+                    &self
+                        .cache_dir
+                        .join(format!("shop_{}_merchandise_list.bin", cached.shop_id)),
+                    &self.cache_dir.join(format!(
+                        "shop_{}_merchandise_list_metadata.json",
+                        cached.shop_id
+                    )),
+                );
+            }
+            self.remove_from_caches(&body_cache_path, &metadata_cache_path);
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
         }
-        Err(err) => {
-            error!("get_merchandise_list_by_shop_id failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+    }
+
+    pub fn delete_merchandise_list_by_shop_id(&self, shop_id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/shops/{}/merchandise_list", shop_id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!(
+            "delete_merchandise_list_by_shop_id response from api: {:?}",
+            resp
+        );
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            let body_cache_path = self
+                .cache_dir
+                .join(format!("shop_{}_merchandise_list.bin", shop_id));
+            let metadata_cache_path = self
+                .cache_dir
+                .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+            self.remove_from_caches(&body_cache_path, &metadata_cache_path);
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
         }
     }
 }
 
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_create_merchandise_list(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        info!(
+            "create_merchandise_list shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}",
+            shop_id, raw_merchandise_len, raw_merchandise_ptr
+        );
+        let merchandise_list = slice_from_raw(
+            raw_merchandise_ptr,
+            raw_merchandise_len,
+            "raw_merchandise_ptr",
+        )
+        .and_then(|raw_merchandise_slice| {
+            MerchandiseList::from_game(shop_id, raw_merchandise_slice)
+        });
+        info!("created merchandise_list from game: shop_id: {}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.create_merchandise_list(&merchandise_list?))
+        {
+            Ok(merchandise_list) => FFIResult::Ok(RawMerchandiseVec::from(merchandise_list)),
+            Err(err) => {
+                error!("create_merchandise_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_create_merchandise_list`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`.
+#[no_mangle]
+pub extern "C" fn client_create_merchandise_list_with_idempotency_key(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+    idempotency_key: *const c_char,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        let idempotency_key = str_from_raw(idempotency_key, "idempotency_key");
+        info!(
+            "create_merchandise_list_with_idempotency_key shop_id: {:?}, raw_merchandise_len: {:?}, idempotency_key: {:?}",
+            shop_id, raw_merchandise_len, idempotency_key
+        );
+        let merchandise_list = slice_from_raw(
+            raw_merchandise_ptr,
+            raw_merchandise_len,
+            "raw_merchandise_ptr",
+        )
+        .and_then(|raw_merchandise_slice| {
+            MerchandiseList::from_game(shop_id, raw_merchandise_slice)
+        });
+
+        match client_from_ptr(client).and_then(|client| {
+            client
+                .create_merchandise_list_with_idempotency_key(&merchandise_list?, &idempotency_key?)
+        }) {
+            Ok(merchandise_list) => FFIResult::Ok(RawMerchandiseVec::from(merchandise_list)),
+            Err(err) => {
+                error!(
+                    "create_merchandise_list_with_idempotency_key failed. {}",
+                    err
+                );
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn create_merchandise_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_create_merchandise_list(
+                client,
+                shop_id,
+                raw_merchandise_ptr,
+                raw_merchandise_len,
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_merchandise_list(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        info!(
+            "update_merchandise_list shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}",
+            shop_id, raw_merchandise_len, raw_merchandise_ptr
+        );
+        let merchandise_list = slice_from_raw(
+            raw_merchandise_ptr,
+            raw_merchandise_len,
+            "raw_merchandise_ptr",
+        )
+        .and_then(|raw_merchandise_slice| {
+            MerchandiseList::from_game(shop_id, raw_merchandise_slice)
+        });
+        info!("created merchandise_list from game: shop_id: {}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.update_merchandise_list(shop_id, &merchandise_list?))
+        {
+            Ok(merchandise_list) => FFIResult::Ok(RawMerchandiseVec::from(merchandise_list)),
+            Err(err) => {
+                error!("update_merchandise_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn update_merchandise_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_update_merchandise_list(
+                client,
+                shop_id,
+                raw_merchandise_ptr,
+                raw_merchandise_len,
+            )
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_get_merchandise_list(
+    client: *const BazaarClient,
+    merchandise_list_id: i32,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        info!(
+            "get_merchandise_list merchandise_list_id: {:?}",
+            merchandise_list_id
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.get_merchandise_list(merchandise_list_id))
+        {
+            Ok(merchandise_list) => FFIResult::Ok(RawMerchandiseVec::from(merchandise_list)),
+            Err(err) => {
+                error!("get_merchandise_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn get_merchandise_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    merchandise_list_id: i32,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_get_merchandise_list(client, merchandise_list_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_merchandise_list_by_shop_id(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        info!("get_merchandise_list_by_shop_id shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.get_merchandise_list_by_shop_id(shop_id))
+        {
+            Ok(merchandise_list) => FFIResult::Ok(RawMerchandiseVec::from(merchandise_list)),
+            Err(err) => {
+                error!("get_merchandise_list_by_shop_id failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_get_merchandise_list_by_shop_id`, but also writes whether the list came from the
+/// server or the cache to `freshness`, which may be null.
+#[no_mangle]
+pub extern "C" fn client_get_merchandise_list_by_shop_id_with_freshness(
+    client: *const BazaarClient,
+    shop_id: i32,
+    freshness: *mut RawFreshness,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        info!(
+            "get_merchandise_list_by_shop_id_with_freshness shop_id: {:?}",
+            shop_id
+        );
+
+        match check_raw_freshness(freshness)
+            .and_then(|_| client_from_ptr(client))
+            .and_then(|client| client.get_merchandise_list_by_shop_id_with_freshness(shop_id))
+        {
+            Ok(fetched) => {
+                write_raw_freshness(freshness, fetched.freshness);
+                FFIResult::Ok(RawMerchandiseVec::from(fetched.value))
+            }
+            Err(err) => {
+                error!(
+                    "get_merchandise_list_by_shop_id_with_freshness failed. {}",
+                    err
+                );
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_merchandise_list_by_shop_id(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<RawMerchandiseVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_get_merchandise_list_by_shop_id(client, shop_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_merchandise_list(
+    client: *const BazaarClient,
+    merchandise_list_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!(
+            "delete_merchandise_list merchandise_list_id: {:?}",
+            merchandise_list_id
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.delete_merchandise_list(merchandise_list_id))
+        {
+            Ok(()) => {
+                info!("delete_merchandise_list successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_merchandise_list failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_merchandise_list(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    merchandise_list_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_delete_merchandise_list(client, merchandise_list_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_merchandise_list_by_shop_id(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!("delete_merchandise_list_by_shop_id shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client)
+            .and_then(|client| client.delete_merchandise_list_by_shop_id(shop_id))
+        {
+            Ok(()) => {
+                info!("delete_merchandise_list_by_shop_id successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_merchandise_list_by_shop_id failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_merchandise_list_by_shop_id(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_delete_merchandise_list_by_shop_id(client, shop_id)
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_create_merchandise_list_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "create_merchandise_list_async shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}",
+            shop_id, raw_merchandise_len, raw_merchandise_ptr
+        );
+        let merchandise_list = slice_from_raw(
+            raw_merchandise_ptr,
+            raw_merchandise_len,
+            "raw_merchandise_ptr",
+        )
+        .and_then(|raw_merchandise_slice| {
+            MerchandiseList::from_game(shop_id, raw_merchandise_slice)
+        });
+
+        submit_request(client, "create_merchandise_list", move |client| {
+            RequestOutcome::MerchandiseList(
+                merchandise_list
+                    .and_then(|merchandise_list| client.create_merchandise_list(&merchandise_list)),
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_merchandise_list_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+    raw_merchandise_ptr: *const RawMerchandise,
+    raw_merchandise_len: usize,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "update_merchandise_list_async shop_id: {:?}, raw_merchandise_len: {:?}, raw_merchandise_ptr: {:?}",
+            shop_id, raw_merchandise_len, raw_merchandise_ptr
+        );
+        let merchandise_list = slice_from_raw(
+            raw_merchandise_ptr,
+            raw_merchandise_len,
+            "raw_merchandise_ptr",
+        )
+        .and_then(|raw_merchandise_slice| {
+            MerchandiseList::from_game(shop_id, raw_merchandise_slice)
+        });
+
+        submit_request(client, "update_merchandise_list", move |client| {
+            RequestOutcome::MerchandiseList(merchandise_list.and_then(|merchandise_list| {
+                client.update_merchandise_list(shop_id, &merchandise_list)
+            }))
+        })
+    })
+}
+
+// TODO: delete me if unused
+#[no_mangle]
+pub extern "C" fn client_get_merchandise_list_async(
+    client: *const BazaarClient,
+    merchandise_list_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "get_merchandise_list_async merchandise_list_id: {:?}",
+            merchandise_list_id
+        );
+        submit_request(client, "get_merchandise_list", move |client| {
+            RequestOutcome::MerchandiseList(client.get_merchandise_list(merchandise_list_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_merchandise_list_by_shop_id_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "get_merchandise_list_by_shop_id_async shop_id: {:?}",
+            shop_id
+        );
+        submit_request(client, "get_merchandise_list_by_shop_id", move |client| {
+            RequestOutcome::MerchandiseList(client.get_merchandise_list_by_shop_id(shop_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_merchandise_list_async(
+    client: *const BazaarClient,
+    merchandise_list_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "delete_merchandise_list_async merchandise_list_id: {:?}",
+            merchandise_list_id
+        );
+        submit_request(client, "delete_merchandise_list", move |client| {
+            RequestOutcome::Deleted(client.delete_merchandise_list(merchandise_list_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_merchandise_list_by_shop_id_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "delete_merchandise_list_by_shop_id_async shop_id: {:?}",
+            shop_id
+        );
+        submit_request(
+            client,
+            "delete_merchandise_list_by_shop_id",
+            move |client| {
+                RequestOutcome::Deleted(client.delete_merchandise_list_by_shop_id(shop_id))
+            },
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::CString;
+    use std::{ffi::CStr, ffi::CString, slice};
 
     use super::*;
+    use crate::{client::test_client, error::Error};
     use chrono::Utc;
     use mockito::mock;
 
@@ -565,8 +1112,7 @@ mod tests {
         let (keywords, keywords_len, _) =
             vec![CString::new("VendorItemWeapon").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (ptr, len, _cap) = vec![RawMerchandise {
             mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             local_form_id: 1,
@@ -579,7 +1125,7 @@ mod tests {
             keywords_len,
         }]
         .into_raw_parts();
-        let result = create_merchandise_list(api_url, api_key, 1, ptr, len);
+        let result = client_create_merchandise_list(&client, 1, ptr, len);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => {
@@ -604,20 +1150,10 @@ mod tests {
                 );
                 assert_eq!(raw_merchandise.quantity, 1);
                 assert_eq!(raw_merchandise.form_type, 1);
-                assert_eq!(raw_merchandise.is_food, false);
+                assert!(!raw_merchandise.is_food);
                 assert_eq!(raw_merchandise.price, 100);
             }
-            FFIResult::Err(error) => panic!(
-                "create_merchandise_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("create_merchandise_list returned error: {}", error),
         }
     }
 
@@ -631,8 +1167,7 @@ mod tests {
         let (keywords, keywords_len, _) =
             vec![CString::new("VendorItemWeapon").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (ptr, len, _cap) = vec![RawMerchandise {
             mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             local_form_id: 1,
@@ -645,7 +1180,7 @@ mod tests {
             keywords_len,
         }]
         .into_raw_parts();
-        let result = create_merchandise_list(api_url, api_key, 1, ptr, len);
+        let result = client_create_merchandise_list(&client, 1, ptr, len);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => panic!(
@@ -665,6 +1200,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_merchandise_list_invalid_arguments() {
+        let (_cache_root, client) = test_client();
+        let result = client_update_merchandise_list(&client, 1, std::ptr::null(), 2);
+        match result {
+            FFIResult::Err(FFIError::InvalidArgument { arg_name, reason }) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                    "raw_merchandise_ptr"
+                );
+                assert_eq!(
+                    unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                    "is null but its length is 2"
+                );
+            }
+            _ => panic!("update_merchandise_list did not return an invalid argument error"),
+        }
+
+        let (ptr, len, _cap) = vec![RawMerchandise {
+            mod_name: std::ptr::null(),
+            local_form_id: 1,
+            name: CString::new("Iron Sword").unwrap().into_raw(),
+            quantity: 1,
+            form_type: 1,
+            is_food: false,
+            price: 100,
+            keywords: std::ptr::null_mut(),
+            keywords_len: 0,
+        }]
+        .into_raw_parts();
+        let result = client_update_merchandise_list(&client, 1, ptr, len);
+        match result {
+            FFIResult::Err(FFIError::InvalidArgument { arg_name, reason }) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                    "raw_merchandise_ptr[0].mod_name"
+                );
+                assert_eq!(
+                    unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                    "is null"
+                );
+            }
+            _ => panic!("update_merchandise_list did not return an invalid argument error"),
+        }
+    }
+
     #[test]
     fn test_update_merchandise_list() {
         let example = SavedMerchandiseList {
@@ -693,8 +1274,7 @@ mod tests {
         let (keywords, keywords_len, _) =
             vec![CString::new("VendorItemWeapon").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (ptr, len, _cap) = vec![RawMerchandise {
             mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             local_form_id: 1,
@@ -707,7 +1287,7 @@ mod tests {
             keywords_len,
         }]
         .into_raw_parts();
-        let result = update_merchandise_list(api_url, api_key, 1, ptr, len);
+        let result = client_update_merchandise_list(&client, 1, ptr, len);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => {
@@ -732,20 +1312,10 @@ mod tests {
                 );
                 assert_eq!(raw_merchandise.quantity, 1);
                 assert_eq!(raw_merchandise.form_type, 1);
-                assert_eq!(raw_merchandise.is_food, false);
+                assert!(!raw_merchandise.is_food);
                 assert_eq!(raw_merchandise.price, 100);
             }
-            FFIResult::Err(error) => panic!(
-                "update_merchandise_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("update_merchandise_list returned error: {}", error),
         }
     }
 
@@ -759,8 +1329,7 @@ mod tests {
         let (keywords, keywords_len, _) =
             vec![CString::new("VendorItemWeapon").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let (ptr, len, _cap) = vec![RawMerchandise {
             mod_name: CString::new("Skyrim.esm").unwrap().into_raw(),
             local_form_id: 1,
@@ -773,7 +1342,7 @@ mod tests {
             keywords_len,
         }]
         .into_raw_parts();
-        let result = update_merchandise_list(api_url, api_key, 1, ptr, len);
+        let result = client_update_merchandise_list(&client, 1, ptr, len);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => panic!(
@@ -817,9 +1386,8 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_merchandise_list(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_merchandise_list(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => {
@@ -844,7 +1412,7 @@ mod tests {
                 );
                 assert_eq!(raw_merchandise.quantity, 1);
                 assert_eq!(raw_merchandise.form_type, 1);
-                assert_eq!(raw_merchandise.is_food, false);
+                assert!(!raw_merchandise.is_food);
                 assert_eq!(raw_merchandise.price, 100);
                 assert!(!raw_merchandise.keywords.is_null());
                 let keywords_slice = unsafe {
@@ -857,17 +1425,7 @@ mod tests {
                     "VendorItemWeapon".to_string(),
                 );
             }
-            FFIResult::Err(error) => panic!(
-                "get_merchandise_list returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("get_merchandise_list returned error: {}", error),
         }
     }
 
@@ -878,9 +1436,8 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_merchandise_list(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_merchandise_list(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => panic!(
@@ -888,13 +1445,13 @@ mod tests {
                 raw_merchandise_vec
             ),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
                         "Object not found in API or in cache: merchandise_list_1.bin",
                     );
                 }
-                _ => panic!("get_merchandise_list did not return a network error"),
+                _ => panic!("get_merchandise_list did not return a cache miss error"),
             },
         }
     }
@@ -924,9 +1481,8 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_merchandise_list_by_shop_id(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_merchandise_list_by_shop_id(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => {
@@ -951,7 +1507,7 @@ mod tests {
                 );
                 assert_eq!(raw_merchandise.quantity, 1);
                 assert_eq!(raw_merchandise.form_type, 1);
-                assert_eq!(raw_merchandise.is_food, false);
+                assert!(!raw_merchandise.is_food);
                 assert_eq!(raw_merchandise.price, 100);
                 assert!(!raw_merchandise.keywords.is_null());
                 let keywords_slice = unsafe {
@@ -964,17 +1520,9 @@ mod tests {
                     "VendorItemWeapon".to_string(),
                 );
             }
-            FFIResult::Err(error) => panic!(
-                "get_merchandise_list_by_shop_id returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => {
+                panic!("get_merchandise_list_by_shop_id returned error: {}", error)
+            }
         }
     }
 
@@ -985,9 +1533,8 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_merchandise_list_by_shop_id(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_merchandise_list_by_shop_id(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_merchandise_vec) => panic!(
@@ -995,13 +1542,182 @@ mod tests {
                 raw_merchandise_vec
             ),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
                         "Object not found in API or in cache: shop_1_merchandise_list.bin",
                     );
                 }
-                _ => panic!("get_merchandise_list_by_shop_id did not return a network error"),
+                _ => panic!("get_merchandise_list_by_shop_id did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_free_raw_merchandise_vec() {
+        let example = SavedMerchandiseList {
+            id: 1,
+            shop_id: 1,
+            owner_id: 1,
+            form_list: vec![Merchandise {
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Iron Sword".to_string(),
+                quantity: 1,
+                form_type: 1,
+                is_food: false,
+                price: 100,
+                keywords: vec!["VendorItemWeapon".to_string()],
+            }],
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let raw_merchandise_vec = RawMerchandiseVec::from(example);
+        assert_eq!(raw_merchandise_vec.len, 1);
+        let raw_merchandise = unsafe { &*raw_merchandise_vec.ptr };
+        assert_eq!(raw_merchandise.keywords_len, 1);
+        free_raw_merchandise_vec(raw_merchandise_vec);
+    }
+
+    #[test]
+    fn test_apply_transaction() {
+        let mut merchandise_list = SavedMerchandiseList {
+            id: 1,
+            shop_id: 1,
+            owner_id: 1,
+            form_list: vec![Merchandise {
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Item".to_string(),
+                quantity: 2,
+                form_type: 41,
+                is_food: false,
+                price: 100,
+                keywords: vec![],
+            }],
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let mut transaction = Transaction {
+            shop_id: 1,
+            owner_id: None,
+            mod_name: "Skyrim.esm".to_string(),
+            local_form_id: 1,
+            name: "Item".to_string(),
+            form_type: 41,
+            is_food: false,
+            price: 100,
+            is_sell: false,
+            quantity: 1,
+            amount: 100,
+            keywords: vec![],
+        };
+
+        merchandise_list.apply_transaction(&transaction).unwrap();
+        assert_eq!(merchandise_list.form_list[0].quantity, 1);
+        merchandise_list.apply_transaction(&transaction).unwrap();
+        assert!(merchandise_list.form_list.is_empty());
+
+        transaction.is_sell = true;
+        transaction.quantity = 3;
+        merchandise_list.apply_transaction(&transaction).unwrap();
+        assert_eq!(merchandise_list.form_list.len(), 1);
+        assert_eq!(merchandise_list.form_list[0].quantity, 3);
+        assert_eq!(merchandise_list.form_list[0].name, "Item");
+
+        // a negative field is rejected instead of wrapping around into a huge id or price
+        transaction.price = -1;
+        match merchandise_list.apply_transaction(&transaction) {
+            Err(Error::InvalidArgument(error)) => assert_eq!(error.arg_name, "transaction.price"),
+            result => panic!("apply_transaction accepted a negative price: {:?}", result),
+        }
+        assert_eq!(merchandise_list.form_list[0].quantity, 3);
+    }
+
+    #[test]
+    fn test_delete_merchandise_list() {
+        let mock = mock("DELETE", "/v1/merchandise_lists/1")
+            .with_status(204)
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_merchandise_list(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!("delete_merchandise_list returned error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_delete_merchandise_list_server_error() {
+        let mock = mock("DELETE", "/v1/merchandise_lists/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_merchandise_list(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => {
+                panic!("delete_merchandise_list returned Ok result: {:?}", success)
+            }
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
+                    );
+                }
+                _ => panic!("delete_merchandise_list did not return a server error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_delete_merchandise_list_by_shop_id() {
+        let mock = mock("DELETE", "/v1/shops/1/merchandise_list")
+            .with_status(204)
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_merchandise_list_by_shop_id(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!(
+                "delete_merchandise_list_by_shop_id returned error: {:?}",
+                error
+            ),
+        }
+    }
+
+    #[test]
+    fn test_delete_merchandise_list_by_shop_id_server_error() {
+        let mock = mock("DELETE", "/v1/shops/1/merchandise_list")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_merchandise_list_by_shop_id(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => panic!(
+                "delete_merchandise_list_by_shop_id returned Ok result: {:?}",
+                success
+            ),
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
+                    );
+                }
+                _ => panic!("delete_merchandise_list_by_shop_id did not return a server error"),
             },
         }
     }
@@ -0,0 +1,654 @@
+use std::{
+    fs::{read, remove_file},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use log::{error, info};
+#[cfg(test)]
+use std::{println as info, println as error};
+
+use crate::{
+    cache::{
+        decode_cache_entry, encode_cache_entry, update_file_caches, write_file_atomically,
+        CacheSchema, Metadata,
+    },
+    client::{client_from_ptr, BazaarClient},
+    error::{Error, Result},
+    interior_ref_list::InteriorRefList,
+    merchandise_list::MerchandiseList,
+    panic::catch_panic,
+    result::{free_ffi_error, FFIError, FFIResult},
+    shop::Shop,
+    transaction::Transaction,
+};
+
+/// A change that was made while the server couldn't be reached and still needs to be sent to it.
+///
+/// Updates keep the ETag of the server copy they were made to, and are sent with it as `If-Match`
+/// so they can't overwrite changes made on the server in the meantime.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Mutation {
+    /// The key is kept so the transaction is sent with the same one every time.
+    CreateTransaction {
+        transaction: Transaction,
+        idempotency_key: String,
+    },
+    UpdateMerchandiseList {
+        shop_id: i32,
+        merchandise_list: MerchandiseList,
+        base_etag: Option<String>,
+    },
+    UpdateInteriorRefList {
+        shop_id: i32,
+        interior_ref_list: InteriorRefList,
+        base_etag: Option<String>,
+    },
+    UpdateShop {
+        id: u32,
+        shop: Shop,
+        base_etag: Option<String>,
+    },
+}
+
+impl Mutation {
+    pub fn kind(&self) -> RawMutationKind {
+        match self {
+            Mutation::CreateTransaction { .. } => RawMutationKind::CreateTransaction,
+            Mutation::UpdateMerchandiseList { .. } => RawMutationKind::UpdateMerchandiseList,
+            Mutation::UpdateInteriorRefList { .. } => RawMutationKind::UpdateInteriorRefList,
+            Mutation::UpdateShop { .. } => RawMutationKind::UpdateShop,
+        }
+    }
+
+    pub fn shop_id(&self) -> i32 {
+        match self {
+            Mutation::CreateTransaction { transaction, .. } => transaction.shop_id,
+            Mutation::UpdateMerchandiseList { shop_id, .. } => *shop_id,
+            Mutation::UpdateInteriorRefList { shop_id, .. } => *shop_id,
+            Mutation::UpdateShop { id, .. } => *id as i32,
+        }
+    }
+
+    /// Cache file stem of what an update changes, `None` for mutations that aren't updates.
+    pub fn cache_file_stem(&self) -> Option<String> {
+        match self {
+            Mutation::CreateTransaction { .. } => None,
+            Mutation::UpdateMerchandiseList { shop_id, .. } => {
+                Some(format!("shop_{}_merchandise_list", shop_id))
+            }
+            Mutation::UpdateInteriorRefList { shop_id, .. } => {
+                Some(format!("shop_{}_interior_ref_list", shop_id))
+            }
+            Mutation::UpdateShop { id, .. } => Some(format!("shop_{}", id)),
+        }
+    }
+
+    pub fn base_etag(&self) -> Option<&str> {
+        match self {
+            Mutation::CreateTransaction { .. } => None,
+            Mutation::UpdateMerchandiseList { base_etag, .. }
+            | Mutation::UpdateInteriorRefList { base_etag, .. }
+            | Mutation::UpdateShop { base_etag, .. } => base_etag.as_deref(),
+        }
+    }
+
+    /// Whether sending it again can't apply it twice, which is needed to queue it after a timeout
+    /// since the server may have applied it anyway. Updates set the same fields every time, and a
+    /// transaction is only created once per idempotency key.
+    pub fn is_safe_to_resend(&self) -> bool {
+        match self {
+            Mutation::CreateTransaction {
+                idempotency_key, ..
+            } => !idempotency_key.is_empty(),
+            Mutation::UpdateMerchandiseList { .. }
+            | Mutation::UpdateInteriorRefList { .. }
+            | Mutation::UpdateShop { .. } => true,
+        }
+    }
+
+    fn base_etag_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Mutation::CreateTransaction { .. } => None,
+            Mutation::UpdateMerchandiseList { base_etag, .. }
+            | Mutation::UpdateInteriorRefList { base_etag, .. }
+            | Mutation::UpdateShop { base_etag, .. } => Some(base_etag),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxItem {
+    pub id: u64,
+    pub mutation: Mutation,
+    pub queued_at: DateTime<Utc>,
+}
+
+impl CacheSchema for OutboxItem {
+    const SCHEMA_VERSION: u32 = 3;
+}
+
+#[derive(Debug)]
+pub struct OutboxOutcome {
+    pub id: u64,
+    pub kind: RawMutationKind,
+    pub shop_id: i32,
+    pub result: Result<()>,
+}
+
+// The outbox file can be shared by several clients for the same API (the handle-less endpoint
+// functions each build their own), so every change re-reads it from disk under this lock.
+static OUTBOX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Mutations that failed to reach the server, persisted in order to `outbox.bin` in the cache
+/// directory. The file is removed once the outbox is empty.
+#[derive(Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    outcomes: Mutex<Vec<OutboxOutcome>>,
+}
+
+impl Outbox {
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            path: cache_dir.join("outbox.bin"),
+            outcomes: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn read_items(&self) -> anyhow::Result<Vec<OutboxItem>> {
+        let contents = match read(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err).context("Failed to read outbox"),
+        };
+        let payload = decode_cache_entry::<Vec<OutboxItem>>(&contents)
+            .map_err(|reason| anyhow!("Outbox is corrupt: {}", reason))?;
+        Ok(bincode::deserialize(payload)?)
+    }
+
+    fn write_items(&self, items: &[OutboxItem]) -> anyhow::Result<()> {
+        if items.is_empty() {
+            return match remove_file(&self.path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    Err(err).context("Failed to remove outbox")
+                }
+                _ => Ok(()),
+            };
+        }
+        let payload = bincode::serialize(items)?;
+        write_file_atomically(&self.path, &encode_cache_entry::<Vec<OutboxItem>>(&payload))
+            .context("Failed to write outbox")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.path.exists()
+    }
+
+    pub fn len(&self) -> anyhow::Result<usize> {
+        let _lock = OUTBOX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(self.read_items()?.len())
+    }
+
+    /// Queues `mutation`. An update without a base ETag that is queued behind another update of
+    /// the same thing was made to that one's offline copy, so it gets the same base ETag.
+    pub fn push(&self, mut mutation: Mutation) -> anyhow::Result<u64> {
+        let _lock = OUTBOX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut items = self.read_items()?;
+        let stem = mutation.cache_file_stem();
+        let queued_base_etag = items
+            .iter()
+            .rev()
+            .find(|item| stem.is_some() && item.mutation.cache_file_stem() == stem)
+            .and_then(|item| item.mutation.base_etag())
+            .map(str::to_string);
+        if let Some(base_etag @ None) = mutation.base_etag_mut() {
+            *base_etag = queued_base_etag;
+        }
+        let id = items.last().map_or(1, |item| item.id + 1);
+        items.push(OutboxItem {
+            id,
+            mutation,
+            queued_at: Utc::now(),
+        });
+        self.write_items(&items)?;
+        Ok(id)
+    }
+
+    /// Sends queued mutations in order with `send` until one fails to reach the server. Items the
+    /// server answered, successfully or not, are removed and their outcome recorded.
+    ///
+    /// `send` returns the ETag an update left on the server. The next queued update of the same
+    /// thing was made on top of it, so it is sent with that ETag instead of their shared base one.
+    /// If the update failed it keeps the base ETag and the server refuses it as well.
+    pub fn replay(&self, send: impl Fn(&Mutation) -> Result<Option<String>>) -> Result<()> {
+        let _lock = OUTBOX_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut items = self.read_items()?;
+        while let Some(item) = items.first() {
+            let result = send(&item.mutation);
+            if let Err(err) = &result {
+                if is_network_error(err) {
+                    info!(
+                        "server unreachable, stopping outbox replay at item {}",
+                        item.id
+                    );
+                    break;
+                }
+                error!(
+                    "outbox item {} was rejected by the server. {}",
+                    item.id, err
+                );
+            } else {
+                info!("outbox item {} sent", item.id);
+            }
+            let item = items.remove(0);
+            if let Ok(Some(etag)) = &result {
+                let stem = item.mutation.cache_file_stem();
+                let base_etag = item.mutation.base_etag().map(str::to_string);
+                if let Some(next_base_etag) = items
+                    .iter_mut()
+                    .find(|next| next.mutation.cache_file_stem() == stem)
+                    .and_then(|next| next.mutation.base_etag_mut())
+                    .filter(|next_base_etag| **next_base_etag == base_etag)
+                {
+                    *next_base_etag = Some(etag.clone());
+                }
+            }
+            self.outcomes.lock().unwrap().push(OutboxOutcome {
+                id: item.id,
+                kind: item.mutation.kind(),
+                shop_id: item.mutation.shop_id(),
+                result: result.map(|_| ()),
+            });
+            // written after every item so a crash mid-replay doesn't resend what already went out
+            self.write_items(&items)?;
+        }
+        Ok(())
+    }
+
+    pub fn take_outcomes(&self) -> Vec<OutboxOutcome> {
+        std::mem::take(&mut *self.outcomes.lock().unwrap())
+    }
+}
+
+/// Whether the request never got a response from the server, as opposed to the server rejecting it.
+pub fn is_network_error(err: &Error) -> bool {
+    match err {
+        Error::Timeout(_) | Error::ConnectionFailed(_) => true,
+        Error::Other(err) => err.is::<reqwest::Error>(),
+        _ => false,
+    }
+}
+
+impl BazaarClient {
+    /// Sends a mutation to the server, or queues it in the outbox and applies it to the local cache
+    /// with `apply_offline` if the server can't be reached. Mutations are also queued while earlier
+    /// ones are still waiting so that they reach the server in order.
+    ///
+    /// A mutation that timed out may have been applied, so unless it is safe to send again the
+    /// timeout is returned instead of queueing it. An error is never returned for a mutation that
+    /// was queued.
+    pub(crate) fn send_or_queue<T>(
+        &self,
+        mutation: impl FnOnce() -> Mutation,
+        send: impl FnOnce() -> Result<T>,
+        apply_offline: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let mutation = if self.outbox.is_empty() {
+            match send() {
+                Err(err) if is_network_error(&err) => {
+                    let mutation = mutation();
+                    if matches!(err, Error::Timeout(_)) && !mutation.is_safe_to_resend() {
+                        error!("request timed out and can't be sent again safely. {}", err);
+                        return Err(err);
+                    }
+                    error!("server unreachable, queueing change in outbox. {}", err);
+                    mutation
+                }
+                result => return result,
+            }
+        } else {
+            info!("outbox is not empty, queueing change behind it");
+            mutation()
+        };
+        // applied before it is queued, so a change that fails here is never sent later
+        let value = apply_offline()?;
+        let queued_key = match &mutation {
+            Mutation::CreateTransaction {
+                idempotency_key, ..
+            } => Some(idempotency_key.clone()),
+            _ => None,
+        };
+        let id = self.outbox.push(mutation)?;
+        info!("queued outbox item {}", id);
+        // the outbox item keeps the key now, so a create after a crash mustn't take it over
+        if let Some(key) = queued_key {
+            if let Err(err) = self.idempotency_keys.forget(&key) {
+                error!("failed to forget idempotency key {}. {}", key, err);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Checks the server is up and, if it is, sends everything that was queued while it wasn't.
+    /// A failed replay is only logged since the server itself is fine.
+    pub fn status_check_and_replay_outbox(&self) -> Result<()> {
+        self.status_check()?;
+        self.replay_outbox()
+            .map_err(|err| error!("Failed to replay outbox: {}", err))
+            .ok();
+        Ok(())
+    }
+
+    pub fn replay_outbox(&self) -> Result<()> {
+        if self.outbox.is_empty() {
+            return Ok(());
+        }
+        self.outbox.replay(|mutation| {
+            match mutation {
+                Mutation::CreateTransaction {
+                    transaction,
+                    idempotency_key,
+                } => self
+                    .send_create_transaction(transaction, idempotency_key)
+                    .map(|_| ()),
+                Mutation::UpdateMerchandiseList {
+                    shop_id,
+                    merchandise_list,
+                    base_etag,
+                } => self
+                    .send_update_merchandise_list(*shop_id, merchandise_list, base_etag.as_deref())
+                    .map(|_| ()),
+                Mutation::UpdateInteriorRefList {
+                    shop_id,
+                    interior_ref_list,
+                    base_etag,
+                } => self
+                    .send_update_interior_ref_list(
+                        *shop_id,
+                        interior_ref_list,
+                        base_etag.as_deref(),
+                    )
+                    .map(|_| ()),
+                Mutation::UpdateShop {
+                    id,
+                    shop,
+                    base_etag,
+                } => self
+                    .send_update_shop(*id, shop, base_etag.as_deref())
+                    .map(|_| ()),
+            }?;
+            Ok(mutation.cache_file_stem().and_then(|stem| {
+                self.cached_etag(
+                    &self.cache_dir.join(format!("{}.bin", stem)),
+                    &self.cache_dir.join(format!("{}_metadata.json", stem)),
+                )
+            }))
+        })
+    }
+
+    /// Stores a locally modified copy of a cached value. It is saved without an ETag so the next
+    /// request that reaches the server replaces it with the server's copy.
+    pub fn cache_offline_copy<T>(
+        &self,
+        body_cache_path: PathBuf,
+        metadata_cache_path: PathBuf,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: Serialize + CacheSchema + Clone + Send + Sync + 'static,
+    {
+        let bytes = Bytes::from(bincode::serialize(value)?);
+        let metadata = Metadata {
+            etag: None,
+            date: Some(Utc::now()),
+        };
+        self.memory_cache
+            .insert(&body_cache_path, metadata, value.clone());
+        update_file_caches::<T>(
+            body_cache_path,
+            metadata_cache_path,
+            bytes,
+            HeaderMap::new(),
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub enum RawMutationKind {
+    CreateTransaction,
+    UpdateMerchandiseList,
+    UpdateInteriorRefList,
+    UpdateShop,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct RawOutboxOutcome {
+    pub id: u64,
+    pub kind: RawMutationKind,
+    pub shop_id: i32,
+    pub result: FFIResult<bool>,
+}
+
+impl From<OutboxOutcome> for RawOutboxOutcome {
+    fn from(outcome: OutboxOutcome) -> Self {
+        Self {
+            id: outcome.id,
+            kind: outcome.kind,
+            shop_id: outcome.shop_id,
+            result: FFIResult::from_result(outcome.result.map(|()| true)),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct RawOutboxOutcomeVec {
+    pub ptr: *mut RawOutboxOutcome,
+    pub len: usize,
+    pub cap: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn free_raw_outbox_outcome_vec(raw_outcome_vec: RawOutboxOutcomeVec) {
+    catch_panic(|| {
+        if raw_outcome_vec.ptr.is_null() {
+            return;
+        }
+        let raw_outcomes = unsafe {
+            Vec::from_raw_parts(
+                raw_outcome_vec.ptr,
+                raw_outcome_vec.len,
+                raw_outcome_vec.cap,
+            )
+        };
+        for raw_outcome in raw_outcomes {
+            if let FFIResult::Err(error) = raw_outcome.result {
+                free_ffi_error(error);
+            }
+        }
+    })
+}
+
+/// Number of changes still waiting in the outbox to be sent, or -1 if it couldn't be read.
+#[no_mangle]
+pub extern "C" fn client_outbox_len(client: *const BazaarClient) -> i64 {
+    catch_panic(
+        || match client_from_ptr(client).and_then(|client| Ok(client.outbox.len()?)) {
+            Ok(len) => len as i64,
+            Err(err) => {
+                error!("outbox_len failed. {}", err);
+                -1
+            }
+        },
+    )
+}
+
+/// Returns the outcome of every outbox item replayed since the last call and forgets them.
+#[no_mangle]
+pub extern "C" fn client_take_outbox_outcomes(
+    client: *const BazaarClient,
+) -> FFIResult<RawOutboxOutcomeVec> {
+    catch_panic(|| match client_from_ptr(client) {
+        Ok(client) => {
+            let (ptr, len, cap) = client
+                .outbox
+                .take_outcomes()
+                .into_iter()
+                .map(RawOutboxOutcome::from)
+                .collect::<Vec<RawOutboxOutcome>>()
+                .into_raw_parts();
+            info!("take_outbox_outcomes returning {} outcomes", len);
+            FFIResult::Ok(RawOutboxOutcomeVec { ptr, len, cap })
+        }
+        Err(err) => {
+            error!("take_outbox_outcomes failed. {}", err);
+            FFIResult::Err(FFIError::from(err))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::fake_api::{fake_client, FakeApi};
+
+    fn update_shop_mutation(id: u32) -> Mutation {
+        Mutation::UpdateShop {
+            id,
+            shop: Shop::from_game("name", "description"),
+            base_etag: None,
+        }
+    }
+
+    #[test]
+    fn test_outbox_persists_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::new(dir.path());
+        assert!(outbox.is_empty());
+        assert_eq!(outbox.push(update_shop_mutation(1)).unwrap(), 1);
+        assert_eq!(outbox.push(update_shop_mutation(2)).unwrap(), 2);
+
+        let reopened = Outbox::new(dir.path());
+        assert_eq!(reopened.len().unwrap(), 2);
+        let items = reopened.read_items().unwrap();
+        assert_eq!(
+            items
+                .iter()
+                .map(|item| item.mutation.shop_id())
+                .collect::<Vec<i32>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_outbox_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::new(dir.path());
+        outbox.push(update_shop_mutation(1)).unwrap();
+        outbox.push(update_shop_mutation(2)).unwrap();
+
+        outbox
+            .replay(|mutation| match mutation.shop_id() {
+                1 => Ok(None),
+                _ => Err(Error::Other(anyhow!("rejected"))),
+            })
+            .unwrap();
+
+        assert!(outbox.is_empty());
+        let outcomes = outbox.take_outcomes();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].kind, RawMutationKind::UpdateShop);
+        assert!(outcomes[0].result.is_ok());
+        assert_eq!(outcomes[1].shop_id, 2);
+        assert!(outcomes[1].result.is_err());
+        assert!(outbox.take_outcomes().is_empty());
+    }
+
+    #[test]
+    fn test_change_that_fails_offline_is_not_queued() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        let (_other_cache_root, other_client) = fake_client(&fake, "owner-key");
+
+        // the other client never cached the shop, so there is nothing to apply the update to
+        fake.set_offline(true);
+        assert!(other_client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline", "description"))
+            .is_err());
+        assert!(other_client.outbox.is_empty());
+    }
+
+    #[test]
+    fn test_replayed_update_does_not_overwrite_newer_change() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_other_cache_root, other_client) = fake_client(&fake, "owner-key");
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client.get_shop(shop.id).unwrap();
+
+        fake.set_offline(true);
+        client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline", "description"))
+            .unwrap();
+        // a second offline edit is based on the first one's offline copy
+        client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline 2", "description"))
+            .unwrap();
+        fake.set_offline(false);
+        other_client
+            .update_shop(shop.id as u32, &Shop::from_game("Theirs", "description"))
+            .unwrap();
+
+        client.replay_outbox().unwrap();
+        let outcomes = client.outbox.take_outcomes();
+        assert_eq!(outcomes.len(), 2);
+        match &outcomes[0].result {
+            Err(Error::Conflict(_)) => {}
+            result => panic!("replay did not conflict: {:?}", result),
+        }
+        assert_eq!(other_client.get_shop(shop.id).unwrap().name, "Theirs");
+    }
+
+    #[test]
+    fn test_replayed_updates_chain() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client.get_shop(shop.id).unwrap();
+
+        fake.set_offline(true);
+        client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline", "description"))
+            .unwrap();
+        client
+            .update_shop(shop.id as u32, &Shop::from_game("Offline 2", "description"))
+            .unwrap();
+        fake.set_offline(false);
+
+        client.replay_outbox().unwrap();
+        assert!(client
+            .outbox
+            .take_outcomes()
+            .iter()
+            .all(|outcome| outcome.result.is_ok()));
+        assert_eq!(client.get_shop(shop.id).unwrap().name, "Offline 2");
+    }
+}
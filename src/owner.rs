@@ -1,8 +1,7 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char};
+use std::{ffi::CString, os::raw::c_char};
 
-use anyhow::Result;
 use chrono::NaiveDateTime;
-use reqwest::Url;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(test))]
@@ -11,10 +10,15 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    cache::file_cache_dir,
-    cache::update_file_caches,
-    error::extract_error_from_response,
+    cache::{update_file_caches, CacheSchema, Metadata},
+    client::{client_from_ptr, with_legacy_client, BazaarClient},
+    error::{conflict_from_response, extract_error_from_response, Result, ServerCopy},
+    free_raw_string, log_server_error,
+    panic::catch_panic,
+    request::{submit_request, RequestOutcome},
     result::{FFIError, FFIResult},
+    shop::SHOPS_PAGE_CACHE_PREFIX,
+    str_from_raw,
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,7 +27,7 @@ pub struct Owner {
     pub mod_version: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SavedOwner {
     pub id: i32,
     pub name: String,
@@ -32,13 +36,22 @@ pub struct SavedOwner {
     pub updated_at: NaiveDateTime,
 }
 
+impl CacheSchema for SavedOwner {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
 impl Owner {
     pub fn from_game(name: &str, mod_version: i32) -> Self {
         Self {
             name: name.to_string(),
-            mod_version: mod_version,
+            mod_version,
         }
     }
+
+    /// Whether `owner` already has everything this update sets.
+    pub fn is_applied_to(&self, owner: &SavedOwner) -> bool {
+        self.name == owner.name && self.mod_version == owner.mod_version
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -59,137 +72,551 @@ impl From<SavedOwner> for RawOwner {
     }
 }
 
+#[derive(Debug)]
+#[repr(C)]
+pub struct RawOwnerVec {
+    pub ptr: *mut RawOwner,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl From<Vec<SavedOwner>> for RawOwnerVec {
+    fn from(owners: Vec<SavedOwner>) -> Self {
+        let (ptr, len, cap) = owners
+            .into_iter()
+            .map(RawOwner::from)
+            .collect::<Vec<RawOwner>>()
+            .into_raw_parts();
+        Self { ptr, len, cap }
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn create_owner(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    name: *const c_char,
-    mod_version: i32,
-) -> FFIResult<RawOwner> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
-    info!(
-        "create_owner api_url: {:?}, api_key: {:?}, name: {:?}, mod_version: {:?}",
-        api_url, api_key, name, mod_version
-    );
-
-    fn inner(api_url: &str, api_key: &str, name: &str, mod_version: i32) -> Result<SavedOwner> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/owners")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/owners")?;
+pub extern "C" fn free_raw_owner(raw_owner: RawOwner) {
+    catch_panic(|| unsafe { free_raw_string(raw_owner.name) })
+}
 
+#[no_mangle]
+pub extern "C" fn free_raw_owner_vec(raw_owner_vec: RawOwnerVec) {
+    catch_panic(|| {
+        if raw_owner_vec.ptr.is_null() {
+            return;
+        }
+        let raw_owners =
+            unsafe { Vec::from_raw_parts(raw_owner_vec.ptr, raw_owner_vec.len, raw_owner_vec.cap) };
+        for raw_owner in raw_owners {
+            free_raw_owner(raw_owner);
+        }
+    })
+}
+
+impl BazaarClient {
+    pub fn create_owner(&self, name: &str, mod_version: i32) -> Result<SavedOwner> {
         let owner = Owner::from_game(name, mod_version);
-        info!("created owner from game: {:?}", &owner);
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post(url)
-            .header("Api-Key", api_key.clone())
-            .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&owner)?)
-            .send()?;
-        info!("create owner response from api: {:?}", &resp);
+        let key = self.idempotency_key_for("v1/owners", &bincode::serialize(&owner)?)?;
+        self.create_owner_with_idempotency_key(name, mod_version, &key)
+    }
+
+    /// Like `create_owner`, but sent with `idempotency_key` instead of a new key, so that sending
+    /// it again with the same key can't create an owner twice.
+    pub fn create_owner_with_idempotency_key(
+        &self,
+        name: &str,
+        mod_version: i32,
+        idempotency_key: &str,
+    ) -> Result<SavedOwner> {
+        let owner = Owner::from_game(name, mod_version);
+        info!("created owner from game: {:?}", owner);
+        let resp = self.send_post("v1/owners", bincode::serialize(&owner)?, idempotency_key)?;
+        info!("create owner response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_owner: SavedOwner = bincode::deserialize(&bytes)?;
-            let body_cache_path = cache_dir.join(format!("owner_{}.bin", saved_owner.id));
-            let metadata_cache_path =
-                cache_dir.join(format!("owner_{}_metadata.json", saved_owner.id));
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            let body_cache_path = self.cache_dir.join(format!("owner_{}.bin", saved_owner.id));
+            let metadata_cache_path = self
+                .cache_dir
+                .join(format!("owner_{}_metadata.json", saved_owner.id));
+            update_file_caches::<SavedOwner>(body_cache_path, metadata_cache_path, bytes, headers);
             Ok(saved_owner)
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, &name, mod_version) {
-        Ok(owner) => {
-            info!("create_owner successful");
-            FFIResult::Ok(RawOwner::from(owner))
+    pub fn get_owner(&self, id: i32) -> Result<SavedOwner> {
+        let url = self.url(&format!("v1/owners/{}", id))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self.cache_dir.join(format!("owner_{}.bin", id));
+        let metadata_cache_path = self.cache_dir.join(format!("owner_{}_metadata.json", id));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
         }
-        Err(err) => {
-            error!("create_owner failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+
+        match self.send(request) {
+            Ok(resp) => {
+                info!("get_owner response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_owner: SavedOwner = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_owner.clone(),
+                    );
+                    update_file_caches::<SavedOwner>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_owner)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("get_owner api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
         }
     }
-}
 
-#[no_mangle]
-pub extern "C" fn update_owner(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    id: i32,
-    name: *const c_char,
-    mod_version: i32,
-) -> FFIResult<RawOwner> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
-    info!(
-        "update_owner api_url: {:?}, api_key: {:?}, name: {:?}, mod_version: {:?}",
-        api_url, api_key, name, mod_version
-    );
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
-        id: i32,
-        name: &str,
-        mod_version: i32,
-    ) -> Result<SavedOwner> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/owners/{}", id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join(&format!("v1/owners/{}", id))?;
+    pub fn list_owners(&self, limit: i32, offset: i32) -> Result<Vec<SavedOwner>> {
+        let url = self.url(&format!("v1/owners?limit={}&offset={}", limit, offset))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("owners_limit_{}_offset_{}.bin", limit, offset));
+        let metadata_cache_path = self.cache_dir.join(format!(
+            "owners_limit_{}_offset_{}_metadata.json",
+            limit, offset
+        ));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
+        }
 
+        match self.send(request) {
+            Ok(resp) => {
+                info!("list_owners response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_owners: Vec<SavedOwner> = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_owners.clone(),
+                    );
+                    update_file_caches::<Vec<SavedOwner>>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_owners)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("list_owners api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
+        }
+    }
+
+    pub fn update_owner(&self, id: i32, name: &str, mod_version: i32) -> Result<SavedOwner> {
+        let url = self.url(&format!("v1/owners/{}", id))?;
+        let body_cache_path = self.cache_dir.join(format!("owner_{}.bin", id));
+        let metadata_cache_path = self.cache_dir.join(format!("owner_{}_metadata.json", id));
         let owner = Owner::from_game(name, mod_version);
-        info!("created owner from game: {:?}", &owner);
-        let client = reqwest::blocking::Client::new();
-        let resp = client
+        info!("created owner from game: {:?}", owner);
+        let request = self
+            .http
             .patch(url)
-            .header("Api-Key", api_key.clone())
+            .header("Api-Key", &self.api_key)
             .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&owner)?)
-            .send()?;
-        info!("update owner response from api: {:?}", &resp);
+            .body(bincode::serialize(&owner)?);
+        let request = self.if_match(request, None, &body_cache_path, &metadata_cache_path);
+        let (resp, attempts) = self.send_counting_attempts(request)?;
+        info!("update owner response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("owner_{}.bin", id));
-        let metadata_cache_path = cache_dir.join(format!("owner_{}_metadata.json", id));
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_owner: SavedOwner = bincode::deserialize(&bytes)?;
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_owner.clone(),
+            );
+            update_file_caches::<SavedOwner>(body_cache_path, metadata_cache_path, bytes, headers);
             Ok(saved_owner)
+        } else if status == StatusCode::PRECONDITION_FAILED {
+            let current: Option<SavedOwner> = self.fetch_server_copy(
+                &format!("v1/owners/{}", id),
+                body_cache_path,
+                metadata_cache_path,
+            );
+            // a retry fails its precondition when the first attempt was applied
+            match current {
+                Some(current) if attempts > 1 && owner.is_applied_to(&current) => Ok(current),
+                current => Err(conflict_from_response(
+                    status,
+                    &bytes,
+                    current.map(ServerCopy::Owner),
+                )),
+            }
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, id, &name, mod_version) {
-        Ok(owner) => {
-            info!("update_owner successful");
-            FFIResult::Ok(RawOwner::from(owner))
-        }
-        Err(err) => {
-            error!("update_owner failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+    pub fn delete_owner(&self, id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/owners/{}", id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!("delete_owner response from api: {:?}", resp);
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            let body_cache_path = self.cache_dir.join(format!("owner_{}.bin", id));
+            let metadata_cache_path = self.cache_dir.join(format!("owner_{}_metadata.json", id));
+            self.remove_from_caches(&body_cache_path, &metadata_cache_path);
+            // shops belonging to the owner are deleted with it
+            self.remove_prefixed_from_caches(SHOPS_PAGE_CACHE_PREFIX);
+            self.remove_from_caches(
+                &self.cache_dir.join(format!("shops_by_owner_{}.bin", id)),
+                &self
+                    .cache_dir
+                    .join(format!("shops_by_owner_{}_metadata.json", id)),
+            );
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
         }
     }
 }
 
+#[no_mangle]
+pub extern "C" fn client_create_owner(
+    client: *const BazaarClient,
+    name: *const c_char,
+    mod_version: i32,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        info!(
+            "create_owner name: {:?}, mod_version: {:?}",
+            name, mod_version
+        );
+
+        match client_from_ptr(client).and_then(|client| client.create_owner(&name?, mod_version)) {
+            Ok(owner) => {
+                info!("create_owner successful");
+                FFIResult::Ok(RawOwner::from(owner))
+            }
+            Err(err) => {
+                error!("create_owner failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_create_owner`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`, so the plugin can retry it itself without creating a second owner.
+#[no_mangle]
+pub extern "C" fn client_create_owner_with_idempotency_key(
+    client: *const BazaarClient,
+    name: *const c_char,
+    mod_version: i32,
+    idempotency_key: *const c_char,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        let idempotency_key = str_from_raw(idempotency_key, "idempotency_key");
+        info!(
+            "create_owner_with_idempotency_key name: {:?}, mod_version: {:?}, idempotency_key: {:?}",
+            name, mod_version, idempotency_key
+        );
+
+        match client_from_ptr(client).and_then(|client| {
+            client.create_owner_with_idempotency_key(&name?, mod_version, &idempotency_key?)
+        }) {
+            Ok(owner) => {
+                info!("create_owner_with_idempotency_key successful");
+                FFIResult::Ok(RawOwner::from(owner))
+            }
+            Err(err) => {
+                error!("create_owner_with_idempotency_key failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn create_owner(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    name: *const c_char,
+    mod_version: i32,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_create_owner(client, name, mod_version)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_owner(client: *const BazaarClient, id: i32) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        info!("get_owner id: {:?}", id);
+
+        match client_from_ptr(client).and_then(|client| client.get_owner(id)) {
+            Ok(owner) => FFIResult::Ok(RawOwner::from(owner)),
+            Err(err) => {
+                error!("get_owner failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_owner(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    id: i32,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| with_legacy_client(api_url, api_key, |client| client_get_owner(client, id)))
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_owners(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawOwnerVec> {
+    catch_panic(|| {
+        info!("list_owners limit: {:?}, offset: {:?}", limit, offset);
+
+        match client_from_ptr(client).and_then(|client| client.list_owners(limit, offset)) {
+            Ok(owners) => FFIResult::Ok(RawOwnerVec::from(owners)),
+            Err(err) => {
+                error!("list_owners failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn list_owners(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawOwnerVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_list_owners(client, limit, offset)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_owner(
+    client: *const BazaarClient,
+    id: i32,
+    name: *const c_char,
+    mod_version: i32,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        info!(
+            "update_owner id: {:?}, name: {:?}, mod_version: {:?}",
+            id, name, mod_version
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.update_owner(id, &name?, mod_version))
+        {
+            Ok(owner) => {
+                info!("update_owner successful");
+                FFIResult::Ok(RawOwner::from(owner))
+            }
+            Err(err) => {
+                error!("update_owner failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn update_owner(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    id: i32,
+    name: *const c_char,
+    mod_version: i32,
+) -> FFIResult<RawOwner> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_update_owner(client, id, name, mod_version)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_owner(client: *const BazaarClient, id: i32) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!("delete_owner id: {:?}", id);
+
+        match client_from_ptr(client).and_then(|client| client.delete_owner(id)) {
+            Ok(()) => {
+                info!("delete_owner successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_owner failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_owner(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| with_legacy_client(api_url, api_key, |client| client_delete_owner(client, id)))
+}
+
+#[no_mangle]
+pub extern "C" fn client_create_owner_async(
+    client: *const BazaarClient,
+    name: *const c_char,
+    mod_version: i32,
+) -> u64 {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        info!(
+            "create_owner_async name: {:?}, mod_version: {:?}",
+            name, mod_version
+        );
+
+        submit_request(client, "create_owner", move |client| {
+            RequestOutcome::Owner(name.and_then(|name| client.create_owner(&name, mod_version)))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_owner_async(client: *const BazaarClient, id: i32) -> u64 {
+    catch_panic(|| {
+        info!("get_owner_async id: {:?}", id);
+        submit_request(client, "get_owner", move |client| {
+            RequestOutcome::Owner(client.get_owner(id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_owners_async(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!("list_owners_async limit: {:?}, offset: {:?}", limit, offset);
+        submit_request(client, "list_owners", move |client| {
+            RequestOutcome::Owners(client.list_owners(limit, offset))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_owner_async(
+    client: *const BazaarClient,
+    id: i32,
+    name: *const c_char,
+    mod_version: i32,
+) -> u64 {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        info!(
+            "update_owner_async id: {:?}, name: {:?}, mod_version: {:?}",
+            id, name, mod_version
+        );
+
+        submit_request(client, "update_owner", move |client| {
+            RequestOutcome::Owner(name.and_then(|name| client.update_owner(id, &name, mod_version)))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_owner_async(client: *const BazaarClient, id: i32) -> u64 {
+    catch_panic(|| {
+        info!("delete_owner_async id: {:?}", id);
+        submit_request(client, "delete_owner", move |client| {
+            RequestOutcome::Deleted(client.delete_owner(id))
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::CString;
+    use std::ffi::{CStr, CString};
 
     use super::*;
+    use crate::client::test_client;
     use chrono::Utc;
     use mockito::mock;
 
@@ -208,11 +635,10 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let mod_version = 1;
-        let result = create_owner(api_url, api_key, name, mod_version);
+        let result = client_create_owner(&client, name, mod_version);
         mock.assert();
         match result {
             FFIResult::Ok(raw_owner) => {
@@ -223,17 +649,7 @@ mod tests {
                 );
                 assert_eq!(raw_owner.mod_version, 1);
             }
-            FFIResult::Err(error) => panic!(
-                "create_owner returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("create_owner returned error: {}", error),
         }
     }
 
@@ -244,11 +660,10 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let mod_version = 1;
-        let result = create_owner(api_url, api_key, name, mod_version);
+        let result = client_create_owner(&client, name, mod_version);
         mock.assert();
         match result {
             FFIResult::Ok(raw_owner) => {
@@ -267,6 +682,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_owner() {
+        let example = SavedOwner {
+            id: 1,
+            name: "name".to_string(),
+            mod_version: 1,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let mock = mock("GET", "/v1/owners/1")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&example).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_get_owner(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_owner) => {
+                assert_eq!(raw_owner.id, 1);
+                assert_eq!(
+                    unsafe { CStr::from_ptr(raw_owner.name).to_string_lossy() },
+                    "name"
+                );
+                assert_eq!(raw_owner.mod_version, 1);
+            }
+            FFIResult::Err(error) => panic!("get_owner returned error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_get_owner_server_error() {
+        let mock = mock("GET", "/v1/owners/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_get_owner(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_owner) => panic!("get_owner returned Ok result: {:#?}", raw_owner),
+            FFIResult::Err(error) => match error {
+                FFIError::CacheMiss(cache_miss_error) => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
+                        "Object not found in API or in cache: owner_1.bin",
+                    );
+                }
+                _ => panic!("get_owner did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_list_owners() {
+        let example = vec![SavedOwner {
+            id: 1,
+            name: "name".to_string(),
+            mod_version: 1,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }];
+        let mock = mock("GET", "/v1/owners?limit=10&offset=0")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&example).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_list_owners(&client, 10, 0);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_owner_vec) => {
+                assert_eq!(raw_owner_vec.len, 1);
+                let raw_owner = unsafe { &*raw_owner_vec.ptr };
+                assert_eq!(raw_owner.id, 1);
+                free_raw_owner_vec(raw_owner_vec);
+            }
+            FFIResult::Err(error) => panic!("list_owners returned error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_free_raw_owner_vec() {
+        let example = vec![SavedOwner {
+            id: 1,
+            name: "name".to_string(),
+            mod_version: 1,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }];
+        let raw_owner_vec = RawOwnerVec::from(example);
+        assert_eq!(raw_owner_vec.len, 1);
+        free_raw_owner_vec(raw_owner_vec);
+    }
+
     #[test]
     fn test_update_owner() {
         let example = SavedOwner {
@@ -282,11 +795,10 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let mod_version = 1;
-        let result = update_owner(api_url, api_key, 1, name, mod_version);
+        let result = client_update_owner(&client, 1, name, mod_version);
         mock.assert();
         match result {
             FFIResult::Ok(raw_owner) => {
@@ -297,17 +809,7 @@ mod tests {
                 );
                 assert_eq!(raw_owner.mod_version, 1);
             }
-            FFIResult::Err(error) => panic!(
-                "update_owner returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("update_owner returned error: {}", error),
         }
     }
 
@@ -318,11 +820,10 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let mod_version = 1;
-        let result = update_owner(api_url, api_key, 1, name, mod_version);
+        let result = client_update_owner(&client, 1, name, mod_version);
         mock.assert();
         match result {
             FFIResult::Ok(raw_owner) => {
@@ -340,4 +841,42 @@ mod tests {
             },
         }
     }
+
+    #[test]
+    fn test_delete_owner() {
+        let mock = mock("DELETE", "/v1/owners/1").with_status(204).create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_owner(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!("delete_owner returned error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_delete_owner_server_error() {
+        let mock = mock("DELETE", "/v1/owners/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_owner(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => panic!("delete_owner returned Ok result: {:?}", success),
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
+                    );
+                }
+                _ => panic!("delete_owner did not return a server error"),
+            },
+        }
+    }
 }
@@ -0,0 +1,148 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr::null_mut,
+    sync::Once,
+};
+
+#[cfg(not(test))]
+use log::error;
+#[cfg(test)]
+use std::println as error;
+
+use crate::{
+    cache::MemoryCacheStats,
+    request::FFIRequestResult,
+    result::{error_string_into_raw, FFIError, FFIErrorCode, FFIResult},
+};
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Logs every panic with its location and a backtrace. Nothing the game loads prints to a console,
+/// so without this the client log would be the only trace of a panic and it would say nothing.
+pub fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            error!("{}\n{}", info, Backtrace::force_capture());
+            default_hook(info);
+        }));
+    });
+}
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// What an exported function returns to the plugin when its body panicked.
+pub trait FromPanic {
+    fn from_panic(message: String) -> Self;
+}
+
+impl FromPanic for () {
+    fn from_panic(_message: String) -> Self {}
+}
+
+impl FromPanic for bool {
+    fn from_panic(_message: String) -> Self {
+        false
+    }
+}
+
+/// Request IDs start at 1, so 0 already means the request was never queued.
+impl FromPanic for u64 {
+    fn from_panic(_message: String) -> Self {
+        0
+    }
+}
+
+impl FromPanic for i64 {
+    fn from_panic(_message: String) -> Self {
+        -1
+    }
+}
+
+impl FromPanic for *mut c_char {
+    fn from_panic(_message: String) -> Self {
+        null_mut()
+    }
+}
+
+impl FromPanic for MemoryCacheStats {
+    fn from_panic(_message: String) -> Self {
+        MemoryCacheStats::default()
+    }
+}
+
+impl FromPanic for FFIError {
+    fn from_panic(message: String) -> Self {
+        FFIError::Panic(error_string_into_raw(&message))
+    }
+}
+
+impl FromPanic for FFIErrorCode {
+    fn from_panic(_message: String) -> Self {
+        FFIErrorCode::Panic
+    }
+}
+
+impl<T> FromPanic for FFIResult<T> {
+    fn from_panic(message: String) -> Self {
+        FFIResult::Err(FFIError::from_panic(message))
+    }
+}
+
+impl FromPanic for FFIRequestResult {
+    fn from_panic(message: String) -> Self {
+        FFIRequestResult::Panicked(FFIError::from_panic(message))
+    }
+}
+
+/// Runs the body of an exported function so that a panic is returned to the plugin as
+/// `R::from_panic` instead of unwinding into the game, which would crash it.
+pub fn catch_panic<R: FromPanic>(f: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => R::from_panic(panic_message(&*payload)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_catch_panic() {
+        let result: FFIResult<i32> = catch_panic(|| FFIResult::Ok(1));
+        assert_eq!(result, FFIResult::Ok(1));
+
+        let result: FFIResult<i32> = catch_panic(|| panic!("something broke: {}", 1));
+        match result {
+            FFIResult::Err(FFIError::Panic(message)) => assert_eq!(
+                unsafe { CStr::from_ptr(message).to_string_lossy() },
+                "something broke: 1"
+            ),
+            _ => panic!("catch_panic did not return a panic error"),
+        }
+
+        let request_id: u64 = catch_panic(|| panic!("something broke"));
+        assert_eq!(request_id, 0);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        assert_eq!(panic_message(&"message"), "message");
+        assert_eq!(panic_message(&"message".to_string()), "message");
+        assert_eq!(panic_message(&1), "unknown panic");
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::mpsc::{channel, Sender},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::anyhow;
+
+#[cfg(not(test))]
+use log::{error, info};
+#[cfg(test)]
+use std::{println as info, println as error};
+
+use crate::{
+    client::{client_from_ptr, BazaarClient},
+    error::{Error, Result},
+    interior_ref_list::{RawInteriorRefData, SavedInteriorRefList},
+    merchandise_list::{RawMerchandiseVec, SavedMerchandiseList},
+    owner::{RawOwner, RawOwnerVec, SavedOwner},
+    panic::{catch_panic, panic_message, FromPanic},
+    result::{FFIError, FFIResult},
+    shop::{RawShop, RawShopPage, RawShopVec, SavedShop, ShopPage},
+    transaction::{RawTransaction, RawTransactionVec, SavedTransaction},
+};
+
+/// Result of a request run on the client's worker pool, kept as Rust types until the plugin polls
+/// for it so that cancelled or abandoned requests can simply be dropped.
+#[derive(Debug)]
+pub enum RequestOutcome {
+    StatusCheck(Result<()>),
+    Deleted(Result<()>),
+    Owner(Result<SavedOwner>),
+    Owners(Result<Vec<SavedOwner>>),
+    Shop(Result<SavedShop>),
+    Shops(Result<Vec<SavedShop>>),
+    ShopPage(Result<ShopPage>),
+    InteriorRefListId(Result<i32>),
+    InteriorRefList(Result<SavedInteriorRefList>),
+    MerchandiseList(Result<SavedMerchandiseList>),
+    Transaction(Result<SavedTransaction>),
+    Transactions(Result<Vec<SavedTransaction>>),
+    /// The request panicked on the worker thread. Holds the panic message.
+    Panicked(Error),
+}
+
+impl RequestOutcome {
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            RequestOutcome::StatusCheck(result) => result.as_ref().err(),
+            RequestOutcome::Deleted(result) => result.as_ref().err(),
+            RequestOutcome::Owner(result) => result.as_ref().err(),
+            RequestOutcome::Owners(result) => result.as_ref().err(),
+            RequestOutcome::Shop(result) => result.as_ref().err(),
+            RequestOutcome::Shops(result) => result.as_ref().err(),
+            RequestOutcome::ShopPage(result) => result.as_ref().err(),
+            RequestOutcome::InteriorRefListId(result) => result.as_ref().err(),
+            RequestOutcome::InteriorRefList(result) => result.as_ref().err(),
+            RequestOutcome::MerchandiseList(result) => result.as_ref().err(),
+            RequestOutcome::Transaction(result) => result.as_ref().err(),
+            RequestOutcome::Transactions(result) => result.as_ref().err(),
+            RequestOutcome::Panicked(err) => Some(err),
+        }
+    }
+}
+
+// the fields are only read by the plugin, through bindings.h
+#[allow(dead_code)]
+#[derive(Debug)]
+#[repr(C, u8)]
+pub enum FFIRequestResult {
+    Pending,
+    Cancelled,
+    Unknown,
+    Bool(FFIResult<bool>),
+    Int(FFIResult<i32>),
+    Owner(FFIResult<RawOwner>),
+    OwnerVec(FFIResult<RawOwnerVec>),
+    Shop(FFIResult<RawShop>),
+    ShopVec(FFIResult<RawShopVec>),
+    ShopPage(FFIResult<RawShopPage>),
+    InteriorRefData(FFIResult<RawInteriorRefData>),
+    MerchandiseVec(FFIResult<RawMerchandiseVec>),
+    Transaction(FFIResult<RawTransaction>),
+    TransactionVec(FFIResult<RawTransactionVec>),
+    Panicked(FFIError),
+}
+
+impl From<RequestOutcome> for FFIRequestResult {
+    fn from(outcome: RequestOutcome) -> Self {
+        match outcome {
+            RequestOutcome::StatusCheck(result) | RequestOutcome::Deleted(result) => {
+                FFIRequestResult::Bool(FFIResult::from_result(result.map(|()| true)))
+            }
+            RequestOutcome::Owner(result) => {
+                FFIRequestResult::Owner(FFIResult::from_result(result))
+            }
+            RequestOutcome::Owners(result) => {
+                FFIRequestResult::OwnerVec(FFIResult::from_result(result))
+            }
+            RequestOutcome::Shop(result) => FFIRequestResult::Shop(FFIResult::from_result(result)),
+            RequestOutcome::Shops(result) => {
+                FFIRequestResult::ShopVec(FFIResult::from_result(result))
+            }
+            RequestOutcome::ShopPage(result) => {
+                FFIRequestResult::ShopPage(FFIResult::from_result(result))
+            }
+            RequestOutcome::InteriorRefListId(result) => {
+                FFIRequestResult::Int(FFIResult::from_result(result))
+            }
+            RequestOutcome::InteriorRefList(result) => {
+                FFIRequestResult::InteriorRefData(FFIResult::from_result(result))
+            }
+            RequestOutcome::MerchandiseList(result) => {
+                FFIRequestResult::MerchandiseVec(FFIResult::from_result(result))
+            }
+            RequestOutcome::Transaction(result) => {
+                FFIRequestResult::Transaction(FFIResult::from_result(result))
+            }
+            RequestOutcome::Transactions(result) => {
+                FFIRequestResult::TransactionVec(FFIResult::from_result(result))
+            }
+            RequestOutcome::Panicked(err) => FFIRequestResult::from_panic(err.to_string()),
+        }
+    }
+}
+
+/// Called from a worker thread when a request finishes. The callback takes ownership of the
+/// result, and the request can no longer be polled.
+pub type RequestCallback = extern "C" fn(request_id: u64, result: FFIRequestResult);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+enum RequestState {
+    Queued,
+    Running,
+    Cancelled,
+    Done(RequestOutcome),
+}
+
+#[derive(Debug)]
+pub struct RequestQueue {
+    worker_threads: usize,
+    sender: Mutex<Option<Sender<Job>>>,
+    requests: Mutex<HashMap<u64, RequestState>>,
+    next_id: AtomicU64,
+    callback: Mutex<Option<RequestCallback>>,
+}
+
+impl RequestQueue {
+    pub fn new(worker_threads: usize) -> Self {
+        Self {
+            worker_threads: worker_threads.max(1),
+            sender: Mutex::new(None),
+            requests: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            callback: Mutex::new(None),
+        }
+    }
+
+    // Workers are only spawned once the first request is queued, so clients that never make an
+    // async request (like the ones shared by the handle-less functions) cost nothing.
+    fn spawn_workers(&self) -> Sender<Job> {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..self.worker_threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("bazaar-realm-worker-{}", i))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .map_err(|err| error!("Failed to spawn request worker thread: {}", err))
+                .ok();
+        }
+        sender
+    }
+
+    fn enqueue(&self, job: Job) -> Result<()> {
+        let mut sender = self.sender.lock().unwrap();
+        let sender = sender.get_or_insert_with(|| self.spawn_workers());
+        sender
+            .send(job)
+            .map_err(|_| Error::Other(anyhow!("request worker pool has shut down")))
+    }
+
+    fn start(&self, id: u64) -> bool {
+        let mut requests = self.requests.lock().unwrap();
+        match requests.get(&id) {
+            Some(RequestState::Queued) => {
+                requests.insert(id, RequestState::Running);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn finish(&self, id: u64, outcome: RequestOutcome) {
+        if let Some(err) = outcome.error() {
+            error!("request {} failed. {}", id, err);
+        } else {
+            info!("request {} successful", id);
+        }
+        let callback = *self.callback.lock().unwrap();
+        let mut requests = self.requests.lock().unwrap();
+        // a cancelled request stays marked until it is polled
+        if let Some(RequestState::Running) = requests.get(&id) {
+            match callback {
+                Some(callback) => {
+                    requests.remove(&id);
+                    drop(requests);
+                    callback(id, FFIRequestResult::from(outcome));
+                }
+                None => {
+                    requests.insert(id, RequestState::Done(outcome));
+                }
+            }
+        }
+    }
+
+    /// Takes the result of a finished or cancelled request. Results are kept until they are
+    /// polled, so every request that isn't answered through the callback should be polled until it
+    /// stops being pending.
+    pub fn poll(&self, id: u64) -> FFIRequestResult {
+        let mut requests = self.requests.lock().unwrap();
+        match requests.remove(&id) {
+            Some(RequestState::Done(outcome)) => FFIRequestResult::from(outcome),
+            Some(RequestState::Cancelled) => FFIRequestResult::Cancelled,
+            Some(state) => {
+                requests.insert(id, state);
+                FFIRequestResult::Pending
+            }
+            None => FFIRequestResult::Unknown,
+        }
+    }
+
+    /// Marks the request as cancelled, so polling it reports `Cancelled`. Requests that are already
+    /// running can not be interrupted, but their result will be discarded when they finish, and a
+    /// result that was waiting to be polled is discarded right away.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut requests = self.requests.lock().unwrap();
+        match requests.get(&id) {
+            Some(RequestState::Queued)
+            | Some(RequestState::Running)
+            | Some(RequestState::Done(_)) => {
+                requests.insert(id, RequestState::Cancelled);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn set_callback(&self, callback: Option<RequestCallback>) {
+        *self.callback.lock().unwrap() = callback;
+    }
+}
+
+impl BazaarClient {
+    /// Queues `f` to run on the client's worker pool and returns the ID to poll for its result.
+    pub fn submit<F>(&self, f: F) -> Result<u64>
+    where
+        F: FnOnce(&BazaarClient) -> RequestOutcome + Send + 'static,
+    {
+        let id = self.requests.next_id.fetch_add(1, Ordering::SeqCst);
+        self.requests
+            .requests
+            .lock()
+            .unwrap()
+            .insert(id, RequestState::Queued);
+        let client = self.clone();
+        let result = self.requests.enqueue(Box::new(move || {
+            if client.requests.start(id) {
+                // a panicking request must not take its worker thread down with it
+                let outcome = match panic::catch_unwind(AssertUnwindSafe(|| f(&client))) {
+                    Ok(outcome) => outcome,
+                    Err(payload) => {
+                        RequestOutcome::Panicked(Error::Other(anyhow!(panic_message(&*payload))))
+                    }
+                };
+                client.requests.finish(id, outcome);
+            }
+        }));
+        if let Err(err) = result {
+            self.requests.requests.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        Ok(id)
+    }
+}
+
+/// Queues a request on the client behind the handle. Returns the request ID, or 0 if the request
+/// could not be queued.
+pub fn submit_request<F>(client: *const BazaarClient, name: &str, f: F) -> u64
+where
+    F: FnOnce(&BazaarClient) -> RequestOutcome + Send + 'static,
+{
+    match client_from_ptr(client).and_then(|client| client.submit(f)) {
+        Ok(id) => {
+            info!("{} queued as request {}", name, id);
+            id
+        }
+        Err(err) => {
+            error!("{} failed to queue. {}", name, err);
+            0
+        }
+    }
+}
+
+/// Takes the result of a request made with a `client_*_async` function. Results are kept until
+/// they are polled, so poll every request that isn't answered through the request callback.
+#[no_mangle]
+pub extern "C" fn poll_request(client: *const BazaarClient, request_id: u64) -> FFIRequestResult {
+    catch_panic(|| match client_from_ptr(client) {
+        Ok(client) => client.requests.poll(request_id),
+        Err(err) => {
+            error!("poll_request failed. {}", err);
+            FFIRequestResult::Unknown
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn cancel_request(client: *const BazaarClient, request_id: u64) -> bool {
+    catch_panic(|| {
+        info!("cancel_request request_id: {:?}", request_id);
+        match client_from_ptr(client) {
+            Ok(client) => client.requests.cancel(request_id),
+            Err(err) => {
+                error!("cancel_request failed. {}", err);
+                false
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn set_request_callback(
+    client: *const BazaarClient,
+    callback: Option<RequestCallback>,
+) -> bool {
+    catch_panic(|| match client_from_ptr(client) {
+        Ok(client) => {
+            client.requests.set_callback(callback);
+            true
+        }
+        Err(err) => {
+            error!("set_request_callback failed. {}", err);
+            false
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_status_check_async(client: *const BazaarClient) -> u64 {
+    catch_panic(|| {
+        submit_request(client, "status_check", |client| {
+            RequestOutcome::StatusCheck(client.status_check_and_replay_outbox())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::Receiver, time::Duration};
+
+    use super::*;
+    use crate::client::test_client;
+
+    fn wait_for(client: &BazaarClient, id: u64) -> FFIRequestResult {
+        for _ in 0..500 {
+            match client.requests.poll(id) {
+                FFIRequestResult::Pending => thread::sleep(Duration::from_millis(10)),
+                result => return result,
+            }
+        }
+        panic!("request {} never finished", id);
+    }
+
+    #[test]
+    fn test_poll_request() {
+        let (_cache_root, client) = test_client();
+        let id = client
+            .submit(|_| RequestOutcome::InteriorRefListId(Ok(1)))
+            .unwrap();
+        match wait_for(&client, id) {
+            FFIRequestResult::Int(FFIResult::Ok(interior_ref_list_id)) => {
+                assert_eq!(interior_ref_list_id, 1)
+            }
+            result => panic!("poll_request returned unexpected result: {:?}", result),
+        }
+        match client.requests.poll(id) {
+            FFIRequestResult::Unknown => {}
+            result => panic!("poll_request returned unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        let (_cache_root, client) = test_client();
+        let (sender, receiver) = channel::<()>();
+        let receiver: Arc<Mutex<Receiver<()>>> = Arc::new(Mutex::new(receiver));
+        let id = client
+            .submit(move |_| {
+                receiver.lock().unwrap().recv().ok();
+                RequestOutcome::InteriorRefListId(Ok(1))
+            })
+            .unwrap();
+        assert!(client.requests.cancel(id));
+        sender.send(()).unwrap();
+        match wait_for(&client, id) {
+            FFIRequestResult::Cancelled => {}
+            result => panic!("poll_request returned unexpected result: {:?}", result),
+        }
+        match client.requests.poll(id) {
+            FFIRequestResult::Unknown => {}
+            result => panic!("poll_request returned unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_cancel_finished_request() {
+        let (_cache_root, client) = test_client();
+        let id = client
+            .submit(|_| RequestOutcome::InteriorRefListId(Ok(1)))
+            .unwrap();
+        loop {
+            let finished = matches!(
+                client.requests.requests.lock().unwrap().get(&id),
+                Some(RequestState::Done(_))
+            );
+            if finished {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(client.requests.cancel(id));
+        match client.requests.poll(id) {
+            FFIRequestResult::Cancelled => {}
+            result => panic!("poll_request returned unexpected result: {:?}", result),
+        }
+    }
+}
@@ -1,11 +1,28 @@
-use anyhow::Error;
+use reqwest::StatusCode;
 
 use std::convert::From;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::fmt;
 use std::os::raw::c_char;
 use std::ptr::null;
 
-use crate::error::ServerError;
+use crate::{
+    error::{ConflictError, Error, ServerCopy, ServerError, ValidationError},
+    free_raw_string,
+    interior_ref_list::{free_raw_interior_ref_data, RawInteriorRefData},
+    merchandise_list::{free_raw_merchandise_vec, RawMerchandiseVec},
+    owner::{free_raw_owner, RawOwner},
+    panic::catch_panic,
+    shop::{free_raw_shop, RawShop},
+};
+
+/// Error messages can come from the server, so any NUL bytes are dropped rather than letting
+/// `CString::new` fail.
+pub(crate) fn error_string_into_raw(message: &str) -> *const c_char {
+    CString::new(message.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
 
 #[derive(Debug, PartialEq)]
 #[repr(C)]
@@ -19,44 +36,484 @@ impl From<&ServerError> for FFIServerError {
     fn from(server_error: &ServerError) -> Self {
         FFIServerError {
             status: server_error.status.as_u16(),
-            // TODO: may need to drop these CStrings once C++ is done reading them
-            title: CString::new(server_error.title.clone())
-                .expect("could not create CString")
-                .into_raw(),
+            title: error_string_into_raw(&server_error.title),
             detail: match &server_error.detail {
-                Some(detail) => CString::new(detail.clone())
-                    .expect("could not create CString")
-                    .into_raw(),
+                Some(detail) => error_string_into_raw(detail),
                 None => null(),
             },
         }
     }
 }
 
+/// The server's current copy of what an update conflicted with, so the plugin can merge its change
+/// into it.
+#[derive(Debug, PartialEq)]
+#[repr(C, u8)]
+pub enum RawServerCopy {
+    /// The conflict was not with an update, or the server's copy could not be fetched.
+    None,
+    Owner(RawOwner),
+    Shop(RawShop),
+    MerchandiseList(RawMerchandiseVec),
+    InteriorRefList(RawInteriorRefData),
+}
+
+impl From<Option<ServerCopy>> for RawServerCopy {
+    fn from(server_copy: Option<ServerCopy>) -> Self {
+        match server_copy {
+            None => RawServerCopy::None,
+            Some(ServerCopy::Owner(owner)) => RawServerCopy::Owner(RawOwner::from(owner)),
+            Some(ServerCopy::Shop(shop)) => RawServerCopy::Shop(RawShop::from(shop)),
+            Some(ServerCopy::MerchandiseList(list)) => {
+                RawServerCopy::MerchandiseList(RawMerchandiseVec::from(list))
+            }
+            Some(ServerCopy::InteriorRefList(list)) => {
+                RawServerCopy::InteriorRefList(RawInteriorRefData::from(list))
+            }
+        }
+    }
+}
+
+fn free_raw_server_copy(server_copy: RawServerCopy) {
+    match server_copy {
+        RawServerCopy::None => {}
+        RawServerCopy::Owner(owner) => free_raw_owner(owner),
+        RawServerCopy::Shop(shop) => free_raw_shop(shop),
+        RawServerCopy::MerchandiseList(list) => free_raw_merchandise_vec(list),
+        RawServerCopy::InteriorRefList(list) => free_raw_interior_ref_data(list),
+    }
+}
+
+/// A 409 Conflict, or a 412 Precondition Failed for an update based on an out of date copy.
+#[derive(Debug, PartialEq)]
+#[repr(C)]
+pub struct FFIConflictError {
+    pub server_error: FFIServerError,
+    pub current: RawServerCopy,
+}
+
+impl From<ConflictError> for FFIConflictError {
+    fn from(conflict_error: ConflictError) -> Self {
+        Self {
+            server_error: FFIServerError::from(&conflict_error.server_error),
+            current: RawServerCopy::from(conflict_error.current),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[repr(C)]
+pub struct RawFieldError {
+    pub field: *const c_char,
+    pub reason: *const c_char,
+}
+
+/// Every field of a value that failed validation.
+#[derive(Debug, PartialEq)]
+#[repr(C)]
+pub struct FFIValidationError {
+    pub ptr: *mut RawFieldError,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl From<ValidationError> for FFIValidationError {
+    fn from(validation_error: ValidationError) -> Self {
+        let (ptr, len, cap) = validation_error
+            .field_errors
+            .into_iter()
+            .map(|field_error| RawFieldError {
+                field: error_string_into_raw(&field_error.field),
+                reason: error_string_into_raw(&field_error.reason),
+            })
+            .collect::<Vec<RawFieldError>>()
+            .into_raw_parts();
+        Self { ptr, len, cap }
+    }
+}
+
+fn free_ffi_validation_error(validation_error: FFIValidationError) {
+    if validation_error.ptr.is_null() {
+        return;
+    }
+    let field_errors = unsafe {
+        Vec::from_raw_parts(
+            validation_error.ptr,
+            validation_error.len,
+            validation_error.cap,
+        )
+    };
+    for field_error in field_errors {
+        unsafe {
+            free_raw_string(field_error.field);
+            free_raw_string(field_error.reason);
+        }
+    }
+}
+
+/// New variants are only ever added at the end so that the tag of each existing variant stays the
+/// same for plugins built against an older header.
 #[derive(Debug, PartialEq)]
 #[repr(C, u8)]
 pub enum FFIError {
+    /// The server responded with an error status that has no more specific variant.
     Server(FFIServerError),
+    /// Any other error. Holds the error message.
     Network(*const c_char),
+    CacheCorrupt(*const c_char),
+    /// The client panicked. Holds the panic message.
+    Panic(*const c_char),
+    /// An argument passed to the client was null or otherwise unreadable.
+    InvalidArgument {
+        arg_name: *const c_char,
+        reason: *const c_char,
+    },
+    Timeout(*const c_char),
+    /// The server could not be reached, or the connection dropped before it responded.
+    ConnectionFailed(*const c_char),
+    /// The server rejected the API key (401 or 403).
+    Unauthorized(FFIServerError),
+    NotFound(FFIServerError),
+    Conflict(FFIConflictError),
+    /// A response or cache entry could not be decoded.
+    Deserialize(*const c_char),
+    /// The request failed and there was no cached copy to fall back to.
+    CacheMiss(*const c_char),
+    InvalidUrl(*const c_char),
+    Io(*const c_char),
+    /// An argument failed validation before anything was sent to the server.
+    Validation(FFIValidationError),
+}
+
+/// Stable numeric code for each kind of `FFIError`, for logging and for plugins that only need to
+/// branch on the kind. Codes are never reused or renumbered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum FFIErrorCode {
+    Network = 1,
+    Server = 2,
+    CacheCorrupt = 3,
+    Panic = 4,
+    InvalidArgument = 5,
+    Timeout = 6,
+    ConnectionFailed = 7,
+    Unauthorized = 8,
+    NotFound = 9,
+    Conflict = 10,
+    Deserialize = 11,
+    CacheMiss = 12,
+    InvalidUrl = 13,
+    Io = 14,
+    Validation = 15,
+}
+
+impl FFIError {
+    pub fn code(&self) -> FFIErrorCode {
+        match self {
+            FFIError::Server(_) => FFIErrorCode::Server,
+            FFIError::Network(_) => FFIErrorCode::Network,
+            FFIError::CacheCorrupt(_) => FFIErrorCode::CacheCorrupt,
+            FFIError::Panic(_) => FFIErrorCode::Panic,
+            FFIError::InvalidArgument { .. } => FFIErrorCode::InvalidArgument,
+            FFIError::Timeout(_) => FFIErrorCode::Timeout,
+            FFIError::ConnectionFailed(_) => FFIErrorCode::ConnectionFailed,
+            FFIError::Unauthorized(_) => FFIErrorCode::Unauthorized,
+            FFIError::NotFound(_) => FFIErrorCode::NotFound,
+            FFIError::Conflict(_) => FFIErrorCode::Conflict,
+            FFIError::Deserialize(_) => FFIErrorCode::Deserialize,
+            FFIError::CacheMiss(_) => FFIErrorCode::CacheMiss,
+            FFIError::InvalidUrl(_) => FFIErrorCode::InvalidUrl,
+            FFIError::Io(_) => FFIErrorCode::Io,
+            FFIError::Validation(_) => FFIErrorCode::Validation,
+        }
+    }
+
+    fn from_server_error(server_error: &ServerError) -> Self {
+        let ffi_server_error = FFIServerError::from(server_error);
+        match server_error.status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                FFIError::Unauthorized(ffi_server_error)
+            }
+            StatusCode::NOT_FOUND => FFIError::NotFound(ffi_server_error),
+            StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+                FFIError::Conflict(FFIConflictError {
+                    server_error: ffi_server_error,
+                    current: RawServerCopy::None,
+                })
+            }
+            _ => FFIError::Server(ffi_server_error),
+        }
+    }
 }
 
 impl From<Error> for FFIError {
     fn from(error: Error) -> Self {
-        if let Some(server_error) = error.downcast_ref::<ServerError>() {
-            FFIError::Server(FFIServerError::from(server_error))
-        } else {
-            // TODO: also need to drop this CString once C++ is done reading it
-            let err_string = CString::new(error.to_string())
-                .expect("could not create CString")
-                .into_raw();
-            FFIError::Network(err_string)
+        let message = error.to_string();
+        match error {
+            Error::InvalidArgument(argument_error) => FFIError::InvalidArgument {
+                arg_name: error_string_into_raw(&argument_error.arg_name),
+                reason: error_string_into_raw(&argument_error.reason),
+            },
+            Error::Validation(validation_error) => {
+                FFIError::Validation(FFIValidationError::from(validation_error))
+            }
+            Error::Server(server_error) => FFIError::from_server_error(&server_error),
+            Error::Conflict(conflict_error) => {
+                FFIError::Conflict(FFIConflictError::from(*conflict_error))
+            }
+            Error::Timeout(_) => FFIError::Timeout(error_string_into_raw(&message)),
+            Error::ConnectionFailed(_) => {
+                FFIError::ConnectionFailed(error_string_into_raw(&message))
+            }
+            Error::CacheMiss(_) => FFIError::CacheMiss(error_string_into_raw(&message)),
+            Error::CacheCorrupt(_) => FFIError::CacheCorrupt(error_string_into_raw(&message)),
+            Error::Deserialize(_) => FFIError::Deserialize(error_string_into_raw(&message)),
+            Error::InvalidUrl(_) => FFIError::InvalidUrl(error_string_into_raw(&message)),
+            Error::Io(_) => FFIError::Io(error_string_into_raw(&message)),
+            Error::Other(_) => FFIError::Network(error_string_into_raw(&message)),
         }
     }
 }
 
+impl From<anyhow::Error> for FFIError {
+    fn from(error: anyhow::Error) -> Self {
+        FFIError::from(Error::from(error))
+    }
+}
+
+impl fmt::Display for FFIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let string = |ptr: *const c_char| match ptr.is_null() {
+            true => "".to_string(),
+            false => unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string(),
+        };
+        match self {
+            FFIError::Server(server_error)
+            | FFIError::Unauthorized(server_error)
+            | FFIError::NotFound(server_error)
+            | FFIError::Conflict(FFIConflictError { server_error, .. }) => write!(
+                f,
+                "{:?} {} {}",
+                self.code(),
+                server_error.status,
+                string(server_error.title)
+            ),
+            FFIError::InvalidArgument { arg_name, reason } => write!(
+                f,
+                "{:?} {} {}",
+                self.code(),
+                string(*arg_name),
+                string(*reason)
+            ),
+            FFIError::Network(message)
+            | FFIError::CacheCorrupt(message)
+            | FFIError::Panic(message)
+            | FFIError::Timeout(message)
+            | FFIError::ConnectionFailed(message)
+            | FFIError::Deserialize(message)
+            | FFIError::CacheMiss(message)
+            | FFIError::InvalidUrl(message)
+            | FFIError::Io(message) => write!(f, "{:?} {}", self.code(), string(*message)),
+            FFIError::Validation(validation_error) => {
+                let field_errors = match validation_error.ptr.is_null() {
+                    true => &[][..],
+                    false => unsafe {
+                        std::slice::from_raw_parts(validation_error.ptr, validation_error.len)
+                    },
+                };
+                let field_errors: Vec<String> = field_errors
+                    .iter()
+                    .map(|field_error| {
+                        format!(
+                            "{} {}",
+                            string(field_error.field),
+                            string(field_error.reason)
+                        )
+                    })
+                    .collect();
+                write!(f, "{:?} {}", self.code(), field_errors.join(", "))
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_error_code(error: *const FFIError) -> FFIErrorCode {
+    catch_panic(|| match unsafe { error.as_ref() } {
+        Some(error) => error.code(),
+        None => FFIErrorCode::InvalidArgument,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_ffi_error(error: FFIError) {
+    catch_panic(|| match error {
+        FFIError::Server(server_error)
+        | FFIError::Unauthorized(server_error)
+        | FFIError::NotFound(server_error) => unsafe {
+            free_raw_string(server_error.title);
+            free_raw_string(server_error.detail);
+        },
+        FFIError::Conflict(FFIConflictError {
+            server_error,
+            current,
+        }) => {
+            unsafe {
+                free_raw_string(server_error.title);
+                free_raw_string(server_error.detail);
+            }
+            free_raw_server_copy(current);
+        }
+        FFIError::InvalidArgument { arg_name, reason } => unsafe {
+            free_raw_string(arg_name);
+            free_raw_string(reason);
+        },
+        FFIError::Network(message)
+        | FFIError::CacheCorrupt(message)
+        | FFIError::Panic(message)
+        | FFIError::Timeout(message)
+        | FFIError::ConnectionFailed(message)
+        | FFIError::Deserialize(message)
+        | FFIError::CacheMiss(message)
+        | FFIError::InvalidUrl(message)
+        | FFIError::Io(message) => unsafe { free_raw_string(message) },
+        FFIError::Validation(validation_error) => free_ffi_validation_error(validation_error),
+    })
+}
+
 #[derive(Debug, PartialEq)]
 #[repr(C, u8)]
 pub enum FFIResult<T> {
     Ok(T),
     Err(FFIError),
 }
+
+impl<T> FFIResult<T> {
+    pub fn from_result<U, E>(result: Result<U, E>) -> Self
+    where
+        T: From<U>,
+        FFIError: From<E>,
+    {
+        match result {
+            Ok(value) => FFIResult::Ok(T::from(value)),
+            Err(err) => FFIResult::Err(FFIError::from(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use anyhow::anyhow;
+    use bytes::Bytes;
+    use reqwest::Url;
+
+    use super::*;
+    use crate::{
+        error::{conflict_from_response, CacheMissError, FieldError, InvalidArgumentError},
+        owner::SavedOwner,
+    };
+
+    fn server_error(status: StatusCode) -> anyhow::Error {
+        anyhow!(ServerError {
+            status,
+            title: status.to_string(),
+            detail: None,
+        })
+    }
+
+    #[test]
+    fn test_ffi_error_from_error() {
+        assert_eq!(
+            FFIError::from(server_error(StatusCode::NOT_FOUND)).code(),
+            FFIErrorCode::NotFound
+        );
+        assert_eq!(
+            FFIError::from(server_error(StatusCode::FORBIDDEN)).code(),
+            FFIErrorCode::Unauthorized
+        );
+        assert_eq!(
+            FFIError::from(server_error(StatusCode::CONFLICT)).code(),
+            FFIErrorCode::Conflict
+        );
+        assert_eq!(
+            FFIError::from(server_error(StatusCode::INTERNAL_SERVER_ERROR)).code(),
+            FFIErrorCode::Server
+        );
+        assert_eq!(
+            FFIError::from(anyhow!(InvalidArgumentError::new("client", "is null"))).code(),
+            FFIErrorCode::InvalidArgument
+        );
+        assert_eq!(
+            FFIError::from(anyhow!(CacheMissError {
+                file_name: "shop_1.bin".to_string()
+            }))
+            .code(),
+            FFIErrorCode::CacheMiss
+        );
+        assert_eq!(
+            FFIError::from(anyhow::Error::new(Url::parse("not a url").unwrap_err())).code(),
+            FFIErrorCode::InvalidUrl
+        );
+        assert_eq!(
+            FFIError::from(anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound))).code(),
+            FFIErrorCode::Io
+        );
+        assert_eq!(
+            FFIError::from(anyhow!(ValidationError {
+                field_errors: vec![FieldError::new("quantity", "must not be negative")]
+            }))
+            .code(),
+            FFIErrorCode::Validation
+        );
+        assert_eq!(
+            FFIError::from(anyhow!("something else")).code(),
+            FFIErrorCode::Network
+        );
+    }
+
+    #[test]
+    fn test_ffi_error_code() {
+        let error = FFIError::from(server_error(StatusCode::NOT_FOUND));
+        assert_eq!(ffi_error_code(&error), FFIErrorCode::NotFound);
+        assert_eq!(ffi_error_code(null()), FFIErrorCode::InvalidArgument);
+        assert_eq!(error.to_string(), "NotFound 404 404 Not Found");
+        free_ffi_error(error);
+    }
+
+    #[test]
+    fn test_ffi_error_from_conflict() {
+        let now = chrono::Utc::now().naive_utc();
+        let error = conflict_from_response(
+            StatusCode::PRECONDITION_FAILED,
+            &Bytes::from_static(
+                br#"{"title":"Precondition Failed","status":412,"detail":"changed"}"#,
+            ),
+            Some(ServerCopy::Owner(SavedOwner {
+                id: 1,
+                name: "Their Name".to_string(),
+                mod_version: 1,
+                created_at: now,
+                updated_at: now,
+            })),
+        );
+        let error = FFIError::from(error);
+        assert_eq!(error.code(), FFIErrorCode::Conflict);
+        assert_eq!(error.to_string(), "Conflict 412 Precondition Failed");
+        match &error {
+            FFIError::Conflict(FFIConflictError {
+                current: RawServerCopy::Owner(owner),
+                ..
+            }) => {
+                assert_eq!(owner.id, 1);
+                assert_eq!(
+                    unsafe { CStr::from_ptr(owner.name).to_string_lossy() },
+                    "Their Name"
+                );
+            }
+            _ => panic!("conflict did not carry the server's copy: {}", error),
+        }
+        free_ffi_error(error);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+use reqwest::{
+    blocking::{Request, RequestBuilder},
+    Method, StatusCode,
+};
+
+#[cfg(not(test))]
+use log::info;
+#[cfg(test)]
+use std::println as info;
+
+use crate::{
+    client::{BazaarClient, ClientOptions},
+    error::{Error, Result},
+    transport::Response,
+};
+
+/// When and how often a request that failed in a way that might be temporary is sent again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of times a request is sent, including the first. 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry. It doubles on every retry after that.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomize each delay between half and all of its value so clients don't retry in lockstep.
+    pub jitter: bool,
+    pub retryable_statuses: Vec<StatusCode>,
+    pub retry_timeouts: bool,
+    /// Retry requests that never got a response, like refused or reset connections.
+    pub retry_connection_errors: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::from(&ClientOptions::default())
+    }
+}
+
+impl From<&ClientOptions> for RetryPolicy {
+    fn from(options: &ClientOptions) -> Self {
+        Self {
+            max_attempts: options.retry_max_attempts.max(1),
+            base_delay: Duration::from_millis(options.retry_base_delay_ms),
+            max_delay: Duration::from_millis(options.retry_max_delay_ms),
+            jitter: options.retry_jitter,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_timeouts: true,
+            retry_connection_errors: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Only GETs, PATCHes and POSTs with an idempotency key are retried. The server ignores a
+    /// duplicate keyed POST, and a PATCH that was already applied comes back as a 412 the update
+    /// functions check against what they sent.
+    pub fn is_retryable_request(&self, request: &Request) -> bool {
+        match *request.method() {
+            Method::GET | Method::PATCH => true,
+            Method::POST => request.headers().contains_key("Idempotency-Key"),
+            _ => false,
+        }
+    }
+
+    pub fn is_retryable_error(&self, err: &Error) -> bool {
+        match err {
+            Error::Timeout(_) => self.retry_timeouts,
+            Error::ConnectionFailed(_) => self.retry_connection_errors,
+            Error::Other(err) => match err.downcast_ref::<reqwest::Error>() {
+                Some(err) => {
+                    self.retry_connection_errors
+                        && !err.is_builder()
+                        && !err.is_redirect()
+                        && !err.is_status()
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Delay before retrying after the given (1-based) attempt failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .checked_mul(1 << (attempt - 1).min(16))
+            .unwrap_or(self.max_delay);
+        let delay = exponential.min(self.max_delay);
+        if self.jitter {
+            let half = delay / 2;
+            let random = RandomState::new().build_hasher().finish();
+            half + Duration::from_nanos(random % (half.as_nanos() as u64 + 1))
+        } else {
+            delay
+        }
+    }
+}
+
+impl BazaarClient {
+    /// Sends a request, retrying it according to the client's retry policy. Once the attempts run
+    /// out the last response or error is returned as is.
+    pub(crate) fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.send_counting_attempts(request).map(|(resp, _)| resp)
+    }
+
+    /// Like `send`, but also returns how many times the request was sent. A response to anything
+    /// but the first attempt may be the server's answer to a request it already applied.
+    pub(crate) fn send_counting_attempts(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<(Response, u32)> {
+        let mut request = request.build()?;
+        let policy = &self.retry_policy;
+        if !policy.is_retryable_request(&request) {
+            return self.transport.execute(request).map(|resp| (resp, 1));
+        }
+
+        let mut attempt = 1;
+        loop {
+            let next_request = match attempt < policy.max_attempts {
+                true => request.try_clone(),
+                false => None,
+            };
+            let method = request.method().clone();
+            let url = request.url().clone();
+            let result = self.transport.execute(request);
+            let next_request = match next_request {
+                Some(next_request) => next_request,
+                None => return result.map(|resp| (resp, attempt)),
+            };
+            let reason = match &result {
+                Ok(resp) if policy.retryable_statuses.contains(&resp.status()) => {
+                    format!("server returned {}", resp.status())
+                }
+                Err(err) if policy.is_retryable_error(err) => err.to_string(),
+                _ => return result.map(|resp| (resp, attempt)),
+            };
+
+            let delay = policy.delay(attempt);
+            attempt += 1;
+            info!(
+                "retrying {} {} in {:?} (attempt {} of {}): {}",
+                method, url, delay, attempt, policy.max_attempts, reason
+            );
+            thread::sleep(delay);
+            request = next_request;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        fake_api::{FakeApi, FAKE_API_URL},
+        shop::Shop,
+    };
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(5), Duration::from_millis(1000));
+        assert_eq!(policy.delay(40), Duration::from_millis(1000));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for attempt in 1..6 {
+            let delay = policy.delay(attempt);
+            let max = Duration::from_millis((100 << (attempt - 1)).min(1000));
+            assert!(delay >= max / 2 && delay <= max, "{:?} out of range", delay);
+        }
+    }
+
+    #[test]
+    fn test_is_retryable_request() {
+        let policy = RetryPolicy::default();
+        let http = reqwest::blocking::Client::new();
+        let url = "http://localhost/v1/shops";
+        assert!(policy.is_retryable_request(&http.get(url).build().unwrap()));
+        assert!(policy.is_retryable_request(&http.patch(url).build().unwrap()));
+        assert!(!policy.is_retryable_request(&http.post(url).build().unwrap()));
+        assert!(!policy.is_retryable_request(&http.put(url).build().unwrap()));
+        assert!(!policy.is_retryable_request(&http.delete(url).build().unwrap()));
+        assert!(!policy.is_retryable_request(&http.head(url).build().unwrap()));
+        assert!(policy.is_retryable_request(
+            &http
+                .post(url)
+                .header("Idempotency-Key", "key")
+                .build()
+                .unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_is_retryable_error() {
+        let policy = RetryPolicy {
+            retry_timeouts: false,
+            ..RetryPolicy::default()
+        };
+        assert!(!policy.is_retryable_error(&Error::Timeout("timed out".to_string())));
+        assert!(policy.is_retryable_error(&Error::ConnectionFailed("refused".to_string())));
+        assert!(!policy.is_retryable_error(&Error::Deserialize("bad body".to_string())));
+    }
+
+    #[test]
+    fn test_retried_update_already_applied() {
+        let fake = Arc::new(FakeApi::new());
+        let cache_root = tempfile::tempdir().unwrap();
+        let client = BazaarClient::with_transport(
+            FAKE_API_URL,
+            "owner-key",
+            ClientOptions {
+                retry_max_attempts: 2,
+                retry_base_delay_ms: 1,
+                ..ClientOptions::default()
+            },
+            cache_root.path(),
+            fake.clone(),
+        )
+        .unwrap();
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client.get_shop(shop.id).unwrap();
+
+        // the first PATCH is applied but its response is lost, so the retry's If-Match is stale
+        fake.drop_next_response();
+        let update = Shop {
+            gold: Some(100),
+            ..Shop::from_game("My Shop", "description")
+        };
+        let saved_shop = client.update_shop(shop.id as u32, &update).unwrap();
+        assert_eq!(saved_shop.name, "My Shop");
+        assert_eq!(saved_shop.gold, 100);
+
+        // a retry that conflicts with someone else's change is still reported
+        let (_other_cache_root, other_client) = crate::fake_api::fake_client(&fake, "owner-key");
+        other_client
+            .update_shop(
+                shop.id as u32,
+                &Shop::from_game("Their Shop", "description"),
+            )
+            .unwrap();
+        fake.drop_next_response();
+        match client.update_shop(shop.id as u32, &Shop::from_game("Other", "description")) {
+            Err(Error::Conflict(_)) => {}
+            result => panic!("update_shop did not return a conflict: {:?}", result),
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char, slice};
+use std::{collections::HashSet, ffi::CString, os::raw::c_char};
 
-use anyhow::Result;
-use chrono::NaiveDateTime;
-use reqwest::{StatusCode, Url};
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(test))]
@@ -11,16 +11,29 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    cache::file_cache_dir,
-    cache::from_file_cache,
-    cache::load_metadata_from_file_cache,
-    cache::update_file_caches,
-    error::extract_error_from_response,
-    log_server_error,
+    cache::{update_file_caches, CacheSchema, Metadata},
+    client::{client_from_ptr, with_legacy_client, BazaarClient},
+    error::{conflict_from_response, extract_error_from_response, Result, ServerCopy},
+    free_raw_keywords, free_raw_string,
+    freshness::{
+        check_raw_freshness, write_raw_freshness, Fetched, Freshness, FreshnessSource, RawFreshness,
+    },
+    keywords_from_raw, keywords_into_raw, log_server_error,
+    outbox::Mutation,
+    panic::catch_panic,
+    request::{submit_request, RequestOutcome},
     result::{FFIError, FFIResult},
+    str_from_raw,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Number of shops requested per page when `list_shops` walks every page.
+pub const SHOPS_PAGE_SIZE: i32 = 128;
+/// Most pages `list_shops` walks, in case the server keeps answering with full pages.
+pub const MAX_SHOPS_PAGES: u32 = 1024;
+/// File name prefix shared by the cached pages of `list_shops_page`.
+pub const SHOPS_PAGE_CACHE_PREFIX: &str = "shops_limit_";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Shop {
     pub name: String,
     pub owner_id: Option<i32>,
@@ -43,9 +56,32 @@ impl Shop {
             vendor_keywords_exclude: None,
         }
     }
+
+    /// Whether `shop` already has every field this update sets.
+    pub fn is_applied_to(&self, shop: &SavedShop) -> bool {
+        self.name == shop.name
+            && self
+                .owner_id
+                .iter()
+                .all(|&owner_id| owner_id == shop.owner_id)
+            && (self.description.is_none() || self.description == shop.description)
+            && self.gold.iter().all(|&gold| gold == shop.gold)
+            && self
+                .shop_type
+                .iter()
+                .all(|shop_type| *shop_type == shop.shop_type)
+            && self
+                .vendor_keywords
+                .iter()
+                .all(|vendor_keywords| *vendor_keywords == shop.vendor_keywords)
+            && self
+                .vendor_keywords_exclude
+                .iter()
+                .all(|&exclude| exclude == shop.vendor_keywords_exclude)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SavedShop {
     pub id: i32,
     pub name: String,
@@ -59,7 +95,37 @@ pub struct SavedShop {
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug)]
+impl CacheSchema for SavedShop {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+impl SavedShop {
+    /// Applies the fields set in `shop` the same way the server does on update.
+    pub fn apply_update(&mut self, shop: &Shop) {
+        self.name = shop.name.clone();
+        if let Some(owner_id) = shop.owner_id {
+            self.owner_id = owner_id;
+        }
+        if shop.description.is_some() {
+            self.description = shop.description.clone();
+        }
+        if let Some(gold) = shop.gold {
+            self.gold = gold;
+        }
+        if let Some(shop_type) = &shop.shop_type {
+            self.shop_type = shop_type.clone();
+        }
+        if let Some(vendor_keywords) = &shop.vendor_keywords {
+            self.vendor_keywords = vendor_keywords.clone();
+        }
+        if let Some(vendor_keywords_exclude) = shop.vendor_keywords_exclude {
+            self.vendor_keywords_exclude = vendor_keywords_exclude;
+        }
+        self.updated_at = Utc::now().naive_utc();
+    }
+}
+
+#[derive(Debug, PartialEq)]
 #[repr(C)]
 pub struct RawShop {
     pub id: i32,
@@ -74,16 +140,11 @@ pub struct RawShop {
 
 impl From<SavedShop> for RawShop {
     fn from(shop: SavedShop) -> Self {
-        let (keywords_ptr, keywords_len, _) = shop
-            .vendor_keywords
-            .into_iter()
-            .map(|keyword| CString::new(keyword).unwrap_or_default().into_raw() as *const c_char)
-            .collect::<Vec<*const c_char>>()
-            .into_raw_parts();
+        let (keywords_ptr, keywords_len) = keywords_into_raw(shop.vendor_keywords);
         Self {
             id: shop.id,
             name: CString::new(shop.name).unwrap_or_default().into_raw(),
-            description: CString::new(shop.description.unwrap_or_else(|| "".to_string()))
+            description: CString::new(shop.description.unwrap_or_default())
                 .unwrap_or_default()
                 .into_raw(),
             gold: shop.gold,
@@ -103,249 +164,754 @@ pub struct RawShopVec {
     pub cap: usize,
 }
 
+impl From<Vec<SavedShop>> for RawShopVec {
+    fn from(shops: Vec<SavedShop>) -> Self {
+        let (ptr, len, cap) = shops
+            .into_iter()
+            .map(RawShop::from)
+            .collect::<Vec<RawShop>>()
+            .into_raw_parts();
+        Self { ptr, len, cap }
+    }
+}
+
+/// One page of shops from `list_shops_page`.
+#[derive(Debug, Clone)]
+pub struct ShopPage {
+    pub shops: Vec<SavedShop>,
+    /// Offset to request the next page with, or `None` if this is the last page.
+    pub next_offset: Option<i32>,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct RawShopPage {
+    pub shops: RawShopVec,
+    /// Offset to request the next page with, or -1 if this is the last page.
+    pub next_offset: i32,
+}
+
+impl From<ShopPage> for RawShopPage {
+    fn from(page: ShopPage) -> Self {
+        Self {
+            shops: RawShopVec::from(page.shops),
+            next_offset: page.next_offset.unwrap_or(-1),
+        }
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn create_shop(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    name: *const c_char,
-    description: *const c_char,
-) -> FFIResult<RawShop> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
-    let description = unsafe { CStr::from_ptr(description) }.to_string_lossy();
-    info!(
-        "create_shop api_url: {:?}, api_key: {:?}, name: {:?}, description: {:?}",
-        api_url, api_key, name, description
-    );
-
-    fn inner(api_url: &str, api_key: &str, name: &str, description: &str) -> Result<SavedShop> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/shops")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/shops")?;
+pub extern "C" fn free_raw_shop(raw_shop: RawShop) {
+    catch_panic(|| unsafe {
+        free_raw_string(raw_shop.name);
+        free_raw_string(raw_shop.description);
+        free_raw_string(raw_shop.shop_type);
+        free_raw_keywords(raw_shop.vendor_keywords, raw_shop.vendor_keywords_len);
+    })
+}
 
+#[no_mangle]
+pub extern "C" fn free_raw_shop_vec(raw_shop_vec: RawShopVec) {
+    catch_panic(|| {
+        if raw_shop_vec.ptr.is_null() {
+            return;
+        }
+        let raw_shops =
+            unsafe { Vec::from_raw_parts(raw_shop_vec.ptr, raw_shop_vec.len, raw_shop_vec.cap) };
+        for raw_shop in raw_shops {
+            free_raw_shop(raw_shop);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_raw_shop_page(raw_shop_page: RawShopPage) {
+    catch_panic(|| {
+        free_raw_shop_vec(raw_shop_page.shops);
+    })
+}
+
+impl BazaarClient {
+    pub fn create_shop(&self, name: &str, description: &str) -> Result<SavedShop> {
         let shop = Shop::from_game(name, description);
-        info!("created shop from game: {:?}", &shop);
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post(url)
-            .header("Api-Key", api_key)
-            .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&shop)?)
-            .send()?;
-        info!("create shop response from api: {:?}", &resp);
+        let key = self.idempotency_key_for("v1/shops", &bincode::serialize(&shop)?)?;
+        self.create_shop_with_idempotency_key(name, description, &key)
+    }
+
+    /// Like `create_shop`, but sent with `idempotency_key` instead of a new key, so that sending it
+    /// again with the same key can't create a shop twice.
+    pub fn create_shop_with_idempotency_key(
+        &self,
+        name: &str,
+        description: &str,
+        idempotency_key: &str,
+    ) -> Result<SavedShop> {
+        let shop = Shop::from_game(name, description);
+        info!("created shop from game: {:?}", shop);
+        let resp = self.send_post("v1/shops", bincode::serialize(&shop)?, idempotency_key)?;
+        info!("create shop response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_shop: SavedShop = bincode::deserialize(&bytes)?;
-            let body_cache_path = cache_dir.join(format!("shop_{}.bin", saved_shop.id));
-            let metadata_cache_path =
-                cache_dir.join(format!("shop_{}_metadata.json", saved_shop.id));
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            let body_cache_path = self.cache_dir.join(format!("shop_{}.bin", saved_shop.id));
+            let metadata_cache_path = self
+                .cache_dir
+                .join(format!("shop_{}_metadata.json", saved_shop.id));
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_shop.clone(),
+            );
+            update_file_caches::<SavedShop>(body_cache_path, metadata_cache_path, bytes, headers);
             Ok(saved_shop)
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, &name, &description) {
-        Ok(shop) => {
-            info!("create_shop successful");
-            FFIResult::Ok(RawShop::from(shop))
-        }
-        Err(err) => {
-            error!("create_shop failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
+    pub fn update_shop(&self, id: u32, shop: &Shop) -> Result<SavedShop> {
+        let body_cache_path = self.cache_dir.join(format!("shop_{}.bin", id));
+        let metadata_cache_path = self.cache_dir.join(format!("shop_{}_metadata.json", id));
+        self.send_or_queue(
+            || Mutation::UpdateShop {
+                id,
+                shop: shop.clone(),
+                base_etag: self.cached_etag(&body_cache_path, &metadata_cache_path),
+            },
+            || self.send_update_shop(id, shop, None),
+            || self.update_shop_offline(id, shop),
+        )
     }
-}
 
-#[no_mangle]
-pub extern "C" fn update_shop(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    id: u32,
-    name: *const c_char,
-    description: *const c_char,
-    gold: i32,
-    shop_type: *const c_char,
-    vendor_keywords: *mut *const c_char,
-    vendor_keywords_len: usize,
-    vendor_keywords_exclude: bool,
-) -> FFIResult<RawShop> {
-    info!("update_shop begin");
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    let name = unsafe { CStr::from_ptr(name) }
-        .to_string_lossy()
-        .to_string();
-    let description = unsafe { CStr::from_ptr(description) }
-        .to_string_lossy()
-        .to_string();
-    let shop_type = unsafe { CStr::from_ptr(shop_type) }
-        .to_string_lossy()
-        .to_string();
-    let keywords = match vendor_keywords.is_null() {
-        true => vec![],
-        false => unsafe { slice::from_raw_parts(vendor_keywords, vendor_keywords_len) }
-            .iter()
-            .map(|&keyword| {
-                unsafe { CStr::from_ptr(keyword) }
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect(),
-    };
-    info!(
-        "update_shop api_url: {:?}, api_key: {:?}, name: {:?}, description: {:?}, gold: {:?}, shop_type: {:?}, keywords: {:?}, keywords_exclude: {:?}",
-        api_url, api_key, name, description, gold, shop_type, keywords, vendor_keywords_exclude
-    );
-
-    fn inner(
-        api_url: &str,
-        api_key: &str,
+    fn update_shop_offline(&self, id: u32, shop: &Shop) -> Result<SavedShop> {
+        let body_cache_path = self.cache_dir.join(format!("shop_{}.bin", id));
+        let metadata_cache_path = self.cache_dir.join(format!("shop_{}_metadata.json", id));
+        let mut saved_shop: SavedShop = self
+            .memory_cache
+            .load(&body_cache_path, &metadata_cache_path)
+            .context("Server unreachable and there is no cached shop to apply the update to")?;
+        saved_shop.apply_update(shop);
+        self.cache_offline_copy(body_cache_path, metadata_cache_path, &saved_shop)?;
+        Ok(saved_shop)
+    }
+
+    pub(crate) fn send_update_shop(
+        &self,
         id: u32,
-        name: String,
-        description: String,
-        gold: i32,
-        shop_type: String,
-        vendor_keywords: Vec<String>,
-        vendor_keywords_exclude: bool,
+        shop: &Shop,
+        base_etag: Option<&str>,
     ) -> Result<SavedShop> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}", id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join(&format!("v1/shops/{}", id))?;
-
-        let shop = Shop {
-            name,
-            owner_id: None,
-            description: Some(description),
-            gold: Some(gold),
-            shop_type: Some(shop_type),
-            vendor_keywords: Some(vendor_keywords),
-            vendor_keywords_exclude: Some(vendor_keywords_exclude),
-        };
-        info!("created shop from game: {:?}", &shop);
-        let client = reqwest::blocking::Client::new();
-        let resp = client
+        let url = self.url(&format!("v1/shops/{}", id))?;
+        let body_cache_path = self.cache_dir.join(format!("shop_{}.bin", id));
+        let metadata_cache_path = self.cache_dir.join(format!("shop_{}_metadata.json", id));
+        let request = self
+            .http
             .patch(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&shop)?)
-            .send()?;
-        info!("update shop response from api: {:?}", &resp);
+            .body(bincode::serialize(shop)?);
+        let request = self.if_match(request, base_etag, &body_cache_path, &metadata_cache_path);
+        let (resp, attempts) = self.send_counting_attempts(request)?;
+        info!("update shop response from api: {:?}", resp);
 
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}.bin", id));
-        let metadata_cache_path = cache_dir.join(format!("shop_{}_metadata.json", id));
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_shop: SavedShop = bincode::deserialize(&bytes)?;
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            self.memory_cache.insert(
+                &body_cache_path,
+                Metadata::from_headers(&headers),
+                saved_shop.clone(),
+            );
+            update_file_caches::<SavedShop>(body_cache_path, metadata_cache_path, bytes, headers);
             Ok(saved_shop)
+        } else if status == StatusCode::PRECONDITION_FAILED {
+            let current: Option<SavedShop> = self.fetch_server_copy(
+                &format!("v1/shops/{}", id),
+                body_cache_path,
+                metadata_cache_path,
+            );
+            // a retry fails its precondition when the first attempt was applied
+            match current {
+                Some(current) if attempts > 1 && shop.is_applied_to(&current) => Ok(current),
+                current => Err(conflict_from_response(
+                    status,
+                    &bytes,
+                    current.map(ServerCopy::Shop),
+                )),
+            }
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(
-        &api_url,
-        &api_key,
-        id,
-        name,
-        description,
-        gold,
-        shop_type,
-        keywords,
-        vendor_keywords_exclude,
-    ) {
-        Ok(shop) => {
-            info!("update_shop successful");
-            FFIResult::Ok(RawShop::from(shop))
-        }
-        Err(err) => {
-            error!("update_shop failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
+    pub fn get_shop(&self, shop_id: i32) -> Result<SavedShop> {
+        self.get_shop_with_freshness(shop_id)
+            .map(Fetched::into_value)
     }
-}
 
-#[no_mangle]
-pub extern "C" fn get_shop(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    shop_id: i32,
-) -> FFIResult<RawShop> {
-    info!("get_shop begin");
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!(
-        "get_shop api_url: {:?}, api_key: {:?}, shop_id: {:?}",
-        api_url, api_key, shop_id
-    );
-
-    fn inner(api_url: &str, api_key: &str, shop_id: i32) -> Result<SavedShop> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join(&format!("v1/shops/{}", shop_id))?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join(&format!("v1/shops/{}", shop_id))?;
+    pub fn get_shop_with_freshness(&self, shop_id: i32) -> Result<Fetched<SavedShop>> {
+        let url = self.url(&format!("v1/shops/{}", shop_id))?;
         info!("api_url: {:?}", url);
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join(format!("shop_{}.bin", shop_id));
-        let metadata_cache_path = cache_dir.join(format!("shop_{}_metadata.json", shop_id));
-        let mut request = client
+        let body_cache_path = self.cache_dir.join(format!("shop_{}.bin", shop_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_metadata.json", shop_id));
+        let mut request = self
+            .http
             .get(url)
-            .header("Api-Key", api_key)
+            .header("Api-Key", &self.api_key)
             .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
             if let Some(etag) = metadata.etag {
                 request = request.header("If-None-Match", etag);
             }
         }
 
-        match request.send() {
+        match self.send(request) {
             Ok(resp) => {
-                info!("get_shop response from api: {:?}", &resp);
+                info!("get_shop response from api: {:?}", resp);
                 if resp.status().is_success() {
                     let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
+                    let bytes = resp.into_body();
                     let saved_shop: SavedShop = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
-                    Ok(saved_shop)
+                    let metadata = Metadata::from_headers(&headers);
+                    let freshness = Freshness::new(FreshnessSource::Network, Some(&metadata));
+                    self.memory_cache
+                        .insert(&body_cache_path, metadata, saved_shop.clone());
+                    update_file_caches::<SavedShop>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(Fetched {
+                        value: saved_shop,
+                        freshness,
+                    })
                 } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::NotModified,
+                    )
                 } else {
                     log_server_error(resp);
-                    from_file_cache(&body_cache_path)
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::OfflineFallback,
+                    )
                 }
             }
             Err(err) => {
                 error!("get_shop api request error: {}", err);
-                from_file_cache(&body_cache_path)
+                self.memory_cache.load_fetched(
+                    &body_cache_path,
+                    &metadata_cache_path,
+                    FreshnessSource::OfflineFallback,
+                )
             }
         }
     }
 
-    match inner(&api_url, &api_key, shop_id) {
-        Ok(shop) => {
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            FFIResult::Ok(RawShop::from(shop))
+    /// Fetches `limit` shops starting at `offset`. Every page is cached separately, so pages that
+    /// were fetched before can still be read when the API is down.
+    pub fn list_shops_page(&self, limit: i32, offset: i32) -> Result<ShopPage> {
+        self.list_shops_page_with_freshness(limit, offset)
+            .map(Fetched::into_value)
+    }
+
+    pub fn list_shops_page_with_freshness(
+        &self,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Fetched<ShopPage>> {
+        let url = self.url(&format!("v1/shops?limit={}&offset={}", limit, offset))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self.cache_dir.join(format!(
+            "{}{}_offset_{}.bin",
+            SHOPS_PAGE_CACHE_PREFIX, limit, offset
+        ));
+        let metadata_cache_path = self.cache_dir.join(format!(
+            "{}{}_offset_{}_metadata.json",
+            SHOPS_PAGE_CACHE_PREFIX, limit, offset
+        ));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
         }
-        Err(err) => {
-            error!("get_shop failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+
+        let saved_shops: Result<Fetched<Vec<SavedShop>>> = match self.send(request) {
+            Ok(resp) => {
+                info!("list_shops_page response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_shops: Vec<SavedShop> = bincode::deserialize(&bytes)?;
+                    let metadata = Metadata::from_headers(&headers);
+                    let freshness = Freshness::new(FreshnessSource::Network, Some(&metadata));
+                    self.memory_cache
+                        .insert(&body_cache_path, metadata, saved_shops.clone());
+                    update_file_caches::<Vec<SavedShop>>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(Fetched {
+                        value: saved_shops,
+                        freshness,
+                    })
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::NotModified,
+                    )
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache.load_fetched(
+                        &body_cache_path,
+                        &metadata_cache_path,
+                        FreshnessSource::OfflineFallback,
+                    )
+                }
+            }
+            Err(err) => {
+                error!("list_shops_page api request error: {}", err);
+                self.memory_cache.load_fetched(
+                    &body_cache_path,
+                    &metadata_cache_path,
+                    FreshnessSource::OfflineFallback,
+                )
+            }
+        };
+        let Fetched {
+            value: shops,
+            freshness,
+        } = saved_shops?;
+        // a short page means the server has run out of shops
+        let next_offset = match shops.len() as i32 >= limit && limit > 0 {
+            true => Some(offset + shops.len() as i32),
+            false => None,
+        };
+        Ok(Fetched {
+            value: ShopPage { shops, next_offset },
+            freshness,
+        })
+    }
+
+    /// Fetches every shop by walking `list_shops_page` until the last page.
+    pub fn list_shops(&self) -> Result<Vec<SavedShop>> {
+        self.list_shops_with_freshness().map(Fetched::into_value)
+    }
+
+    /// Like `list_shops`. The freshness is that of the least fresh page.
+    pub fn list_shops_with_freshness(&self) -> Result<Fetched<Vec<SavedShop>>> {
+        let mut shops = Vec::new();
+        let mut ids = HashSet::new();
+        let mut freshness: Option<Freshness> = None;
+        let mut offset = 0;
+        let mut page_number = 0;
+        loop {
+            page_number += 1;
+            let page = self.list_shops_page_with_freshness(SHOPS_PAGE_SIZE, offset)?;
+            let ids_before = ids.len();
+            ids.extend(page.value.shops.iter().map(|shop| shop.id));
+            shops.extend(page.value.shops);
+            let combined = match freshness {
+                Some(freshness) => freshness.combine(page.freshness),
+                None => page.freshness,
+            };
+            // a page of shops that were all seen already means the server is repeating itself
+            let next_offset = match page.value.next_offset {
+                Some(_) if ids.len() == ids_before => None,
+                Some(_) if page_number >= MAX_SHOPS_PAGES => {
+                    error!("list_shops stopped after {} pages", page_number);
+                    None
+                }
+                next_offset => next_offset,
+            };
+            match next_offset {
+                Some(next_offset) => {
+                    offset = next_offset;
+                    freshness = Some(combined);
+                }
+                None => {
+                    return Ok(Fetched {
+                        value: shops,
+                        freshness: combined,
+                    })
+                }
+            }
         }
     }
+
+    pub fn list_shops_by_owner(&self, owner_id: i32) -> Result<Vec<SavedShop>> {
+        let url = self.url(&format!("v1/owners/{}/shops", owner_id))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("shops_by_owner_{}.bin", owner_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("shops_by_owner_{}_metadata.json", owner_id));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
+        }
+
+        match self.send(request) {
+            Ok(resp) => {
+                info!("list_shops_by_owner response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_shops: Vec<SavedShop> = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_shops.clone(),
+                    );
+                    update_file_caches::<Vec<SavedShop>>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_shops)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("list_shops_by_owner api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
+        }
+    }
+
+    pub fn delete_shop(&self, shop_id: i32) -> Result<()> {
+        let url = self.url(&format!("v1/shops/{}", shop_id))?;
+        let request = self.http.delete(url).header("Api-Key", &self.api_key);
+        let resp = self.send(request)?;
+        info!("delete_shop response from api: {:?}", resp);
+
+        let status = resp.status();
+        let bytes = resp.into_body();
+        if status.is_success() {
+            // the server deletes the shop's lists along with it
+            for name in &[
+                format!("shop_{}", shop_id),
+                format!("shop_{}_merchandise_list", shop_id),
+                format!("shop_{}_interior_ref_list", shop_id),
+            ] {
+                self.remove_from_caches(
+                    &self.cache_dir.join(format!("{}.bin", name)),
+                    &self.cache_dir.join(format!("{}_metadata.json", name)),
+                );
+            }
+            self.remove_prefixed_from_caches(SHOPS_PAGE_CACHE_PREFIX);
+            self.remove_prefixed_from_caches("shops_by_owner_");
+            Ok(())
+        } else {
+            Err(extract_error_from_response(status, &bytes))
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn client_create_shop(
+    client: *const BazaarClient,
+    name: *const c_char,
+    description: *const c_char,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        let description = str_from_raw(description, "description");
+        info!(
+            "create_shop name: {:?}, description: {:?}",
+            name, description
+        );
+
+        match client_from_ptr(client).and_then(|client| client.create_shop(&name?, &description?)) {
+            Ok(shop) => {
+                info!("create_shop successful");
+                FFIResult::Ok(RawShop::from(shop))
+            }
+            Err(err) => {
+                error!("create_shop failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_create_shop`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`, so the plugin can retry it itself without creating a second shop.
+#[no_mangle]
+pub extern "C" fn client_create_shop_with_idempotency_key(
+    client: *const BazaarClient,
+    name: *const c_char,
+    description: *const c_char,
+    idempotency_key: *const c_char,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        let description = str_from_raw(description, "description");
+        let idempotency_key = str_from_raw(idempotency_key, "idempotency_key");
+        info!(
+            "create_shop_with_idempotency_key name: {:?}, description: {:?}, idempotency_key: {:?}",
+            name, description, idempotency_key
+        );
+
+        match client_from_ptr(client).and_then(|client| {
+            client.create_shop_with_idempotency_key(&name?, &description?, &idempotency_key?)
+        }) {
+            Ok(shop) => {
+                info!("create_shop_with_idempotency_key successful");
+                FFIResult::Ok(RawShop::from(shop))
+            }
+            Err(err) => {
+                error!("create_shop_with_idempotency_key failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn create_shop(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    name: *const c_char,
+    description: *const c_char,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_create_shop(client, name, description)
+        })
+    })
+}
+
+fn shop_from_raw(
+    name: *const c_char,
+    description: *const c_char,
+    gold: i32,
+    shop_type: *const c_char,
+    vendor_keywords: *mut *const c_char,
+    vendor_keywords_len: usize,
+    vendor_keywords_exclude: bool,
+) -> Result<Shop> {
+    let name = str_from_raw(name, "name")?;
+    let description = str_from_raw(description, "description")?;
+    let shop_type = str_from_raw(shop_type, "shop_type")?;
+    let keywords = keywords_from_raw(vendor_keywords, vendor_keywords_len, "vendor_keywords")?;
+    let shop = Shop {
+        name,
+        owner_id: None,
+        description: Some(description),
+        gold: Some(gold),
+        shop_type: Some(shop_type),
+        vendor_keywords: Some(keywords),
+        vendor_keywords_exclude: Some(vendor_keywords_exclude),
+    };
+    info!("created shop from game: {:?}", shop);
+    Ok(shop)
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_shop(
+    client: *const BazaarClient,
+    id: u32,
+    name: *const c_char,
+    description: *const c_char,
+    gold: i32,
+    shop_type: *const c_char,
+    vendor_keywords: *mut *const c_char,
+    vendor_keywords_len: usize,
+    vendor_keywords_exclude: bool,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        info!("update_shop begin");
+        let shop = shop_from_raw(
+            name,
+            description,
+            gold,
+            shop_type,
+            vendor_keywords,
+            vendor_keywords_len,
+            vendor_keywords_exclude,
+        );
+
+        match client_from_ptr(client).and_then(|client| client.update_shop(id, &shop?)) {
+            Ok(shop) => {
+                info!("update_shop successful");
+                FFIResult::Ok(RawShop::from(shop))
+            }
+            Err(err) => {
+                error!("update_shop failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn update_shop(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    id: u32,
+    name: *const c_char,
+    description: *const c_char,
+    gold: i32,
+    shop_type: *const c_char,
+    vendor_keywords: *mut *const c_char,
+    vendor_keywords_len: usize,
+    vendor_keywords_exclude: bool,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_update_shop(
+                client,
+                id,
+                name,
+                description,
+                gold,
+                shop_type,
+                vendor_keywords,
+                vendor_keywords_len,
+                vendor_keywords_exclude,
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_shop(client: *const BazaarClient, shop_id: i32) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        info!("get_shop shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client).and_then(|client| client.get_shop(shop_id)) {
+            Ok(shop) => FFIResult::Ok(RawShop::from(shop)),
+            Err(err) => {
+                error!("get_shop failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_get_shop`, but also writes whether the shop came from the server or the cache to
+/// `freshness`, which may be null.
+#[no_mangle]
+pub extern "C" fn client_get_shop_with_freshness(
+    client: *const BazaarClient,
+    shop_id: i32,
+    freshness: *mut RawFreshness,
+) -> FFIResult<RawShop> {
+    catch_panic(|| {
+        info!("get_shop_with_freshness shop_id: {:?}", shop_id);
+
+        match check_raw_freshness(freshness)
+            .and_then(|_| client_from_ptr(client))
+            .and_then(|client| client.get_shop_with_freshness(shop_id))
+        {
+            Ok(fetched) => {
+                write_raw_freshness(freshness, fetched.freshness);
+                FFIResult::Ok(RawShop::from(fetched.value))
+            }
+            Err(err) => {
+                error!("get_shop_with_freshness failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_shop(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<RawShop> {
+    catch_panic(|| with_legacy_client(api_url, api_key, |client| client_get_shop(client, shop_id)))
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_shops(client: *const BazaarClient) -> FFIResult<RawShopVec> {
+    catch_panic(|| {
+        info!("list_shops");
+
+        match client_from_ptr(client).and_then(|client| client.list_shops()) {
+            Ok(shops) => FFIResult::Ok(RawShopVec::from(shops)),
+            Err(err) => {
+                error!("list_shops failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_list_shops`, but also writes the freshness of the least fresh page to `freshness`,
+/// which may be null.
+#[no_mangle]
+pub extern "C" fn client_list_shops_with_freshness(
+    client: *const BazaarClient,
+    freshness: *mut RawFreshness,
+) -> FFIResult<RawShopVec> {
+    catch_panic(|| {
+        info!("list_shops_with_freshness");
+
+        match check_raw_freshness(freshness)
+            .and_then(|_| client_from_ptr(client))
+            .and_then(|client| client.list_shops_with_freshness())
+        {
+            Ok(fetched) => {
+                write_raw_freshness(freshness, fetched.freshness);
+                FFIResult::Ok(RawShopVec::from(fetched.value))
+            }
+            Err(err) => {
+                error!("list_shops_with_freshness failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
 }
 
 #[no_mangle]
@@ -353,76 +919,232 @@ pub extern "C" fn list_shops(
     api_url: *const c_char,
     api_key: *const c_char,
 ) -> FFIResult<RawShopVec> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    info!("list_shops api_url: {:?}, api_key: {:?}", api_url, api_key);
-
-    fn inner(api_url: &str, api_key: &str) -> Result<Vec<SavedShop>> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/shops?limit=128")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/shops?limit=128")?;
-        info!("api_url: {:?}", url);
+    catch_panic(|| with_legacy_client(api_url, api_key, |client| client_list_shops(client)))
+}
 
-        let client = reqwest::blocking::Client::new();
-        let cache_dir = file_cache_dir(api_url)?;
-        let body_cache_path = cache_dir.join("shops.bin");
-        let metadata_cache_path = cache_dir.join("shops_metadata.json");
-        let mut request = client
-            .get(url)
-            .header("Api-Key", api_key)
-            .header("Accept", "application/octet-stream");
-        // TODO: load metadata from in-memory LRU cache first before trying to load from file
-        if let Ok(metadata) = load_metadata_from_file_cache(&metadata_cache_path) {
-            if let Some(etag) = metadata.etag {
-                request = request.header("If-None-Match", etag);
+#[no_mangle]
+pub extern "C" fn client_list_shops_page(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawShopPage> {
+    catch_panic(|| {
+        info!("list_shops_page limit: {:?}, offset: {:?}", limit, offset);
+
+        match client_from_ptr(client).and_then(|client| client.list_shops_page(limit, offset)) {
+            Ok(page) => FFIResult::Ok(RawShopPage::from(page)),
+            Err(err) => {
+                error!("list_shops_page failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn list_shops_page(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawShopPage> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_list_shops_page(client, limit, offset)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_shops_by_owner(
+    client: *const BazaarClient,
+    owner_id: i32,
+) -> FFIResult<RawShopVec> {
+    catch_panic(|| {
+        info!("list_shops_by_owner owner_id: {:?}", owner_id);
+
+        match client_from_ptr(client).and_then(|client| client.list_shops_by_owner(owner_id)) {
+            Ok(shops) => FFIResult::Ok(RawShopVec::from(shops)),
+            Err(err) => {
+                error!("list_shops_by_owner failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
             }
         }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn list_shops_by_owner(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    owner_id: i32,
+) -> FFIResult<RawShopVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_list_shops_by_owner(client, owner_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_shop(client: *const BazaarClient, shop_id: i32) -> FFIResult<bool> {
+    catch_panic(|| {
+        info!("delete_shop shop_id: {:?}", shop_id);
+
+        match client_from_ptr(client).and_then(|client| client.delete_shop(shop_id)) {
+            Ok(()) => {
+                info!("delete_shop successful");
+                FFIResult::Ok(true)
+            }
+            Err(err) => {
+                error!("delete_shop failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn delete_shop(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+) -> FFIResult<bool> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_delete_shop(client, shop_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_create_shop_async(
+    client: *const BazaarClient,
+    name: *const c_char,
+    description: *const c_char,
+) -> u64 {
+    catch_panic(|| {
+        let name = str_from_raw(name, "name");
+        let description = str_from_raw(description, "description");
+        info!(
+            "create_shop_async name: {:?}, description: {:?}",
+            name, description
+        );
+
+        // invalid arguments are reported through the request's result like any other error
+        submit_request(client, "create_shop", move |client| {
+            RequestOutcome::Shop(name.and_then(|name| client.create_shop(&name, &description?)))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_update_shop_async(
+    client: *const BazaarClient,
+    id: u32,
+    name: *const c_char,
+    description: *const c_char,
+    gold: i32,
+    shop_type: *const c_char,
+    vendor_keywords: *mut *const c_char,
+    vendor_keywords_len: usize,
+    vendor_keywords_exclude: bool,
+) -> u64 {
+    catch_panic(|| {
+        info!("update_shop_async begin");
+        let shop = shop_from_raw(
+            name,
+            description,
+            gold,
+            shop_type,
+            vendor_keywords,
+            vendor_keywords_len,
+            vendor_keywords_exclude,
+        );
+
+        submit_request(client, "update_shop", move |client| {
+            RequestOutcome::Shop(shop.and_then(|shop| client.update_shop(id, &shop)))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_shop_async(client: *const BazaarClient, shop_id: i32) -> u64 {
+    catch_panic(|| {
+        info!("get_shop_async shop_id: {:?}", shop_id);
+        submit_request(client, "get_shop", move |client| {
+            RequestOutcome::Shop(client.get_shop(shop_id))
+        })
+    })
+}
 
-        match request.send() {
-            Ok(resp) => {
-                info!("list_shops response from api: {:?}", &resp);
-                if resp.status().is_success() {
-                    let headers = resp.headers().clone();
-                    let bytes = resp.bytes()?;
-                    let saved_shops: Vec<SavedShop> = bincode::deserialize(&bytes)?;
-                    update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
-                    Ok(saved_shops)
-                } else if resp.status() == StatusCode::NOT_MODIFIED {
-                    from_file_cache(&body_cache_path)
-                } else {
-                    log_server_error(resp);
-                    from_file_cache(&body_cache_path)
-                }
-            }
-            Err(err) => {
-                error!("list_shops api request error: {}", err);
-                from_file_cache(&body_cache_path)
-            }
-        }
-    }
+#[no_mangle]
+pub extern "C" fn client_list_shops_async(client: *const BazaarClient) -> u64 {
+    catch_panic(|| {
+        info!("list_shops_async");
+        submit_request(client, "list_shops", |client| {
+            RequestOutcome::Shops(client.list_shops())
+        })
+    })
+}
 
-    match inner(&api_url, &api_key) {
-        Ok(shops) => {
-            // TODO: need to pass this back into Rust once C++ is done with it so it can be manually dropped and the CStrings dropped from raw pointers.
-            let raw_shops: Vec<RawShop> = shops.into_iter().map(RawShop::from).collect();
-            let (ptr, len, cap) = raw_shops.into_raw_parts();
-            FFIResult::Ok(RawShopVec { ptr, len, cap })
-        }
-        Err(err) => {
-            error!("list_shops failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
-        }
-    }
+#[no_mangle]
+pub extern "C" fn client_list_shops_page_async(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "list_shops_page_async limit: {:?}, offset: {:?}",
+            limit, offset
+        );
+        submit_request(client, "list_shops_page", move |client| {
+            RequestOutcome::ShopPage(client.list_shops_page(limit, offset))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_shops_by_owner_async(
+    client: *const BazaarClient,
+    owner_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!("list_shops_by_owner_async owner_id: {:?}", owner_id);
+        submit_request(client, "list_shops_by_owner", move |client| {
+            RequestOutcome::Shops(client.list_shops_by_owner(owner_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_delete_shop_async(client: *const BazaarClient, shop_id: i32) -> u64 {
+    catch_panic(|| {
+        info!("delete_shop_async shop_id: {:?}", shop_id);
+        submit_request(client, "delete_shop", move |client| {
+            RequestOutcome::Deleted(client.delete_shop(shop_id))
+        })
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{ffi::CString, slice};
+    use std::{ffi::CStr, ffi::CString, slice};
 
     use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        cache::commit_file_caches,
+        client::{test_client, test_client_with_options, ClientOptions},
+        error::Error,
+        fake_api::{fake_client, FakeApi},
+    };
+    use bytes::Bytes;
     use chrono::Utc;
-    use mockito::mock;
+    use mockito::{mock, Matcher};
+    use reqwest::header::HeaderMap;
 
     #[test]
     fn test_create_shop() {
@@ -444,11 +1166,10 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let description = CString::new("description").unwrap().into_raw();
-        let result = create_shop(api_url, api_key, name, description);
+        let result = client_create_shop(&client, name, description);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shop) => {
@@ -476,19 +1197,9 @@ mod tests {
                         .to_string(),
                     "VendorNoSale".to_string(),
                 );
-                assert_eq!(raw_shop.vendor_keywords_exclude, true);
-            }
-            FFIResult::Err(error) => panic!(
-                "create_shop returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+                assert!(raw_shop.vendor_keywords_exclude);
+            }
+            FFIResult::Err(error) => panic!("create_shop returned error: {}", error),
         }
     }
 
@@ -499,11 +1210,10 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let description = CString::new("description").unwrap().into_raw();
-        let result = create_shop(api_url, api_key, name, description);
+        let result = client_create_shop(&client, name, description);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shop) => panic!("create_shop returned Ok result: {:#x?}", raw_shop),
@@ -520,6 +1230,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_create_shop_null_name() {
+        let (_cache_root, client) = test_client();
+        let description = CString::new("description").unwrap().into_raw();
+        let result = client_create_shop(&client, std::ptr::null(), description);
+        match result {
+            FFIResult::Err(FFIError::InvalidArgument { arg_name, reason }) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                    "name"
+                );
+                assert_eq!(
+                    unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                    "is null"
+                );
+            }
+            _ => panic!("create_shop did not return an invalid argument error"),
+        }
+    }
+
+    #[test]
+    fn test_update_shop_null_keyword() {
+        let (_cache_root, client) = test_client();
+        let name = CString::new("name").unwrap().into_raw();
+        let description = CString::new("description").unwrap().into_raw();
+        let shop_type = CString::new("general_store").unwrap().into_raw();
+        let (keywords_ptr, keywords_len, _) = vec![
+            CString::new("VendorNoSale").unwrap().into_raw() as *const c_char,
+            std::ptr::null(),
+        ]
+        .into_raw_parts();
+        let result = client_update_shop(
+            &client,
+            1,
+            name,
+            description,
+            100,
+            shop_type,
+            keywords_ptr,
+            keywords_len,
+            true,
+        );
+        match result {
+            FFIResult::Err(FFIError::InvalidArgument { arg_name, reason }) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(arg_name).to_string_lossy() },
+                    "vendor_keywords[1]"
+                );
+                assert_eq!(
+                    unsafe { CStr::from_ptr(reason).to_string_lossy() },
+                    "is null"
+                );
+            }
+            _ => panic!("update_shop did not return an invalid argument error"),
+        }
+    }
+
     #[test]
     fn test_update_shop() {
         let example = SavedShop {
@@ -540,17 +1307,15 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let description = CString::new("description").unwrap().into_raw();
         let shop_type = CString::new("general_store").unwrap().into_raw();
         let (keywords_ptr, keywords_len, _) =
             vec![CString::new("VendorNoSale").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let result = update_shop(
-            api_url,
-            api_key,
+        let result = client_update_shop(
+            &client,
             1,
             name,
             description,
@@ -587,19 +1352,9 @@ mod tests {
                         .to_string(),
                     "VendorNoSale".to_string(),
                 );
-                assert_eq!(raw_shop.vendor_keywords_exclude, true);
-            }
-            FFIResult::Err(error) => panic!(
-                "update_shop returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+                assert!(raw_shop.vendor_keywords_exclude);
+            }
+            FFIResult::Err(error) => panic!("update_shop returned error: {}", error),
         }
     }
 
@@ -610,17 +1365,15 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let name = CString::new("name").unwrap().into_raw();
         let description = CString::new("description").unwrap().into_raw();
         let shop_type = CString::new("general_store").unwrap().into_raw();
         let (keywords_ptr, keywords_len, _) =
             vec![CString::new("VendorNoSale").unwrap().into_raw() as *const c_char]
                 .into_raw_parts();
-        let result = update_shop(
-            api_url,
-            api_key,
+        let result = client_update_shop(
+            &client,
             1,
             name,
             description,
@@ -646,6 +1399,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_shop_conflict() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        let (_other_cache_root, other_client) = fake_client(&fake, "owner-key");
+
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        other_client.get_shop(shop.id).unwrap();
+        other_client
+            .update_shop(
+                shop.id as u32,
+                &Shop::from_game("Their Shop", "description"),
+            )
+            .unwrap();
+
+        let update = Shop::from_game("My Shop", "description");
+        match client.update_shop(shop.id as u32, &update) {
+            Err(Error::Conflict(conflict_error)) => {
+                assert_eq!(
+                    conflict_error.server_error.status,
+                    StatusCode::PRECONDITION_FAILED
+                );
+                match conflict_error.current {
+                    Some(ServerCopy::Shop(current)) => assert_eq!(current.name, "Their Shop"),
+                    current => panic!("conflict did not carry the server's shop: {:?}", current),
+                }
+            }
+            result => panic!("update_shop did not return a conflict: {:?}", result),
+        }
+        // the server's copy is cached now, so the update can be sent again on top of it
+        assert_eq!(
+            client.update_shop(shop.id as u32, &update).unwrap().name,
+            "My Shop"
+        );
+    }
+
     #[test]
     fn test_get_shop() {
         let example = SavedShop {
@@ -666,9 +1456,8 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_shop(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_shop(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shop) => {
@@ -696,19 +1485,187 @@ mod tests {
                         .to_string(),
                     "VendorNoSale".to_string(),
                 );
-                assert_eq!(raw_shop.vendor_keywords_exclude, true);
-            }
-            FFIResult::Err(error) => panic!(
-                "get_shop returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+                assert!(raw_shop.vendor_keywords_exclude);
+            }
+            FFIResult::Err(error) => panic!("get_shop returned error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_get_shop_with_freshness() {
+        let example = SavedShop {
+            id: 1,
+            owner_id: 1,
+            name: "name".to_string(),
+            description: None,
+            gold: 100,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec![],
+            vendor_keywords_exclude: true,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let mock = mock("GET", "/v1/shops/1")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_header("date", "Sun, 13 Sep 2020 12:26:40 GMT")
+            .with_body(bincode::serialize(&example).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let mut freshness = RawFreshness {
+            source: FreshnessSource::OfflineFallback,
+            date: -1,
+        };
+        let result = client_get_shop_with_freshness(&client, 1, &mut freshness);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_shop) => {
+                assert_eq!(raw_shop.id, 1);
+                assert_eq!(
+                    freshness,
+                    RawFreshness {
+                        source: FreshnessSource::Network,
+                        date: 1_600_000_000,
+                    }
+                );
+            }
+            FFIResult::Err(error) => {
+                panic!("get_shop_with_freshness returned error: {}", error)
+            }
+        }
+    }
+
+    fn example_saved_shop() -> SavedShop {
+        SavedShop {
+            id: 1,
+            owner_id: 1,
+            name: "name".to_string(),
+            description: None,
+            gold: 100,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec![],
+            vendor_keywords_exclude: true,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// Client whose requests all fail to connect, without retrying.
+    fn offline_client() -> (tempfile::TempDir, BazaarClient) {
+        test_client_with_options(
+            "http://127.0.0.1:9",
+            ClientOptions {
+                retry_max_attempts: 1,
+                ..ClientOptions::default()
+            },
+        )
+    }
+
+    fn cache_shop(client: &BazaarClient, contents: &[u8]) {
+        let mut headers = HeaderMap::new();
+        headers.insert("etag", "\"etag\"".parse().unwrap());
+        headers.insert("date", "Sun, 13 Sep 2020 12:26:40 GMT".parse().unwrap());
+        commit_file_caches::<SavedShop>(
+            &client.cache_dir.join("shop_1.bin"),
+            &client.cache_dir.join("shop_1_metadata.json"),
+            &Bytes::from(contents.to_vec()),
+            &headers,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_get_shop_not_modified() {
+        let example = example_saved_shop();
+        let ok_mock = mock("GET", "/v1/shops/1")
+            .match_header("If-None-Match", Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_header("etag", "\"etag\"")
+            .with_body(bincode::serialize(&example).unwrap())
+            .create();
+        let not_modified_mock = mock("GET", "/v1/shops/1")
+            .match_header("If-None-Match", "\"etag\"")
+            .with_status(304)
+            .create();
+
+        // without a memory cache the second request has to go through the file cache
+        let (_cache_root, client) = test_client_with_options(
+            &mockito::server_url(),
+            ClientOptions {
+                memory_cache_capacity: 0,
+                ..ClientOptions::default()
+            },
+        );
+        let fetched = client.get_shop_with_freshness(1).unwrap();
+        assert_eq!(fetched.freshness.source, FreshnessSource::Network);
+        assert!(client.cache_dir.join("shop_1_metadata.json").exists());
+
+        let fetched = client.get_shop_with_freshness(1).unwrap();
+        ok_mock.assert();
+        not_modified_mock.assert();
+        assert_eq!(fetched.freshness.source, FreshnessSource::NotModified);
+        assert_eq!(fetched.value.id, 1);
+        assert_eq!(fetched.value.name, "name");
+    }
+
+    #[test]
+    fn test_get_shop_offline_fallback() {
+        let (_cache_root, client) = offline_client();
+        cache_shop(&client, &bincode::serialize(&example_saved_shop()).unwrap());
+
+        let mut freshness = RawFreshness {
+            source: FreshnessSource::Network,
+            date: -1,
+        };
+        match client_get_shop_with_freshness(&client, 1, &mut freshness) {
+            FFIResult::Ok(raw_shop) => {
+                assert_eq!(raw_shop.id, 1);
+                assert_eq!(
+                    unsafe { CStr::from_ptr(raw_shop.name).to_string_lossy() },
+                    "name"
+                );
+                assert_eq!(
+                    freshness,
+                    RawFreshness {
+                        source: FreshnessSource::OfflineFallback,
+                        date: 1_600_000_000,
+                    }
+                );
+            }
+            FFIResult::Err(error) => panic!("get_shop returned error: {}", error),
+        }
+        // the fallback is kept in memory for the next lookup
+        assert_eq!(client.memory_cache.stats().len, 1);
+    }
+
+    #[test]
+    fn test_get_shop_corrupt_cache() {
+        let (_cache_root, client) = offline_client();
+        cache_shop(&client, b"not a shop");
+        let mut contents = std::fs::read(client.cache_dir.join("shop_1.bin")).unwrap();
+        *contents.last_mut().unwrap() ^= 1;
+        std::fs::write(client.cache_dir.join("shop_1.bin"), &contents).unwrap();
+
+        match client_get_shop(&client, 1) {
+            FFIResult::Ok(raw_shop) => panic!("get_shop returned Ok result: {:?}", raw_shop),
+            FFIResult::Err(FFIError::CacheCorrupt(message)) => {
+                assert_eq!(
+                    unsafe { CStr::from_ptr(message).to_string_lossy() },
+                    "Cache entry shop_1.bin is corrupt: checksum mismatch"
+                );
+            }
+            FFIResult::Err(error) => panic!("get_shop returned wrong error: {}", error),
+        }
+        assert!(!client.cache_dir.join("shop_1.bin").exists());
+        assert!(client.cache_dir.join("shop_1.bin.corrupt").exists());
+        // the ETag of the corrupt body would only get it validated again
+        assert!(!client.cache_dir.join("shop_1_metadata.json").exists());
+
+        match client_get_shop(&client, 1) {
+            FFIResult::Err(FFIError::CacheMiss(_)) => {}
+            result => panic!("get_shop did not return a cache miss error: {:?}", result),
         }
     }
 
@@ -719,20 +1676,19 @@ mod tests {
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = get_shop(api_url, api_key, 1);
+        let (_cache_root, client) = test_client();
+        let result = client_get_shop(&client, 1);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shop) => panic!("get_shop returned Ok result: {:#x?}", raw_shop),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
                         "Object not found in API or in cache: shop_1.bin",
                     );
                 }
-                _ => panic!("get_shop did not return a network error"),
+                _ => panic!("get_shop did not return a cache miss error"),
             },
         }
     }
@@ -751,15 +1707,14 @@ mod tests {
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
         }];
-        let mock = mock("GET", "/v1/shops?limit=128")
+        let mock = mock("GET", "/v1/shops?limit=128&offset=0")
             .with_status(201)
             .with_header("content-type", "application/octet-stream")
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = list_shops(api_url, api_key);
+        let (_cache_root, client) = test_client();
+        let result = client_list_shops(&client);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shops_vec) => {
@@ -792,44 +1747,246 @@ mod tests {
                         .to_string(),
                     "VendorNoSale".to_string(),
                 );
-                assert_eq!(raw_shop.vendor_keywords_exclude, true);
-            }
-            FFIResult::Err(error) => panic!(
-                "list_shops returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+                assert!(raw_shop.vendor_keywords_exclude);
+            }
+            FFIResult::Err(error) => panic!("list_shops returned error: {}", error),
         }
     }
 
     #[test]
     fn test_list_shops_server_error() {
-        let mock = mock("GET", "/v1/shops?limit=128")
+        let mock = mock("GET", "/v1/shops?limit=128&offset=0")
             .with_status(500)
             .with_body("Internal Server Error")
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
-        let result = list_shops(api_url, api_key);
+        let (_cache_root, client) = test_client();
+        let result = client_list_shops(&client);
         mock.assert();
         match result {
             FFIResult::Ok(raw_shop) => panic!("list_shops returned Ok result: {:#x?}", raw_shop),
             FFIResult::Err(error) => match error {
-                FFIError::Network(network_error) => {
+                FFIError::CacheMiss(cache_miss_error) => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
+                        "Object not found in API or in cache: shops_limit_128_offset_0.bin",
+                    );
+                }
+                _ => panic!("list_shops did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_list_shops_page() {
+        let example = vec![
+            SavedShop {
+                id: 3,
+                owner_id: 1,
+                name: "name".to_string(),
+                description: None,
+                gold: 100,
+                shop_type: "general_store".to_string(),
+                vendor_keywords: vec![],
+                vendor_keywords_exclude: true,
+                created_at: Utc::now().naive_utc(),
+                updated_at: Utc::now().naive_utc(),
+            };
+            2
+        ];
+        let full_page_mock = mock("GET", "/v1/shops?limit=2&offset=2")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&example).unwrap())
+            .create();
+        let last_page_mock = mock("GET", "/v1/shops?limit=2&offset=4")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&example[..1]).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let full_page = client_list_shops_page(&client, 2, 2);
+        let last_page = client_list_shops_page(&client, 2, 4);
+        full_page_mock.assert();
+        last_page_mock.assert();
+        match (full_page, last_page) {
+            (FFIResult::Ok(full_page), FFIResult::Ok(last_page)) => {
+                assert_eq!(full_page.shops.len, 2);
+                assert_eq!(full_page.next_offset, 4);
+                assert_eq!(last_page.shops.len, 1);
+                assert_eq!(last_page.next_offset, -1);
+                free_raw_shop_page(full_page);
+                free_raw_shop_page(last_page);
+            }
+            _ => panic!("list_shops_page returned error"),
+        }
+    }
+
+    #[test]
+    fn test_list_shops_walks_pages() {
+        let shop = SavedShop {
+            id: 1,
+            owner_id: 1,
+            name: "name".to_string(),
+            description: None,
+            gold: 100,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec![],
+            vendor_keywords_exclude: true,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let first_page_mock = mock("GET", "/v1/shops?limit=128&offset=0")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&vec![shop.clone(); 128]).unwrap())
+            .create();
+        let second_page_mock = mock("GET", "/v1/shops?limit=128&offset=128")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&vec![shop; 3]).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let shops = client.list_shops().unwrap();
+        first_page_mock.assert();
+        second_page_mock.assert();
+        assert_eq!(shops.len(), 131);
+    }
+
+    #[test]
+    fn test_list_shops_by_owner_server_error() {
+        let mock = mock("GET", "/v1/owners/1/shops")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_list_shops_by_owner(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_shop_vec) => {
+                panic!(
+                    "list_shops_by_owner returned Ok result: {:#x?}",
+                    raw_shop_vec
+                )
+            }
+            FFIResult::Err(error) => match error {
+                FFIError::CacheMiss(cache_miss_error) => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
+                        "Object not found in API or in cache: shops_by_owner_1.bin",
+                    );
+                }
+                _ => panic!("list_shops_by_owner did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_free_raw_shop_vec() {
+        let example = vec![SavedShop {
+            id: 1,
+            owner_id: 1,
+            name: "name".to_string(),
+            description: None,
+            gold: 100,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec!["VendorNoSale".to_string(), "VendorItemMisc".to_string()],
+            vendor_keywords_exclude: true,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }];
+        let raw_shop_vec = RawShopVec::from(example);
+        assert_eq!(raw_shop_vec.len, 1);
+        let raw_shop = unsafe { &*raw_shop_vec.ptr };
+        assert_eq!(raw_shop.vendor_keywords_len, 2);
+        free_raw_shop_vec(raw_shop_vec);
+    }
+
+    #[test]
+    fn test_delete_shop() {
+        let mock = mock("DELETE", "/v1/shops/1").with_status(204).create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_shop(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => assert!(success),
+            FFIResult::Err(error) => panic!("delete_shop returned error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_delete_shop_server_error() {
+        let mock = mock("DELETE", "/v1/shops/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_delete_shop(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(success) => panic!("delete_shop returned Ok result: {:?}", success),
+            FFIResult::Err(error) => match error {
+                FFIError::Server(server_error) => {
+                    assert_eq!(server_error.status, 500);
                     assert_eq!(
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() },
-                        "Object not found in API or in cache: shops.bin",
+                        unsafe { CStr::from_ptr(server_error.title).to_string_lossy() },
+                        "Internal Server Error"
                     );
                 }
-                _ => panic!("list_shops did not return a network error"),
+                _ => panic!("delete_shop did not return a server error"),
             },
         }
     }
+
+    #[test]
+    fn test_delete_shop_cache_removal_failure() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+
+        // a directory where the cached shop should be can't be removed like a file
+        let body_cache_path = client.cache_dir.join(format!("shop_{}.bin", shop.id));
+        std::fs::remove_file(&body_cache_path).ok();
+        std::fs::create_dir_all(&body_cache_path).unwrap();
+
+        // the server has deleted the shop, so that is reported even though the cache wasn't cleared
+        client.delete_shop(shop.id).unwrap();
+        assert!(client.get_shop(shop.id).is_err());
+    }
+
+    #[test]
+    fn test_list_shops_stops_on_repeated_page() {
+        let (_cache_root, client) = offline_client();
+        let shops: Vec<SavedShop> = (1..=SHOPS_PAGE_SIZE)
+            .map(|id| SavedShop {
+                id,
+                ..example_saved_shop()
+            })
+            .collect();
+        // the second page repeats the first, like a server that ignores the offset
+        for offset in &[0, SHOPS_PAGE_SIZE] {
+            commit_file_caches::<Vec<SavedShop>>(
+                &client.cache_dir.join(format!(
+                    "{}{}_offset_{}.bin",
+                    SHOPS_PAGE_CACHE_PREFIX, SHOPS_PAGE_SIZE, offset
+                )),
+                &client.cache_dir.join(format!(
+                    "{}{}_offset_{}_metadata.json",
+                    SHOPS_PAGE_CACHE_PREFIX, SHOPS_PAGE_SIZE, offset
+                )),
+                &Bytes::from(bincode::serialize(&shops).unwrap()),
+                &HeaderMap::new(),
+            )
+            .unwrap();
+        }
+
+        let listed = client.list_shops().unwrap();
+        assert_eq!(listed.len(), 2 * SHOPS_PAGE_SIZE as usize);
+    }
 }
@@ -1,8 +1,7 @@
-use std::{ffi::CStr, ffi::CString, os::raw::c_char, slice};
+use std::{convert::TryFrom, ffi::CString, os::raw::c_char};
 
-use anyhow::Result;
-use chrono::NaiveDateTime;
-use reqwest::Url;
+use chrono::{NaiveDateTime, Utc};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(test))]
@@ -11,13 +10,20 @@ use log::{error, info};
 use std::{println as info, println as error};
 
 use crate::{
-    cache::file_cache_dir,
-    cache::update_file_caches,
-    error::extract_error_from_response,
+    cache::{update_file_caches, CacheSchema, Metadata},
+    client::{client_from_ptr, with_legacy_client, BazaarClient},
+    error::{extract_error_from_response, Error, FieldError, Result, ValidationError},
+    free_raw_keywords, free_raw_string, keywords_from_raw, keywords_into_raw, log_server_error,
+    merchandise_list::SavedMerchandiseList,
+    outbox::Mutation,
+    panic::catch_panic,
+    request::{submit_request, RequestOutcome},
     result::{FFIError, FFIResult},
+    shop::SavedShop,
+    str_from_raw,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     pub shop_id: i32,
     pub owner_id: Option<i32>,
@@ -33,7 +39,7 @@ pub struct Transaction {
     pub keywords: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SavedTransaction {
     pub id: i32,
     pub owner_id: i32,
@@ -52,38 +58,32 @@ pub struct SavedTransaction {
     pub updated_at: NaiveDateTime,
 }
 
-impl From<RawTransaction> for Transaction {
-    fn from(raw_transaction: RawTransaction) -> Self {
-        Self {
+impl CacheSchema for SavedTransaction {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+impl TryFrom<RawTransaction> for Transaction {
+    type Error = Error;
+
+    fn try_from(raw_transaction: RawTransaction) -> Result<Self> {
+        Ok(Self {
             shop_id: raw_transaction.shop_id,
             owner_id: None,
-            mod_name: unsafe { CStr::from_ptr(raw_transaction.mod_name) }
-                .to_string_lossy()
-                .to_string(),
+            mod_name: str_from_raw(raw_transaction.mod_name, "raw_transaction.mod_name")?,
             local_form_id: raw_transaction.local_form_id,
-            name: unsafe { CStr::from_ptr(raw_transaction.name) }
-                .to_string_lossy()
-                .to_string(),
+            name: str_from_raw(raw_transaction.name, "raw_transaction.name")?,
             form_type: raw_transaction.form_type,
             is_food: raw_transaction.is_food,
             price: raw_transaction.price,
             is_sell: raw_transaction.is_sell,
             quantity: raw_transaction.quantity,
             amount: raw_transaction.amount,
-            keywords: match raw_transaction.keywords.is_null() {
-                true => vec![],
-                false => unsafe {
-                    slice::from_raw_parts(raw_transaction.keywords, raw_transaction.keywords_len)
-                }
-                .iter()
-                .map(|&keyword| {
-                    unsafe { CStr::from_ptr(keyword) }
-                        .to_string_lossy()
-                        .to_string()
-                })
-                .collect(),
-            },
-        }
+            keywords: keywords_from_raw(
+                raw_transaction.keywords,
+                raw_transaction.keywords_len,
+                "raw_transaction.keywords",
+            )?,
+        })
     }
 }
 
@@ -107,12 +107,7 @@ pub struct RawTransaction {
 
 impl From<SavedTransaction> for RawTransaction {
     fn from(transaction: SavedTransaction) -> Self {
-        let (keywords_ptr, keywords_len, _) = transaction
-            .keywords
-            .into_iter()
-            .map(|keyword| CString::new(keyword).unwrap_or_default().into_raw() as *const c_char)
-            .collect::<Vec<*const c_char>>()
-            .into_raw_parts();
+        let (keywords_ptr, keywords_len) = keywords_into_raw(transaction.keywords);
         Self {
             id: transaction.id,
             shop_id: transaction.shop_id,
@@ -135,6 +130,15 @@ impl From<SavedTransaction> for RawTransaction {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn free_raw_transaction(raw_transaction: RawTransaction) {
+    catch_panic(|| unsafe {
+        free_raw_string(raw_transaction.mod_name);
+        free_raw_string(raw_transaction.name);
+        free_raw_keywords(raw_transaction.keywords, raw_transaction.keywords_len);
+    })
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct RawTransactionVec {
@@ -143,71 +147,910 @@ pub struct RawTransactionVec {
     pub cap: usize,
 }
 
+impl From<Vec<SavedTransaction>> for RawTransactionVec {
+    fn from(transactions: Vec<SavedTransaction>) -> Self {
+        let (ptr, len, cap) = transactions
+            .into_iter()
+            .map(RawTransaction::from)
+            .collect::<Vec<RawTransaction>>()
+            .into_raw_parts();
+        Self { ptr, len, cap }
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn create_transaction(
-    api_url: *const c_char,
-    api_key: *const c_char,
-    raw_transaction: RawTransaction,
-) -> FFIResult<RawTransaction> {
-    let api_url = unsafe { CStr::from_ptr(api_url) }.to_string_lossy();
-    let api_key = unsafe { CStr::from_ptr(api_key) }.to_string_lossy();
-    let transaction = Transaction::from(raw_transaction);
-    info!(
-        "create_transaction api_url: {:?}, api_key: {:?}, transaction: {:?}",
-        api_url, api_key, transaction
-    );
-
-    fn inner(api_url: &str, api_key: &str, transaction: Transaction) -> Result<SavedTransaction> {
-        #[cfg(not(test))]
-        let url = Url::parse(api_url)?.join("v1/transactions")?;
-        #[cfg(test)]
-        let url = Url::parse(&mockito::server_url())?.join("v1/transactions")?;
-
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .post(url)
-            .header("Api-Key", api_key)
-            .header("Content-Type", "application/octet-stream")
-            .body(bincode::serialize(&transaction)?)
-            .send()?;
-        info!("create transaction response from api: {:?}", &resp);
-
-        let cache_dir = file_cache_dir(api_url)?;
+pub extern "C" fn free_raw_transaction_vec(raw_transaction_vec: RawTransactionVec) {
+    catch_panic(|| {
+        if raw_transaction_vec.ptr.is_null() {
+            return;
+        }
+        let raw_transactions = unsafe {
+            Vec::from_raw_parts(
+                raw_transaction_vec.ptr,
+                raw_transaction_vec.len,
+                raw_transaction_vec.cap,
+            )
+        };
+        for raw_transaction in raw_transactions {
+            free_raw_transaction(raw_transaction);
+        }
+    })
+}
+
+/// The shop's gold once `transaction` is made: selling to the shop costs it `amount`, buying from
+/// it earns it.
+pub(crate) fn shop_gold_after(
+    gold: i32,
+    transaction: &Transaction,
+) -> Result<i32, ValidationError> {
+    match transaction.is_sell {
+        true => gold.checked_sub(transaction.amount),
+        false => gold.checked_add(transaction.amount),
+    }
+    .ok_or_else(|| ValidationError {
+        field_errors: vec![FieldError::new("amount", "overflows the shop's gold")],
+    })
+}
+
+impl Transaction {
+    /// Checks the transaction on its own and against the cached shop and merchandise list, if
+    /// there are any.
+    pub fn validate(
+        &self,
+        shop: Option<&SavedShop>,
+        merchandise_list: Option<&SavedMerchandiseList>,
+    ) -> Result<(), ValidationError> {
+        let mut field_errors = vec![];
+        for (field, value) in [
+            ("local_form_id", self.local_form_id),
+            ("form_type", self.form_type),
+            ("price", self.price),
+            ("quantity", self.quantity),
+        ]
+        .iter()
+        {
+            if *value < 0 {
+                field_errors.push(FieldError::new(field, "must not be negative"));
+            }
+        }
+        match self.price.checked_mul(self.quantity) {
+            Some(total) if total == self.amount => {}
+            Some(total) => field_errors.push(FieldError::new(
+                "amount",
+                &format!("must be price * quantity ({})", total),
+            )),
+            None => field_errors.push(FieldError::new("amount", "price * quantity overflows")),
+        }
+        if let (false, Some(merchandise_list)) = (self.is_sell, merchandise_list) {
+            let in_stock = merchandise_list
+                .form_list
+                .iter()
+                .find(|merchandise| {
+                    merchandise.mod_name == self.mod_name
+                        && merchandise.local_form_id as i32 == self.local_form_id
+                })
+                .map_or(0, |merchandise| merchandise.quantity as i64);
+            if self.quantity as i64 > in_stock {
+                field_errors.push(FieldError::new(
+                    "quantity",
+                    &format!("is more than the {} in stock", in_stock),
+                ));
+            }
+        }
+        if let Some(shop) = shop {
+            if let Err(err) = shop_gold_after(shop.gold, self) {
+                field_errors.extend(err.field_errors);
+            }
+        }
+        if let (true, Some(shop)) = (self.is_sell, shop) {
+            if self.amount > shop.gold {
+                field_errors.push(FieldError::new(
+                    "amount",
+                    &format!("is more than the shop's {} gold", shop.gold),
+                ));
+            }
+        }
+        match field_errors.is_empty() {
+            true => Ok(()),
+            false => Err(ValidationError { field_errors }),
+        }
+    }
+}
+
+impl BazaarClient {
+    /// Validates `transaction` against the cached shop and merchandise list without contacting the
+    /// server. Checks that need a cached value are skipped if it isn't cached.
+    pub fn validate_transaction(&self, transaction: &Transaction) -> Result<()> {
+        let shop_id = transaction.shop_id;
+        let shop = self
+            .memory_cache
+            .load::<SavedShop>(
+                &self.cache_dir.join(format!("shop_{}.bin", shop_id)),
+                &self
+                    .cache_dir
+                    .join(format!("shop_{}_metadata.json", shop_id)),
+            )
+            .ok();
+        let merchandise_list = self
+            .memory_cache
+            .load::<SavedMerchandiseList>(
+                &self
+                    .cache_dir
+                    .join(format!("shop_{}_merchandise_list.bin", shop_id)),
+                &self
+                    .cache_dir
+                    .join(format!("shop_{}_merchandise_list_metadata.json", shop_id)),
+            )
+            .ok();
+        transaction.validate(shop.as_ref(), merchandise_list.as_ref())?;
+        Ok(())
+    }
+
+    pub fn create_transaction(&self, transaction: &Transaction) -> Result<SavedTransaction> {
+        let key = self.idempotency_key_for("v1/transactions", &bincode::serialize(transaction)?)?;
+        self.create_transaction_with_idempotency_key(transaction, &key)
+    }
+
+    /// Like `create_transaction`, but sent with `idempotency_key` instead of a new key. The key is
+    /// kept with the transaction if it is queued in the outbox and sent again when it is replayed.
+    pub fn create_transaction_with_idempotency_key(
+        &self,
+        transaction: &Transaction,
+        idempotency_key: &str,
+    ) -> Result<SavedTransaction> {
+        self.validate_transaction(transaction)?;
+        self.send_or_queue(
+            || Mutation::CreateTransaction {
+                transaction: transaction.clone(),
+                idempotency_key: idempotency_key.to_string(),
+            },
+            || self.send_create_transaction(transaction, idempotency_key),
+            || self.create_transaction_offline(transaction),
+        )
+    }
+
+    /// Applies a queued transaction to the cached shop gold and merchandise stock. The returned
+    /// transaction has an `id` of 0 since the server hasn't assigned one yet.
+    fn create_transaction_offline(&self, transaction: &Transaction) -> Result<SavedTransaction> {
+        let shop_id = transaction.shop_id;
+        let mut owner_id = transaction.owner_id.unwrap_or(0);
+
+        // both copies are changed before either is cached, so a rejected transaction changes neither
+        let list_body_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list.bin", shop_id));
+        let list_metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_merchandise_list_metadata.json", shop_id));
+        let saved_merchandise_list = match self
+            .memory_cache
+            .load::<SavedMerchandiseList>(&list_body_cache_path, &list_metadata_cache_path)
+        {
+            Ok(mut saved_merchandise_list) => {
+                saved_merchandise_list.apply_transaction(transaction)?;
+                Some(saved_merchandise_list)
+            }
+            Err(_) => None,
+        };
+
+        let shop_body_cache_path = self.cache_dir.join(format!("shop_{}.bin", shop_id));
+        let shop_metadata_cache_path = self
+            .cache_dir
+            .join(format!("shop_{}_metadata.json", shop_id));
+        let saved_shop = match self
+            .memory_cache
+            .load::<SavedShop>(&shop_body_cache_path, &shop_metadata_cache_path)
+        {
+            Ok(mut saved_shop) => {
+                owner_id = saved_shop.owner_id;
+                saved_shop.gold = shop_gold_after(saved_shop.gold, transaction)?;
+                Some(saved_shop)
+            }
+            Err(_) => None,
+        };
+
+        if let Some(saved_merchandise_list) = saved_merchandise_list {
+            self.cache_offline_copy(
+                list_body_cache_path,
+                list_metadata_cache_path,
+                &saved_merchandise_list,
+            )?;
+        }
+        if let Some(saved_shop) = saved_shop {
+            self.cache_offline_copy(shop_body_cache_path, shop_metadata_cache_path, &saved_shop)?;
+        }
+
+        let now = Utc::now().naive_utc();
+        Ok(SavedTransaction {
+            id: 0,
+            owner_id,
+            shop_id,
+            mod_name: transaction.mod_name.clone(),
+            local_form_id: transaction.local_form_id,
+            name: transaction.name.clone(),
+            form_type: transaction.form_type,
+            is_food: transaction.is_food,
+            price: transaction.price,
+            is_sell: transaction.is_sell,
+            quantity: transaction.quantity,
+            amount: transaction.amount,
+            keywords: transaction.keywords.clone(),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub(crate) fn send_create_transaction(
+        &self,
+        transaction: &Transaction,
+        idempotency_key: &str,
+    ) -> Result<SavedTransaction> {
+        let resp = self.send_post(
+            "v1/transactions",
+            bincode::serialize(transaction)?,
+            idempotency_key,
+        )?;
+        info!("create transaction response from api: {:?}", resp);
+
         let headers = resp.headers().clone();
         let status = resp.status();
-        let bytes = resp.bytes()?;
+        let bytes = resp.into_body();
         if status.is_success() {
             let saved_transaction: SavedTransaction = bincode::deserialize(&bytes)?;
-            let body_cache_path =
-                cache_dir.join(format!("transaction_{}.bin", saved_transaction.id));
-            let metadata_cache_path = cache_dir.join(format!(
+            let body_cache_path = self
+                .cache_dir
+                .join(format!("transaction_{}.bin", saved_transaction.id));
+            let metadata_cache_path = self.cache_dir.join(format!(
                 "transaction_{}_metadata.json",
                 saved_transaction.id
             ));
-            update_file_caches(body_cache_path, metadata_cache_path, bytes, headers);
+            update_file_caches::<SavedTransaction>(
+                body_cache_path,
+                metadata_cache_path,
+                bytes,
+                headers,
+            );
             Ok(saved_transaction)
         } else {
             Err(extract_error_from_response(status, &bytes))
         }
     }
 
-    match inner(&api_url, &api_key, transaction) {
-        Ok(transaction) => FFIResult::Ok(RawTransaction::from(transaction)),
-        Err(err) => {
-            error!("create_transaction failed. {}", err);
-            FFIResult::Err(FFIError::from(err))
+    pub fn get_transaction(&self, transaction_id: i32) -> Result<SavedTransaction> {
+        let url = self.url(&format!("v1/transactions/{}", transaction_id))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self
+            .cache_dir
+            .join(format!("transaction_{}.bin", transaction_id));
+        let metadata_cache_path = self
+            .cache_dir
+            .join(format!("transaction_{}_metadata.json", transaction_id));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
+        }
+
+        match self.send(request) {
+            Ok(resp) => {
+                info!("get_transaction response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_transaction: SavedTransaction = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_transaction.clone(),
+                    );
+                    update_file_caches::<SavedTransaction>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_transaction)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("get_transaction api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
+        }
+    }
+
+    pub fn list_transactions(&self, limit: i32, offset: i32) -> Result<Vec<SavedTransaction>> {
+        let url = self.url(&format!(
+            "v1/transactions?limit={}&offset={}",
+            limit, offset
+        ))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self.cache_dir.join(format!(
+            "transactions_limit_{}_offset_{}.bin",
+            limit, offset
+        ));
+        let metadata_cache_path = self.cache_dir.join(format!(
+            "transactions_limit_{}_offset_{}_metadata.json",
+            limit, offset
+        ));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
+        }
+
+        match self.send(request) {
+            Ok(resp) => {
+                info!("list_transactions response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_transactions: Vec<SavedTransaction> = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_transactions.clone(),
+                    );
+                    update_file_caches::<Vec<SavedTransaction>>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_transactions)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("list_transactions api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
+        }
+    }
+
+    pub fn list_transactions_by_shop_id(
+        &self,
+        shop_id: i32,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<SavedTransaction>> {
+        let url = self.url(&format!(
+            "v1/shops/{}/transactions?limit={}&offset={}",
+            shop_id, limit, offset
+        ))?;
+        info!("api_url: {:?}", url);
+
+        let body_cache_path = self.cache_dir.join(format!(
+            "shop_{}_transactions_limit_{}_offset_{}.bin",
+            shop_id, limit, offset
+        ));
+        let metadata_cache_path = self.cache_dir.join(format!(
+            "shop_{}_transactions_limit_{}_offset_{}_metadata.json",
+            shop_id, limit, offset
+        ));
+        let mut request = self
+            .http
+            .get(url)
+            .header("Api-Key", &self.api_key)
+            .header("Accept", "application/octet-stream");
+        if let Ok(metadata) = self
+            .memory_cache
+            .load_metadata(&body_cache_path, &metadata_cache_path)
+        {
+            if let Some(etag) = metadata.etag {
+                request = request.header("If-None-Match", etag);
+            }
+        }
+
+        match self.send(request) {
+            Ok(resp) => {
+                info!("list_transactions_by_shop_id response from api: {:?}", resp);
+                if resp.status().is_success() {
+                    let headers = resp.headers().clone();
+                    let bytes = resp.into_body();
+                    let saved_transactions: Vec<SavedTransaction> = bincode::deserialize(&bytes)?;
+                    self.memory_cache.insert(
+                        &body_cache_path,
+                        Metadata::from_headers(&headers),
+                        saved_transactions.clone(),
+                    );
+                    update_file_caches::<Vec<SavedTransaction>>(
+                        body_cache_path,
+                        metadata_cache_path,
+                        bytes,
+                        headers,
+                    );
+                    Ok(saved_transactions)
+                } else if resp.status() == StatusCode::NOT_MODIFIED {
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                } else {
+                    log_server_error(resp);
+                    self.memory_cache
+                        .load(&body_cache_path, &metadata_cache_path)
+                }
+            }
+            Err(err) => {
+                error!("list_transactions_by_shop_id api request error: {}", err);
+                self.memory_cache
+                    .load(&body_cache_path, &metadata_cache_path)
+            }
         }
     }
 }
 
+#[no_mangle]
+pub extern "C" fn client_create_transaction(
+    client: *const BazaarClient,
+    raw_transaction: RawTransaction,
+) -> FFIResult<RawTransaction> {
+    catch_panic(|| {
+        let transaction = Transaction::try_from(raw_transaction);
+        info!("create_transaction transaction: {:?}", transaction);
+
+        match client_from_ptr(client).and_then(|client| client.create_transaction(&transaction?)) {
+            Ok(transaction) => FFIResult::Ok(RawTransaction::from(transaction)),
+            Err(err) => {
+                error!("create_transaction failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+/// Like `client_create_transaction`, but sent with `idempotency_key`, e.g. one from
+/// `generate_idempotency_key`. Retrying a transaction that timed out with the same key can't make
+/// the purchase twice.
+#[no_mangle]
+pub extern "C" fn client_create_transaction_with_idempotency_key(
+    client: *const BazaarClient,
+    raw_transaction: RawTransaction,
+    idempotency_key: *const c_char,
+) -> FFIResult<RawTransaction> {
+    catch_panic(|| {
+        let transaction = Transaction::try_from(raw_transaction);
+        let idempotency_key = str_from_raw(idempotency_key, "idempotency_key");
+        info!(
+            "create_transaction_with_idempotency_key transaction: {:?}, idempotency_key: {:?}",
+            transaction, idempotency_key
+        );
+
+        match client_from_ptr(client).and_then(|client| {
+            client.create_transaction_with_idempotency_key(&transaction?, &idempotency_key?)
+        }) {
+            Ok(transaction) => FFIResult::Ok(RawTransaction::from(transaction)),
+            Err(err) => {
+                error!("create_transaction_with_idempotency_key failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn create_transaction(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    raw_transaction: RawTransaction,
+) -> FFIResult<RawTransaction> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_create_transaction(client, raw_transaction)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_transaction(
+    client: *const BazaarClient,
+    transaction_id: i32,
+) -> FFIResult<RawTransaction> {
+    catch_panic(|| {
+        info!("get_transaction transaction_id: {:?}", transaction_id);
+
+        match client_from_ptr(client).and_then(|client| client.get_transaction(transaction_id)) {
+            Ok(transaction) => FFIResult::Ok(RawTransaction::from(transaction)),
+            Err(err) => {
+                error!("get_transaction failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_transaction(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    transaction_id: i32,
+) -> FFIResult<RawTransaction> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_get_transaction(client, transaction_id)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_transactions(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawTransactionVec> {
+    catch_panic(|| {
+        info!("list_transactions limit: {:?}, offset: {:?}", limit, offset);
+
+        match client_from_ptr(client).and_then(|client| client.list_transactions(limit, offset)) {
+            Ok(transactions) => FFIResult::Ok(RawTransactionVec::from(transactions)),
+            Err(err) => {
+                error!("list_transactions failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn list_transactions(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawTransactionVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_list_transactions(client, limit, offset)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_transactions_by_shop_id(
+    client: *const BazaarClient,
+    shop_id: i32,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawTransactionVec> {
+    catch_panic(|| {
+        info!(
+            "list_transactions_by_shop_id shop_id: {:?}, limit: {:?}, offset: {:?}",
+            shop_id, limit, offset
+        );
+
+        match client_from_ptr(client)
+            .and_then(|client| client.list_transactions_by_shop_id(shop_id, limit, offset))
+        {
+            Ok(transactions) => FFIResult::Ok(RawTransactionVec::from(transactions)),
+            Err(err) => {
+                error!("list_transactions_by_shop_id failed. {}", err);
+                FFIResult::Err(FFIError::from(err))
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn list_transactions_by_shop_id(
+    api_url: *const c_char,
+    api_key: *const c_char,
+    shop_id: i32,
+    limit: i32,
+    offset: i32,
+) -> FFIResult<RawTransactionVec> {
+    catch_panic(|| {
+        with_legacy_client(api_url, api_key, |client| {
+            client_list_transactions_by_shop_id(client, shop_id, limit, offset)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_create_transaction_async(
+    client: *const BazaarClient,
+    raw_transaction: RawTransaction,
+) -> u64 {
+    catch_panic(|| {
+        let transaction = Transaction::try_from(raw_transaction);
+        info!("create_transaction_async transaction: {:?}", transaction);
+
+        submit_request(client, "create_transaction", move |client| {
+            RequestOutcome::Transaction(
+                transaction.and_then(|transaction| client.create_transaction(&transaction)),
+            )
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_get_transaction_async(
+    client: *const BazaarClient,
+    transaction_id: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!("get_transaction_async transaction_id: {:?}", transaction_id);
+        submit_request(client, "get_transaction", move |client| {
+            RequestOutcome::Transaction(client.get_transaction(transaction_id))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_transactions_async(
+    client: *const BazaarClient,
+    limit: i32,
+    offset: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "list_transactions_async limit: {:?}, offset: {:?}",
+            limit, offset
+        );
+        submit_request(client, "list_transactions", move |client| {
+            RequestOutcome::Transactions(client.list_transactions(limit, offset))
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn client_list_transactions_by_shop_id_async(
+    client: *const BazaarClient,
+    shop_id: i32,
+    limit: i32,
+    offset: i32,
+) -> u64 {
+    catch_panic(|| {
+        info!(
+            "list_transactions_by_shop_id_async shop_id: {:?}, limit: {:?}, offset: {:?}",
+            shop_id, limit, offset
+        );
+        submit_request(client, "list_transactions_by_shop_id", move |client| {
+            RequestOutcome::Transactions(
+                client.list_transactions_by_shop_id(shop_id, limit, offset),
+            )
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::CString;
+    use std::{ffi::CStr, ffi::CString, slice};
+
+    use std::sync::Arc;
 
     use super::*;
+    use crate::{
+        client::test_client,
+        fake_api::{fake_client, FakeApi},
+        merchandise_list::{Merchandise, MerchandiseList},
+        result::free_ffi_error,
+    };
     use chrono::Utc;
     use mockito::mock;
 
+    fn example_transaction(shop_id: i32) -> Transaction {
+        Transaction {
+            shop_id,
+            owner_id: None,
+            mod_name: "Skyrim.esm".to_string(),
+            local_form_id: 1,
+            name: "Iron Sword".to_string(),
+            form_type: 41,
+            is_food: false,
+            price: 25,
+            is_sell: false,
+            quantity: 2,
+            amount: 50,
+            keywords: vec!["VendorItemWeapon".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let now = Utc::now().naive_utc();
+        let shop = SavedShop {
+            id: 1,
+            owner_id: 1,
+            name: "Shop".to_string(),
+            description: None,
+            gold: 40,
+            shop_type: "general_store".to_string(),
+            vendor_keywords: vec![],
+            vendor_keywords_exclude: false,
+            created_at: now,
+            updated_at: now,
+        };
+        let merchandise_list = SavedMerchandiseList {
+            id: 1,
+            shop_id: 1,
+            owner_id: 1,
+            form_list: vec![Merchandise {
+                mod_name: "Skyrim.esm".to_string(),
+                local_form_id: 1,
+                name: "Iron Sword".to_string(),
+                quantity: 3,
+                form_type: 41,
+                is_food: false,
+                price: 25,
+                keywords: vec![],
+            }],
+            created_at: now,
+            updated_at: now,
+        };
+        let validate = |transaction: &Transaction| {
+            transaction
+                .validate(Some(&shop), Some(&merchandise_list))
+                .map_err(|err| err.field_errors)
+        };
+
+        let mut transaction = example_transaction(1);
+        assert_eq!(validate(&transaction), Ok(()));
+        assert_eq!(transaction.validate(None, None), Ok(()));
+
+        transaction.quantity = -2;
+        assert_eq!(
+            validate(&transaction),
+            Err(vec![
+                FieldError::new("quantity", "must not be negative"),
+                FieldError::new("amount", "must be price * quantity (-50)"),
+            ])
+        );
+
+        let mut negative = example_transaction(1);
+        negative.local_form_id = -1;
+        negative.form_type = -41;
+        negative.price = -25;
+        negative.quantity = -1;
+        negative.amount = 25;
+        assert_eq!(
+            validate(&negative),
+            Err(vec![
+                FieldError::new("local_form_id", "must not be negative"),
+                FieldError::new("form_type", "must not be negative"),
+                FieldError::new("price", "must not be negative"),
+                FieldError::new("quantity", "must not be negative"),
+            ])
+        );
+
+        transaction.quantity = 4;
+        transaction.amount = 100;
+        assert_eq!(
+            validate(&transaction),
+            Err(vec![FieldError::new(
+                "quantity",
+                "is more than the 3 in stock"
+            )])
+        );
+        assert_eq!(transaction.validate(Some(&shop), None), Ok(()));
+
+        transaction.is_sell = true;
+        assert_eq!(
+            validate(&transaction),
+            Err(vec![FieldError::new(
+                "amount",
+                "is more than the shop's 40 gold"
+            )])
+        );
+
+        let rich_shop = SavedShop {
+            gold: i32::MAX - 10,
+            ..shop.clone()
+        };
+        let mut expensive = example_transaction(1);
+        expensive.price = 1_000_000_000;
+        expensive.amount = 2_000_000_000;
+        assert_eq!(
+            expensive
+                .validate(Some(&rich_shop), Some(&merchandise_list))
+                .map_err(|err| err.field_errors),
+            Err(vec![FieldError::new("amount", "overflows the shop's gold")])
+        );
+
+        transaction.local_form_id = 2;
+        transaction.is_sell = false;
+        transaction.quantity = 1;
+        transaction.amount = 25;
+        assert_eq!(
+            validate(&transaction),
+            Err(vec![FieldError::new(
+                "quantity",
+                "is more than the 0 in stock"
+            )])
+        );
+    }
+
+    #[test]
+    fn test_create_transaction_validation_error() {
+        let fake = Arc::new(FakeApi::new());
+        let (_cache_root, client) = fake_client(&fake, "owner-key");
+        client.create_owner("Owner", 1).unwrap();
+        let shop = client.create_shop("Shop", "description").unwrap();
+        client
+            .create_merchandise_list(&MerchandiseList {
+                shop_id: shop.id,
+                owner_id: None,
+                form_list: vec![Merchandise {
+                    mod_name: "Skyrim.esm".to_string(),
+                    local_form_id: 1,
+                    name: "Iron Sword".to_string(),
+                    quantity: 1,
+                    form_type: 41,
+                    is_food: false,
+                    price: 25,
+                    keywords: vec![],
+                }],
+            })
+            .unwrap();
+        // fetched by shop like the game does before trading, which caches it for validation
+        client.get_merchandise_list_by_shop_id(shop.id).unwrap();
+        // an invalid transaction that got as far as the network would be queued in the outbox
+        fake.set_offline(true);
+
+        let mod_name = CString::new("Skyrim.esm").unwrap().into_raw();
+        let name = CString::new("Iron Sword").unwrap().into_raw();
+        let raw_transaction = RawTransaction {
+            id: 0,
+            shop_id: shop.id,
+            mod_name,
+            local_form_id: 1,
+            name,
+            form_type: 41,
+            is_food: false,
+            price: 25,
+            is_sell: false,
+            amount: 40,
+            quantity: 2,
+            keywords: std::ptr::null_mut(),
+            keywords_len: 0,
+        };
+        match client_create_transaction(&client, raw_transaction) {
+            FFIResult::Ok(raw_transaction) => panic!(
+                "create_transaction returned Ok result: {:#x?}",
+                raw_transaction
+            ),
+            FFIResult::Err(error) => {
+                assert_eq!(
+                    error.to_string(),
+                    "Validation amount must be price * quantity (50), quantity is more than the 1 in stock"
+                );
+                free_ffi_error(error);
+            }
+        }
+        assert!(client.outbox.is_empty());
+    }
+
     #[test]
     fn test_create_transaction() {
         let example = SavedTransaction {
@@ -233,8 +1076,7 @@ mod tests {
             .with_body(bincode::serialize(&example).unwrap())
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let mod_name = CString::new("Skyrim.esm").unwrap().into_raw();
         let name = CString::new("Item").unwrap().into_raw();
         let (keywords, keywords_len, _) =
@@ -255,7 +1097,7 @@ mod tests {
             keywords,
             keywords_len,
         };
-        let result = create_transaction(api_url, api_key, raw_transaction);
+        let result = client_create_transaction(&client, raw_transaction);
         mock.assert();
         match result {
             FFIResult::Ok(raw_transaction) => {
@@ -271,9 +1113,9 @@ mod tests {
                     "Item"
                 );
                 assert_eq!(raw_transaction.form_type, 41);
-                assert_eq!(raw_transaction.is_food, false);
+                assert!(!raw_transaction.is_food);
                 assert_eq!(raw_transaction.price, 100);
-                assert_eq!(raw_transaction.is_sell, false);
+                assert!(!raw_transaction.is_sell);
                 assert_eq!(raw_transaction.quantity, 1);
                 assert_eq!(raw_transaction.amount, 100);
                 assert_eq!(raw_transaction.keywords_len, 1);
@@ -292,17 +1134,7 @@ mod tests {
                     vec!["VendorItemMisc".to_string()]
                 );
             }
-            FFIResult::Err(error) => panic!(
-                "create_transaction returned error: {:?}",
-                match error {
-                    FFIError::Server(server_error) =>
-                        format!("{} {}", server_error.status, unsafe {
-                            CStr::from_ptr(server_error.title).to_string_lossy()
-                        }),
-                    FFIError::Network(network_error) =>
-                        unsafe { CStr::from_ptr(network_error).to_string_lossy() }.to_string(),
-                }
-            ),
+            FFIResult::Err(error) => panic!("create_transaction returned error: {}", error),
         }
     }
 
@@ -321,8 +1153,7 @@ mod tests {
             )
             .create();
 
-        let api_url = CString::new("url").unwrap().into_raw();
-        let api_key = CString::new("api-key").unwrap().into_raw();
+        let (_cache_root, client) = test_client();
         let mod_name = CString::new("Skyrim.esm").unwrap().into_raw();
         let name = CString::new("Item").unwrap().into_raw();
         let (keywords, keywords_len, _) =
@@ -343,7 +1174,7 @@ mod tests {
             keywords,
             keywords_len,
         };
-        let result = create_transaction(api_url, api_key, raw_transaction);
+        let result = client_create_transaction(&client, raw_transaction);
         mock.assert();
         match result {
             FFIResult::Ok(raw_transaction) => panic!(
@@ -362,4 +1193,137 @@ mod tests {
             },
         }
     }
+
+    fn example_saved_transaction() -> SavedTransaction {
+        SavedTransaction {
+            id: 1,
+            shop_id: 1,
+            owner_id: 1,
+            mod_name: "Skyrim.esm".to_string(),
+            local_form_id: 1,
+            name: "Item".to_string(),
+            form_type: 41,
+            is_food: false,
+            is_sell: false,
+            price: 100,
+            quantity: 1,
+            amount: 100,
+            keywords: vec!["VendorItemMisc".to_string()],
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_get_transaction() {
+        let mock = mock("GET", "/v1/transactions/1")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&example_saved_transaction()).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_get_transaction(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_transaction) => {
+                assert_eq!(raw_transaction.id, 1);
+                assert_eq!(raw_transaction.shop_id, 1);
+                assert_eq!(
+                    unsafe { CStr::from_ptr(raw_transaction.name).to_string_lossy() },
+                    "Item"
+                );
+                assert_eq!(raw_transaction.amount, 100);
+                assert_eq!(raw_transaction.keywords_len, 1);
+            }
+            FFIResult::Err(error) => panic!("get_transaction returned error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_get_transaction_server_error() {
+        let mock = mock("GET", "/v1/transactions/1")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_get_transaction(&client, 1);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_transaction) => {
+                panic!("get_transaction returned Ok result: {:#?}", raw_transaction)
+            }
+            FFIResult::Err(error) => match error {
+                FFIError::CacheMiss(cache_miss_error) => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
+                        "Object not found in API or in cache: transaction_1.bin",
+                    );
+                }
+                _ => panic!("get_transaction did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_list_transactions_by_shop_id() {
+        let mock = mock("GET", "/v1/shops/1/transactions?limit=10&offset=0")
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(bincode::serialize(&vec![example_saved_transaction()]).unwrap())
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_list_transactions_by_shop_id(&client, 1, 10, 0);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_transaction_vec) => {
+                assert_eq!(raw_transaction_vec.len, 1);
+                let raw_transaction = unsafe { &*raw_transaction_vec.ptr };
+                assert_eq!(raw_transaction.id, 1);
+                assert_eq!(raw_transaction.shop_id, 1);
+                free_raw_transaction_vec(raw_transaction_vec);
+            }
+            FFIResult::Err(error) => {
+                panic!("list_transactions_by_shop_id returned error: {}", error)
+            }
+        }
+    }
+
+    #[test]
+    fn test_list_transactions_server_error() {
+        let mock = mock("GET", "/v1/transactions?limit=10&offset=20")
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let (_cache_root, client) = test_client();
+        let result = client_list_transactions(&client, 10, 20);
+        mock.assert();
+        match result {
+            FFIResult::Ok(raw_transaction_vec) => panic!(
+                "list_transactions returned Ok result: {:#?}",
+                raw_transaction_vec
+            ),
+            FFIResult::Err(error) => match error {
+                FFIError::CacheMiss(cache_miss_error) => {
+                    assert_eq!(
+                        unsafe { CStr::from_ptr(cache_miss_error).to_string_lossy() },
+                        "Object not found in API or in cache: transactions_limit_10_offset_20.bin",
+                    );
+                }
+                _ => panic!("list_transactions did not return a cache miss error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_free_raw_transaction_vec() {
+        let raw_transaction_vec = RawTransactionVec::from(vec![example_saved_transaction()]);
+        assert_eq!(raw_transaction_vec.len, 1);
+        let raw_transaction = unsafe { &*raw_transaction_vec.ptr };
+        assert_eq!(raw_transaction.keywords_len, 1);
+        free_raw_transaction_vec(raw_transaction_vec);
+    }
 }
@@ -0,0 +1,77 @@
+use std::{fmt, str};
+
+use bytes::Bytes;
+use reqwest::{blocking::Request, header::HeaderMap, StatusCode};
+
+use crate::error::Result;
+
+/// Everything the endpoints read from a response, already read off the connection.
+#[derive(Clone)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+// bodies can be whole lists of shops, so only their length makes it into the log
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body_len", &self.body.len())
+            .finish()
+    }
+}
+
+impl Response {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn into_body(self) -> Bytes {
+        self.body
+    }
+
+    pub fn text(&self) -> &str {
+        str::from_utf8(&self.body).unwrap_or("<binary>")
+    }
+}
+
+/// Sends a built request and returns the server's response. `ReqwestTransport` talks to a real
+/// server; `FakeApi` answers from memory.
+///
+/// A transport only fails when it got no response at all. Error statuses are returned as
+/// responses like any other.
+pub trait Transport: fmt::Debug + Send + Sync {
+    fn execute(&self, request: Request) -> Result<Response>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    http: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(http: reqwest::blocking::Client) -> Self {
+        Self { http }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn execute(&self, request: Request) -> Result<Response> {
+        let resp = self.http.execute(request)?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp.bytes()?;
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}